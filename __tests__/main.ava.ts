@@ -1,6 +1,6 @@
 import anyTest, { TestFn } from "ava";
 import { NEAR, NearAccount, Worker } from "near-workspaces";
-import { displayFailureLog, generateKeyPairs, generateLimitedAccessKeyData } from "./utils/general";
+import { displayFailureLog, generateKeyPairs, generateLimitedAccessKeyData, LIMITED_ACCESS_KEY_ALLOWANCE } from "./utils/general";
 import { readFileSync } from 'fs';
 
 const test = anyTest as TestFn<{
@@ -17,7 +17,7 @@ test.beforeEach(async (t) => {
 
     // Deploy the linkdrop contract and initialize it
     await root.deploy(`./res/linkdrop.wasm`);
-    await root.call(root, 'new', {});
+    await root.call(root, 'new', { owner_id: root.accountId });
 
     // // Test users
     const creator = await root.createSubAccount('creator');
@@ -77,7 +77,7 @@ test('Add 5 different FAKs and Limited Access Keys', async t => {
 
     for (const key of newAccountKeys['keys']) {
         if (limited_access_keys.includes(key['public_key'])) {
-            const expectedPermission = { FunctionCall: { allowance: null, method_names: ["create_account_advanced", "bar"], receiver_id: root.accountId } }
+            const expectedPermission = { FunctionCall: { allowance: LIMITED_ACCESS_KEY_ALLOWANCE, method_names: ["create_account_advanced", "bar"], receiver_id: root.accountId } }
             t.deepEqual(key['access_key']['permission'], expectedPermission)
         } else {
             t.is(key['access_key']['permission'], "FullAccess")
@@ -100,17 +100,15 @@ test('Deploy a contract and no keys', async t => {
 
     // Get the bytes of a contract
     const contractBytes = Buffer.from(readFileSync('./__tests__/ext-wasm/nft-tutorial.wasm'));
-    const bytes = Array.from(Uint8Array.from(contractBytes));
 
     // Try to create an account with a contract
     const res = await creator.callRaw(
         root,
-        'create_account_advanced',
+        'create_account_with_contract',
         {
             new_account_id: newAccount.accountId,
-            options: {
-                contract_bytes: bytes,
-            }
+            contract_bytes: contractBytes.toString('base64'),
+            keys: {},
         },
         {
             attachedDeposit: NEAR.parse("10 N").toString(),
@@ -180,10 +178,11 @@ test('Add 2 types of access keys with same public key', async t => {
         }
     );
 
-    // Check for any failures (should be 1 due to adding a key that already exists)
+    // Check for any failures (should be 1 as the contract rejects the duplicate key before creating anything)
     const errors = displayFailureLog(res);
     t.is(errors.length, 1);
-    t.is(errors[0].hasOwnProperty('AddKeyAlreadyExists'), true);
+    t.is(errors[0].hasOwnProperty('FunctionCallError'), true);
+    t.regex(JSON.stringify(errors[0]), /is added more than once/);
 
     // The account was not created
     const doesNewAccountExistNow = await newAccount.exists();
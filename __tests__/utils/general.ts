@@ -1,5 +1,8 @@
 import { KeyPair } from "near-api-js";
-import { TransactionResult } from "near-workspaces";
+import { NEAR, TransactionResult } from "near-workspaces";
+
+// Allowance given to the generated limited access keys, the contract rejects zero allowances
+export const LIMITED_ACCESS_KEY_ALLOWANCE = NEAR.parse("0.25 N").toString();
 
 export function generateLimitedAccessKeyData(
   pubKeys: string[],
@@ -10,7 +13,7 @@ export function generateLimitedAccessKeyData(
   for (let i = 0; i < pubKeys.length; i++) {
     keys.push({
       public_key: pubKeys[i],
-      allowance: "0",
+      allowance: LIMITED_ACCESS_KEY_ALLOWANCE,
       receiver_id: receiverId,
       method_names: methodName
     })
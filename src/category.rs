@@ -0,0 +1,177 @@
+use crate::*;
+
+#[near_bindgen]
+impl LinkDrop {
+    /// Set what the linkdrops funded under `category` can lock at most, e.g. to cap the spend of a promotion,
+    /// keeping what the category already spent. `send` refuses the linkdrops of a category without a budget.
+    /// Category names are at most `MAX_CATEGORY_BYTES` long.
+    #[handle_result]
+    pub fn set_category_budget(&mut self, category: String, limit: U128) -> Result<(), ContractError> {
+        self.check_owner_action()?;
+        self.internal_set_category_budget(category, limit)
+    }
+
+    /// Returns the budget of `category`, if the owner set one.
+    pub fn get_category_budget(&self, category: String) -> Option<CategoryBudget> {
+        self.category_budgets.get(&category)
+    }
+}
+
+impl LinkDrop {
+    /// Set the limit of the budget of `category`, see `set_category_budget`.
+    pub(crate) fn internal_set_category_budget(&mut self, category: String, limit: U128) -> Result<(), ContractError> {
+        ensure(category.len() <= MAX_CATEGORY_BYTES, || {
+            ContractError::InvalidArgument(format!(
+                "Category {} is longer than {} bytes",
+                category, MAX_CATEGORY_BYTES
+            ))
+        })?;
+        let spent = self.category_budgets.get(&category).map_or(U128(0), |budget| budget.spent);
+        self.category_budgets.insert(&category, &CategoryBudget { limit, spent });
+        Ok(())
+    }
+
+    /// Charge `amount` funded under `category` to its budget. Fails if the category has no budget or the amount
+    /// would take it over its limit.
+    pub(crate) fn internal_spend_category(&mut self, category: &String, amount: Balance) -> Result<(), ContractError> {
+        let mut budget = self.category_budgets.get(category).ok_or_else(|| {
+            ContractError::InvalidArgument(format!("Category {} has no budget", category))
+        })?;
+        ensure(budget.spent.0 + amount <= budget.limit.0, || {
+            ContractError::InvalidArgument(format!(
+                "Funding the linkdrop would take category {} over its budget of {} yoctoNEAR, {} yoctoNEAR are left",
+                category,
+                budget.limit.0,
+                budget.limit.0.saturating_sub(budget.spent.0)
+            ))
+        })?;
+        budget.spent.0 += amount;
+        self.category_budgets.insert(category, &budget);
+        Ok(())
+    }
+
+    /// Give `amount` reclaimed from a linkdrop of `category` back to its budget.
+    pub(crate) fn internal_refund_category(&mut self, category: &String, amount: Balance) {
+        if let Some(mut budget) = self.category_budgets.get(category) {
+            budget.spent.0 = budget.spent.0.saturating_sub(amount);
+            self.category_budgets.insert(category, &budget);
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[cfg(test)]
+mod tests {
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::testing_env;
+
+    use crate::tests::{bob, funding_key, key, linkdrop, owner, register_storage};
+
+    use super::*;
+
+    /// A contract where the "launch" category can spend `limit`.
+    fn budgeted_contract(limit: Balance) -> LinkDrop {
+        testing_env!(VMContextBuilder::new().current_account_id(linkdrop()).predecessor_account_id(owner()).context.clone());
+        let mut contract = LinkDrop::new(owner(), None);
+        contract.set_category_budget("launch".to_string(), U128(limit)).unwrap();
+        register_storage(&mut contract);
+        contract
+    }
+
+    /// Fund `public_key` as bob with `deposit`, under the "launch" category.
+    fn send_launch(contract: &mut LinkDrop, public_key: PublicKey, deposit: Balance) -> Result<Promise, ContractError> {
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob())
+            .attached_deposit(deposit)
+            .context.clone()
+        );
+        contract.send(public_key, None, None, None, None, None, Some("launch".to_string()))
+    }
+
+    #[test]
+    fn test_send_within_budget() {
+        let amount = MIN_SEND_DEPOSIT - ACCESS_KEY_ALLOWANCE;
+        let mut contract = budgeted_contract(amount * 2);
+
+        send_launch(&mut contract, funding_key(), MIN_SEND_DEPOSIT).unwrap();
+        send_launch(&mut contract, key(1), MIN_SEND_DEPOSIT).unwrap();
+        assert_eq!(
+            contract.get_category_budget("launch".to_string()),
+            Some(CategoryBudget { limit: U128(amount * 2), spent: U128(amount * 2) })
+        );
+        assert_eq!(contract.accounts.get(&funding_key()).unwrap().category, Some("launch".to_string()));
+    }
+
+    #[test]
+    fn test_send_over_budget() {
+        let amount = MIN_SEND_DEPOSIT - ACCESS_KEY_ALLOWANCE;
+        let mut contract = budgeted_contract(amount * 3 / 2);
+
+        send_launch(&mut contract, funding_key(), MIN_SEND_DEPOSIT).unwrap();
+        assert_eq!(
+            send_launch(&mut contract, key(1), MIN_SEND_DEPOSIT).err(),
+            Some(ContractError::InvalidArgument(format!(
+                "Funding the linkdrop would take category launch over its budget of {} yoctoNEAR, {} yoctoNEAR are left",
+                amount * 3 / 2,
+                amount / 2
+            )))
+        );
+        assert_eq!(contract.get_category_budget("launch".to_string()).unwrap().spent, U128(amount));
+
+        // Nor can a category be used before the owner gives it a budget
+        testing_env!(VMContextBuilder::new().current_account_id(linkdrop()).attached_deposit(MIN_SEND_DEPOSIT).context.clone());
+        assert_eq!(
+            contract.send(key(1), None, None, None, None, None, Some("other".to_string())).err(),
+            Some(ContractError::InvalidArgument("Category other has no budget".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_reclaim_restores_budget() {
+        let amount = MIN_SEND_DEPOSIT - ACCESS_KEY_ALLOWANCE;
+        let mut contract = budgeted_contract(amount);
+        send_launch(&mut contract, funding_key(), MIN_SEND_DEPOSIT).unwrap();
+
+        // Once the owner reclaims the linkdrop, the category can fund another one
+        testing_env!(VMContextBuilder::new().current_account_id(linkdrop()).predecessor_account_id(owner()).context.clone());
+        contract.delete_key(funding_key(), owner()).unwrap();
+        assert_eq!(contract.get_category_budget("launch".to_string()).unwrap().spent, U128(0));
+        send_launch(&mut contract, key(1), MIN_SEND_DEPOSIT).unwrap();
+    }
+
+    #[test]
+    fn test_set_category_budget_keeps_spent() {
+        let amount = MIN_SEND_DEPOSIT - ACCESS_KEY_ALLOWANCE;
+        let mut contract = budgeted_contract(amount);
+        send_launch(&mut contract, funding_key(), MIN_SEND_DEPOSIT).unwrap();
+
+        testing_env!(VMContextBuilder::new().current_account_id(linkdrop()).predecessor_account_id(owner()).context.clone());
+        contract.set_category_budget("launch".to_string(), U128(amount * 2)).unwrap();
+        assert_eq!(
+            contract.get_category_budget("launch".to_string()),
+            Some(CategoryBudget { limit: U128(amount * 2), spent: U128(amount) })
+        );
+    }
+
+    #[test]
+    fn test_set_category_budget_name_too_long() {
+        let category = "a".repeat(MAX_CATEGORY_BYTES + 1);
+        assert_eq!(
+            budgeted_contract(1_000).set_category_budget(category.clone(), U128(1_000)).err(),
+            Some(ContractError::InvalidArgument(format!("Category {} is longer than 64 bytes", category)))
+        );
+    }
+
+    #[test]
+    fn test_set_category_budget_not_owner() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        testing_env!(VMContextBuilder::new().current_account_id(linkdrop()).predecessor_account_id(bob()).context.clone());
+        assert_eq!(
+            contract.set_category_budget("launch".to_string(), U128(1_000)).err(),
+            Some(ContractError::Unauthorized("Only the owner can call this method"))
+        );
+    }
+}
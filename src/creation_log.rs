@@ -0,0 +1,136 @@
+use crate::*;
+
+#[near_bindgen]
+impl LinkDrop {
+    /// Paginate through the `creation_log`, oldest first, starting at `from_index` (0 by default) and returning
+    /// at most `limit` records (`DEFAULT_KEYS_LIMIT` by default, capped at `MAX_KEYS_LIMIT`).
+    pub fn get_creation_log(&self, from_index: Option<u64>, limit: Option<u64>) -> Vec<CreationRecord> {
+        let from_index = from_index.unwrap_or(0);
+        let limit = limit.unwrap_or(DEFAULT_KEYS_LIMIT).min(MAX_KEYS_LIMIT);
+
+        (from_index..self.creation_log.len().min(from_index.saturating_add(limit)))
+            .filter_map(|index| self.creation_log.get(index))
+            .collect()
+    }
+
+    /// Remove the records of the creations before `before_timestamp`, in nanoseconds, from the `creation_log` to
+    /// free their storage. The remaining records move to the front of the log. Only callable by the owner.
+    /// Returns how many were removed.
+    pub fn prune_creation_log(&mut self, before_timestamp: U64) -> u64 {
+        self.assert_owner();
+        // Records are appended as the callbacks run, so the old ones are at the front
+        let len = self.creation_log.len();
+        let pruned = (0..len)
+            .find(|&index| self.creation_log.get(index).unwrap().timestamp.0 >= before_timestamp.0)
+            .unwrap_or(len);
+        if pruned == 0 {
+            return 0;
+        }
+        for index in pruned..len {
+            let record = self.creation_log.get(index).unwrap();
+            self.creation_log.replace(index - pruned, &record);
+        }
+        for _ in 0..pruned {
+            self.creation_log.pop();
+        }
+        pruned
+    }
+}
+
+impl LinkDrop {
+    /// Append the outcome of the creation of `new_account_id` to the `creation_log`.
+    pub(crate) fn internal_log_creation(
+        &mut self,
+        new_account_id: &AccountId,
+        predecessor_account_id: &AccountId,
+        amount: U128,
+        success: bool,
+    ) {
+        self.creation_log.push(&CreationRecord {
+            new_account_id: new_account_id.clone(),
+            predecessor_account_id: predecessor_account_id.clone(),
+            amount,
+            success,
+            timestamp: U64(env::block_timestamp()),
+        });
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[cfg(test)]
+mod tests {
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::{testing_env, RuntimeFeesConfig, VMConfig};
+
+    use crate::tests::{bob, linkdrop, new_account, owner};
+
+    use super::*;
+
+    fn callback_env(block_timestamp: u64, result: PromiseResult) {
+        testing_env!(
+            VMContextBuilder::new()
+                .current_account_id(linkdrop())
+                .predecessor_account_id(linkdrop())
+                .block_timestamp(block_timestamp)
+                .context.clone(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![result]
+        );
+    }
+
+    fn record(success: bool, timestamp: u64) -> CreationRecord {
+        CreationRecord {
+            new_account_id: new_account(),
+            predecessor_account_id: bob(),
+            amount: U128(1_000),
+            success,
+            timestamp: U64(timestamp),
+        }
+    }
+
+    /// A contract whose log holds a successful creation at 100, a failed one at 200 and another success at 300.
+    fn logged_contract() -> LinkDrop {
+        let mut contract = LinkDrop::new(owner(), None);
+        for (timestamp, result) in [
+            (100, PromiseResult::Successful(vec![])),
+            (200, PromiseResult::Failed),
+            (300, PromiseResult::Successful(vec![])),
+        ] {
+            callback_env(timestamp, result);
+            drop(contract.on_account_created(new_account(), bob(), bob(), U128(1_000), None, None, None));
+        }
+        contract
+    }
+
+    #[test]
+    fn test_log_creations() {
+        let contract = logged_contract();
+
+        assert_eq!(contract.get_creation_log(None, None), vec![record(true, 100), record(false, 200), record(true, 300)]);
+        assert_eq!(contract.get_creation_log(Some(1), Some(1)), vec![record(false, 200)]);
+        assert!(contract.get_creation_log(Some(3), None).is_empty());
+    }
+
+    #[test]
+    fn test_prune_creation_log() {
+        let mut contract = logged_contract();
+
+        testing_env!(VMContextBuilder::new().predecessor_account_id(owner()).context.clone());
+        assert_eq!(contract.prune_creation_log(U64(250)), 2);
+        assert_eq!(contract.get_creation_log(None, None), vec![record(true, 300)]);
+        assert_eq!(contract.prune_creation_log(U64(250)), 0);
+        assert_eq!(contract.prune_creation_log(U64(1_000)), 1);
+        assert!(contract.get_creation_log(None, None).is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the owner can call this method")]
+    fn test_prune_creation_log_not_owner() {
+        let mut contract = logged_contract();
+
+        testing_env!(VMContextBuilder::new().predecessor_account_id(bob()).context.clone());
+        contract.prune_creation_log(U64(250));
+    }
+}
@@ -0,0 +1,143 @@
+use crate::*;
+
+#[near_bindgen]
+impl LinkDrop {
+    /// Pay back the debt of the predecessor to the sponsor of its creation with the attached deposit, see
+    /// `create_account_advanced`. A deposit below the debt only reduces it, the part above it is refunded.
+    /// Returns what is still owed, the record is removed once the whole debt is repaid.
+    #[payable]
+    #[handle_result]
+    pub fn repay(&mut self) -> Result<U128, ContractError> {
+        let account_id = env::predecessor_account_id();
+        let mut debt = self.debts.get(&account_id).ok_or_else(|| {
+            ContractError::InvalidArgument(format!("{} has no debt to repay", account_id))
+        })?;
+        let deposit = env::attached_deposit();
+        ensure(deposit > 0, || {
+            ContractError::InsufficientDeposit("Attach the yoctoNEAR to repay".to_string())
+        })?;
+
+        let repaid = deposit.min(debt.amount.0);
+        debt.amount.0 -= repaid;
+        if debt.amount.0 == 0 {
+            self.debts.remove(&account_id);
+        } else {
+            self.debts.insert(&account_id, &debt);
+        }
+        Promise::new(debt.sponsor_id.clone()).transfer(repaid);
+        if deposit > repaid {
+            Promise::new(account_id.clone()).transfer(deposit - repaid);
+        }
+        EventLog::new(EventLogVariant::DebtRepaid(vec![DebtRepaidLog {
+            account_id,
+            sponsor_id: debt.sponsor_id,
+            amount: U128(repaid),
+            remaining: debt.amount,
+        }]))
+        .emit();
+        Ok(debt.amount)
+    }
+
+    /// Returns what `account_id` still owes to the sponsor of its creation, if anything.
+    pub fn get_debt(&self, account_id: AccountId) -> Option<DebtRecord> {
+        self.debts.get(&account_id)
+    }
+}
+
+impl LinkDrop {
+    /// Record that `account_id` owes `amount` to `sponsor_id`, replacing any previous debt of the account.
+    pub(crate) fn internal_record_debt(&mut self, account_id: &AccountId, sponsor_id: AccountId, amount: Balance) {
+        self.debts.insert(account_id, &DebtRecord { sponsor_id, amount: U128(amount) });
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[cfg(test)]
+mod tests {
+    use near_sdk::mock::VmAction;
+    use near_sdk::test_utils::{get_created_receipts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use crate::tests::{bob, callback_env, full_key_options, key, linkdrop, new_account, owner};
+
+    use super::*;
+
+    fn set_context(predecessor_account_id: AccountId, attached_deposit: Balance) {
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(predecessor_account_id)
+            .attached_deposit(attached_deposit)
+            .context.clone()
+        );
+    }
+
+    /// A contract where bob sponsored the creation of `new_account()` with a debt of 600, settled with `result`.
+    fn sponsored_contract(result: PromiseResult) -> LinkDrop {
+        set_context(bob(), 1_000);
+        let mut contract = LinkDrop::new(owner(), None);
+        drop(
+            contract
+                .create_account_advanced(new_account(), full_key_options(vec![key(1)]), None, None, Some(U128(600)), None)
+                .unwrap(),
+        );
+
+        callback_env(result);
+        drop(contract.on_account_created(new_account(), bob(), bob(), U128(1_000), None, Some(U128(600)), None));
+        contract
+    }
+
+    #[test]
+    fn test_debt_recorded_on_creation() {
+        let contract = sponsored_contract(PromiseResult::Successful(vec![]));
+        assert_eq!(
+            contract.get_debt(new_account()),
+            Some(DebtRecord { sponsor_id: bob(), amount: U128(600) })
+        );
+    }
+
+    #[test]
+    fn test_no_debt_on_failed_creation() {
+        let contract = sponsored_contract(PromiseResult::Failed);
+        assert!(contract.get_debt(new_account()).is_none());
+    }
+
+    #[test]
+    fn test_partial_repay() {
+        let mut contract = sponsored_contract(PromiseResult::Successful(vec![]));
+
+        set_context(new_account(), 250);
+        assert_eq!(contract.repay().unwrap(), U128(350));
+        assert_eq!(contract.get_debt(new_account()).unwrap().amount, U128(350));
+        let receipts = get_created_receipts();
+        assert_eq!(receipts.len(), 1);
+        assert_eq!(receipts[0].receiver_id, bob());
+        assert_eq!(receipts[0].actions, vec![VmAction::Transfer { deposit: 250 }]);
+    }
+
+    #[test]
+    fn test_full_repay_clears_debt() {
+        let mut contract = sponsored_contract(PromiseResult::Successful(vec![]));
+
+        // The part above the debt goes back to the new account
+        set_context(new_account(), 700);
+        assert_eq!(contract.repay().unwrap(), U128(0));
+        assert!(contract.get_debt(new_account()).is_none());
+        let receipts = get_created_receipts();
+        assert_eq!(receipts[0].receiver_id, bob());
+        assert_eq!(receipts[0].actions, vec![VmAction::Transfer { deposit: 600 }]);
+        assert_eq!(receipts[1].receiver_id, new_account());
+        assert_eq!(receipts[1].actions, vec![VmAction::Transfer { deposit: 100 }]);
+    }
+
+    #[test]
+    fn test_repay_without_debt() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        set_context(new_account(), 100);
+        assert_eq!(
+            contract.repay().err(),
+            Some(ContractError::InvalidArgument(format!("{} has no debt to repay", new_account())))
+        );
+    }
+}
@@ -0,0 +1,155 @@
+use std::fmt;
+
+/// Why a public method failed, returned through `#[handle_result]` so that callers can tell the failures apart.
+/// The transaction still fails with the message the method used to panic with, see `Display`.
+#[derive(Debug, PartialEq)]
+pub enum ContractError {
+    /// The options of a creation have neither keys nor a contract to deploy.
+    NoOptionsProvided,
+    /// No linkdrop is funded under the key.
+    KeyMissing,
+    /// The contract is paused, see `pause`.
+    Paused,
+    /// The predecessor isn't allowed to call the method, for the given reason.
+    Unauthorized(&'static str),
+    /// The attached deposit doesn't cover what the call needs, for the given reason.
+    InsufficientDeposit(String),
+    /// An argument or the state of the contract rules the call out, for the given reason.
+    InvalidArgument(String),
+}
+
+impl fmt::Display for ContractError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_ref())
+    }
+}
+
+/// The message the transaction fails with, through the `FunctionError` implementation of near-sdk for `AsRef<str>`.
+impl AsRef<str> for ContractError {
+    fn as_ref(&self) -> &str {
+        match self {
+            ContractError::NoOptionsProvided => {
+                "Cannot create account with no options. Please specify either contract bytes, full access keys, or limited access keys."
+            }
+            ContractError::KeyMissing => "Key is missing",
+            ContractError::Paused => "The contract is paused",
+            ContractError::Unauthorized(reason) => reason,
+            ContractError::InsufficientDeposit(reason) | ContractError::InvalidArgument(reason) => reason,
+        }
+    }
+}
+
+/// The checks returning a plain message, e.g. `check_unique_keys`, fail on a bad argument.
+impl From<String> for ContractError {
+    fn from(reason: String) -> Self {
+        ContractError::InvalidArgument(reason)
+    }
+}
+
+/// `Err(error)` unless `condition` holds, to chain the checks with `?`.
+pub(crate) fn ensure(condition: bool, error: impl FnOnce() -> ContractError) -> Result<(), ContractError> {
+    if condition {
+        Ok(())
+    } else {
+        Err(error())
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[cfg(test)]
+mod tests {
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::testing_env;
+
+    use crate::tests::{bob, full_key_options, funding_key, key, linkdrop, new_account, owner};
+
+    use super::*;
+    use crate::*;
+
+    fn set_context(predecessor_account_id: AccountId, attached_deposit: Balance) {
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(predecessor_account_id)
+            .attached_deposit(attached_deposit)
+            .context.clone()
+        );
+    }
+
+    #[test]
+    fn test_no_options_provided() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        set_context(bob(), 1_000);
+        let options = CreateAccountOptions { full_access_keys: None, ..full_key_options(vec![]) };
+        let result = contract.create_account_advanced(new_account(), options, None, None, None, None);
+        assert_eq!(result.err(), Some(ContractError::NoOptionsProvided));
+    }
+
+    #[test]
+    fn test_key_missing() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        set_context(bob(), 0);
+        assert_eq!(contract.reclaim_expired(funding_key()).err(), Some(ContractError::KeyMissing));
+    }
+
+    #[test]
+    fn test_paused() {
+        let mut contract = LinkDrop::new(owner(), None);
+        set_context(owner(), 0);
+        contract.pause().unwrap();
+
+        set_context(bob(), MIN_SEND_DEPOSIT);
+        let result = contract.send(funding_key(), None, None, None, None, None, None);
+        assert_eq!(result.err(), Some(ContractError::Paused));
+    }
+
+    #[test]
+    fn test_unauthorized() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        set_context(new_account(), 0);
+        assert_eq!(
+            contract.change_key(bob(), key(1), key(2)).err(),
+            Some(ContractError::Unauthorized("Only the account itself can change its keys"))
+        );
+    }
+
+    #[test]
+    fn test_insufficient_deposit() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        set_context(bob(), MIN_SEND_DEPOSIT - 1);
+        assert_eq!(
+            contract.send(funding_key(), None, None, None, None, None, None).err(),
+            Some(ContractError::InsufficientDeposit(format!(
+                "Attached deposit must be at least {} yoctoNEAR",
+                MIN_SEND_DEPOSIT
+            )))
+        );
+    }
+
+    #[test]
+    fn test_invalid_argument() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        set_context(bob(), 0);
+        assert_eq!(
+            contract.create_accounts(vec![]).err(),
+            Some(ContractError::InvalidArgument("Cannot create accounts without requests".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_message_is_kept() {
+        assert_eq!(
+            ContractError::NoOptionsProvided.to_string(),
+            "Cannot create account with no options. Please specify either contract bytes, full access keys, or limited access keys."
+        );
+        assert_eq!(ContractError::KeyMissing.to_string(), "Key is missing");
+        assert_eq!(ContractError::Paused.to_string(), "The contract is paused");
+        assert_eq!(ContractError::KeyMissing.as_ref(), "Key is missing");
+        assert_eq!(ContractError::InvalidArgument("Bad argument".to_string()).as_ref(), "Bad argument");
+    }
+}
@@ -0,0 +1,202 @@
+use std::fmt;
+
+use near_sdk::serde::Serialize;
+use near_sdk::serde_json;
+
+use crate::*;
+
+/// Standard name of the events emitted by this contract.
+pub const EVENT_STANDARD: &str = "arkana_account";
+/// Version of the events emitted by this contract, bumped whenever the data of an event changes.
+pub const VERSION: &str = "1.1.0";
+
+/// Write `event` to the logs of the current receipt as `EVENT_JSON:{...}`. Every event goes through here.
+pub fn log_event(event: EventLog) {
+    env::log_str(&event.to_string());
+}
+
+/// NEP-297 event log, emitted through `log_event` as `EVENT_JSON:{...}`.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct EventLog {
+    pub standard: String,
+    pub version: String,
+    /// The event name and its data, flattened into the log as `event` and `data`.
+    #[serde(flatten)]
+    pub event: EventLogVariant,
+}
+
+impl EventLog {
+    /// Wrap an event with the contract's standard and version.
+    pub fn new(event: EventLogVariant) -> Self {
+        Self {
+            standard: EVENT_STANDARD.to_string(),
+            version: VERSION.to_string(),
+            event,
+        }
+    }
+
+    /// Write the event to the logs of the current receipt, see `log_event`.
+    pub fn emit(self) {
+        log_event(self);
+    }
+}
+
+impl fmt::Display for EventLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "EVENT_JSON:{}", serde_json::to_string(self).map_err(|_| fmt::Error)?)
+    }
+}
+
+/// All the events emitted by this contract.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(tag = "event", content = "data")]
+#[serde(rename_all = "snake_case")]
+pub enum EventLogVariant {
+    AccountCreated(Vec<AccountCreatedLog>),
+    LinkdropClaimed(Vec<LinkdropClaimedLog>),
+    CreationFailed(Vec<CreationFailedLog>),
+    CircuitBreakerTripped(Vec<CircuitBreakerTrippedLog>),
+    RefundRetained(Vec<RefundRetainedLog>),
+    DebtRepaid(Vec<DebtRepaidLog>),
+    DefaultKeysAdded(Vec<DefaultKeysAddedLog>),
+    LowStorageBalance(Vec<LowStorageBalanceLog>),
+    EmergencyReclaim(Vec<EmergencyReclaimLog>),
+}
+
+/// An account was created by `create_account_advanced`.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AccountCreatedLog {
+    pub new_account_id: AccountId,
+    /// yoctoNEAR$ transferred to the new account.
+    pub amount: U128,
+}
+
+/// A linkdrop was claimed into a newly created account.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct LinkdropClaimedLog {
+    pub account_id: AccountId,
+    /// The funding key the linkdrop was claimed with.
+    pub public_key: PublicKey,
+}
+
+/// The creation of an account by `create_account_advanced` failed and its deposit is refunded, unless retained.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CreationFailedLog {
+    pub new_account_id: AccountId,
+    /// The account that asked for the creation.
+    pub predecessor_account_id: AccountId,
+    /// The account the deposit is refunded to, `None` when `RefundPolicy::Retain` keeps it.
+    pub refund_to: Option<AccountId>,
+    /// yoctoNEAR$ refunded.
+    pub refunded: U128,
+    /// Why the creation failed as far as the callback can tell, for relayers deciding whether to retry.
+    pub reason: CreationFailureReason,
+}
+
+/// Best-effort reason of a failed creation, see `creation_failure_reason`.
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde", rename_all = "snake_case")]
+pub enum CreationFailureReason {
+    /// The account already exists, retrying with the same id fails again.
+    AccountExists,
+    /// The deposit didn't cover the creation, retrying with a larger deposit may succeed.
+    InsufficientFunds,
+    /// The runtime gave no detail on the failure.
+    Unknown,
+}
+
+/// The deposit of a failed creation is kept for the treasury under `RefundPolicy::Retain`.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RefundRetainedLog {
+    pub new_account_id: AccountId,
+    /// The account the deposit would have been refunded to.
+    pub predecessor_account_id: AccountId,
+    /// yoctoNEAR$ retained.
+    pub amount: U128,
+}
+
+/// The contract paused itself after too many failed creations in a row, see `set_max_consecutive_failures`.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CircuitBreakerTrippedLog {
+    pub consecutive_failures: u32,
+}
+
+/// An account created with a debt paid part of it back to its sponsor, see `repay`.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DebtRepaidLog {
+    pub account_id: AccountId,
+    pub sponsor_id: AccountId,
+    /// yoctoNEAR$ repaid.
+    pub amount: U128,
+    /// yoctoNEAR$ still owed after the repayment.
+    pub remaining: U128,
+}
+
+/// The default full access keys were added to an account being created, see `set_default_full_access_keys`.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DefaultKeysAddedLog {
+    pub new_account_id: AccountId,
+    pub public_keys: Vec<PublicKey>,
+}
+
+/// The margin of the contract balance over its storage stake fell below `LOW_STORAGE_MARGIN` while funding a
+/// linkdrop, see `get_storage_margin`.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct LowStorageBalanceLog {
+    /// yoctoNEAR$ left over the storage stake before drawing from the reserve.
+    pub margin: U128,
+    /// yoctoNEAR$ drawn from the storage reserve, short of the margin once it is empty.
+    pub drawn: U128,
+    /// yoctoNEAR$ left in the storage reserve.
+    pub storage_reserve: U128,
+}
+
+/// The owner removed a funded linkdrop during an incident, see `force_delete_key`.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct EmergencyReclaimLog {
+    /// The funding key of the removed linkdrop.
+    pub public_key: PublicKey,
+    pub funder_id: AccountId,
+    /// The account the balance was swept to.
+    pub recipient_id: AccountId,
+    /// yoctoNEAR$ swept.
+    pub amount: U128,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[cfg(test)]
+mod tests {
+    use near_sdk::test_utils::{get_logs, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+
+    #[test]
+    fn test_log_event() {
+        testing_env!(VMContextBuilder::new().context.clone());
+        log_event(EventLog::new(EventLogVariant::CircuitBreakerTripped(vec![CircuitBreakerTrippedLog {
+            consecutive_failures: 3,
+        }])));
+
+        // A single NEP-297 line carrying the standard and version
+        let logs = get_logs();
+        assert_eq!(logs.len(), 1);
+        let json = logs[0].strip_prefix("EVENT_JSON:").unwrap();
+        let event: serde_json::Value = serde_json::from_str(json).unwrap();
+        assert_eq!(event["standard"], EVENT_STANDARD);
+        assert_eq!(event["version"], VERSION);
+        assert_eq!(event["event"], "circuit_breaker_tripped");
+        assert_eq!(event["data"][0]["consecutive_failures"], 3);
+    }
+}
@@ -0,0 +1,321 @@
+use crate::*;
+
+/// yoctoNEAR attached to `storage_deposit` on the token contract to register the claimer of an FT drop, the
+/// usual minimum storage balance of NEP-141 contracts. Kept out of the balance claimed with the key.
+pub const FT_STORAGE_DEPOSIT: Balance = 12_500_000_000_000_000_000_000;
+
+/// Gas attached to `storage_deposit` on the token contract.
+pub const FT_STORAGE_DEPOSIT_GAS: Gas = Gas(5_000_000_000_000);
+
+/// Gas attached to `ft_transfer` on the token contract.
+pub const FT_TRANSFER_GAS: Gas = Gas(10_000_000_000_000);
+
+/// Gas attached to the callback after sending the tokens of an FT drop.
+pub const ON_FT_CLAIMED_CALLBACK_GAS: Gas = Gas(10_000_000_000_000);
+
+/// Gas attached to the claim callback of an FT drop, which also pays for registering the claimer, sending the
+/// tokens and the callback after it.
+pub const ON_FT_DROP_CLAIMED_CALLBACK_GAS: Gas = Gas(
+    ON_CREATE_ACCOUNT_CALLBACK_GAS.0 + FT_STORAGE_DEPOSIT_GAS.0 + FT_TRANSFER_GAS.0 + ON_FT_CLAIMED_CALLBACK_GAS.0,
+);
+
+/// Gas attached to the callback after claiming `drop`.
+pub(crate) fn claim_callback_gas(drop: &DropInfo) -> Gas {
+    let gas = if drop.ft.is_some() {
+        ON_FT_DROP_CLAIMED_CALLBACK_GAS
+    } else if drop.nft.is_some() {
+        ON_NFT_DROP_CLAIMED_CALLBACK_GAS
+    } else {
+        ON_CREATE_ACCOUNT_CALLBACK_GAS
+    };
+    gas + claim_notification_gas(drop)
+}
+
+/// Gas the claim of `drop` needs on top to notify its claim callback, if any.
+pub(crate) fn claim_notification_gas(drop: &DropInfo) -> Gas {
+    if drop.claim_callback().is_some() {
+        CLAIM_NOTIFICATION_GAS
+    } else {
+        Gas(0)
+    }
+}
+
+/// Send the tokens of `ft` back to the funder, who is registered with the token contract since they sent them.
+pub(crate) fn internal_refund_ft(ft: &FtDropData, funder_id: &AccountId) -> Promise {
+    ext_ft::ext(ft.contract_id.clone())
+        .with_attached_deposit(1)
+        .with_static_gas(FT_TRANSFER_GAS)
+        .ft_transfer(funder_id.clone(), ft.amount, None)
+}
+
+#[near_bindgen]
+impl LinkDrop {
+    /// NEP-141 receiver: add the tokens transferred with `ft_transfer_call` to the linkdrop of the public key
+    /// given as `msg`. The key must already be funded by `sender_id` with `send`, with at least
+    /// `FT_STORAGE_DEPOSIT` to register the claimer with the token contract. A linkdrop holds the tokens of a
+    /// single contract.
+    pub fn ft_on_transfer(&mut self, sender_id: AccountId, amount: U128, msg: String) -> PromiseOrValue<U128> {
+        self.assert_not_paused();
+        let contract_id = env::predecessor_account_id();
+        let public_key: PublicKey = msg.parse().expect("The message must be the public key of the linkdrop");
+        let mut drop = self.accounts.get(&public_key).expect("Key is missing");
+        assert_eq!(drop.funder_id, sender_id, "Key is already funded by another account");
+        assert!(!drop.is_expired(), "Linkdrop has expired");
+        assert_eq!(drop.uses_remaining, 1, "Fungible token linkdrops can only be claimed once");
+        assert!(drop.nft.is_none(), "Key already holds an NFT");
+        assert!(
+            drop.balance.0 >= FT_STORAGE_DEPOSIT,
+            "The linkdrop must hold at least {} yoctoNEAR to register the claimer with the token contract",
+            FT_STORAGE_DEPOSIT
+        );
+
+        let ft = drop.ft.get_or_insert(FtDropData {
+            contract_id: contract_id.clone(),
+            amount: U128(0),
+        });
+        assert_eq!(ft.contract_id, contract_id, "Key already holds the tokens of another contract");
+        ft.amount.0 += amount.0;
+        self.accounts.insert(&public_key, &drop);
+
+        // All the tokens are used
+        PromiseOrValue::Value(U128(0))
+    }
+
+    /// Callback after sending the tokens of the FT drop claimed with `public_key` to `account_id`. If the
+    /// transfer failed the tokens stay claimable with the key, the NEAR balance having already been claimed.
+    pub fn on_ft_claimed(&mut self, account_id: AccountId, public_key: PublicKey, drop: DropInfo) -> bool {
+        assert_eq!(
+            env::predecessor_account_id(),
+            env::current_account_id(),
+            "Callback can only be called from the contract"
+        );
+        let transferred = is_promise_success();
+        if transferred {
+            // Tokens only go with the last use
+            self.internal_finish_claim(account_id, public_key, &drop, true);
+        } else {
+            self.internal_restore_drop(
+                public_key,
+                DropInfo {
+                    balance: U128(0),
+                    ..drop
+                },
+            );
+        }
+        transferred
+    }
+}
+
+impl LinkDrop {
+    /// Send the tokens of the FT drop claimed with `public_key` to `account_id`, registering it with the token
+    /// contract first with `storage_deposit` yoctoNEAR.
+    pub(crate) fn internal_claim_ft(
+        &self,
+        account_id: AccountId,
+        public_key: PublicKey,
+        drop: DropInfo,
+        storage_deposit: Balance,
+    ) -> Promise {
+        let ft = drop.ft.clone().unwrap();
+        ext_ft::ext(ft.contract_id.clone())
+            .with_attached_deposit(storage_deposit)
+            .with_static_gas(FT_STORAGE_DEPOSIT_GAS)
+            .storage_deposit(Some(account_id.clone()), Some(true))
+            .then(
+                ext_ft::ext(ft.contract_id)
+                    .with_attached_deposit(1)
+                    .with_static_gas(FT_TRANSFER_GAS)
+                    .ft_transfer(account_id.clone(), ft.amount, None)
+            )
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(ON_FT_CLAIMED_CALLBACK_GAS + claim_notification_gas(&drop))
+                    .on_ft_claimed(account_id, public_key, drop)
+            )
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[cfg(test)]
+mod tests {
+    use near_sdk::mock::VmAction;
+    use near_sdk::serde_json::{self, json};
+    use near_sdk::test_utils::{get_created_receipts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use crate::tests::{bob, callback_env, drop_info, funding_key, key, linkdrop, new_account, owner, register_storage};
+
+    use super::*;
+
+    fn token() -> AccountId {
+        "token".parse().unwrap()
+    }
+
+    fn ft_drop_info(balance: Balance, amount: Balance) -> DropInfo {
+        DropInfo {
+            ft: Some(FtDropData { contract_id: token(), amount: U128(amount) }),
+            ..drop_info(balance)
+        }
+    }
+
+    /// Mock `token` calling `ft_on_transfer` for tokens sent by `sender_id`.
+    fn transfer_tokens(contract: &mut LinkDrop, token: AccountId, sender_id: AccountId, amount: Balance, msg: String) {
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(token)
+            .context.clone()
+        );
+        let unused = contract.ft_on_transfer(sender_id, U128(amount), msg);
+        assert!(matches!(unused, PromiseOrValue::Value(U128(0))));
+    }
+
+    #[test]
+    fn test_ft_on_transfer() {
+        let mut contract = LinkDrop::new(owner(), None);
+        contract.accounts.insert(&funding_key(), &drop_info(FT_STORAGE_DEPOSIT));
+
+        // The tokens are recorded under the key, and add up
+        transfer_tokens(&mut contract, token(), bob(), 100, String::from(&funding_key()));
+        transfer_tokens(&mut contract, token(), bob(), 50, String::from(&funding_key()));
+        assert_eq!(contract.accounts.get(&funding_key()).unwrap(), ft_drop_info(FT_STORAGE_DEPOSIT, 150));
+    }
+
+    #[test]
+    #[should_panic(expected = "Key is missing")]
+    fn test_ft_on_transfer_missing_key() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        transfer_tokens(&mut contract, token(), bob(), 100, String::from(&funding_key()));
+    }
+
+    #[test]
+    #[should_panic(expected = "Key is already funded by another account")]
+    fn test_ft_on_transfer_other_funder() {
+        let mut contract = LinkDrop::new(owner(), None);
+        contract.accounts.insert(&funding_key(), &drop_info(FT_STORAGE_DEPOSIT));
+
+        transfer_tokens(&mut contract, token(), owner(), 100, String::from(&funding_key()));
+    }
+
+    #[test]
+    #[should_panic(expected = "Key already holds the tokens of another contract")]
+    fn test_ft_on_transfer_other_token() {
+        let mut contract = LinkDrop::new(owner(), None);
+        contract.accounts.insert(&funding_key(), &ft_drop_info(FT_STORAGE_DEPOSIT, 100));
+
+        transfer_tokens(&mut contract, "other-token".parse().unwrap(), bob(), 100, String::from(&funding_key()));
+    }
+
+    #[test]
+    #[should_panic(expected = "to register the claimer with the token contract")]
+    fn test_ft_on_transfer_without_storage_deposit() {
+        let mut contract = LinkDrop::new(owner(), None);
+        contract.accounts.insert(&funding_key(), &drop_info(FT_STORAGE_DEPOSIT - 1));
+
+        transfer_tokens(&mut contract, token(), bob(), 100, String::from(&funding_key()));
+    }
+
+    #[test]
+    fn test_claim_ft_drop() {
+        let mut contract = LinkDrop::new(owner(), None);
+        register_storage(&mut contract);
+        contract.accounts.insert(&funding_key(), &ft_drop_info(FT_STORAGE_DEPOSIT + 1_000, 100));
+        contract.total_locked = FT_STORAGE_DEPOSIT + 1_000;
+
+        // The storage deposit for the token contract is kept out of the claimed balance
+        callback_env(PromiseResult::Successful(vec![]));
+        contract.claim(new_account(), None).unwrap();
+        assert_eq!(get_created_receipts()[0].actions, vec![VmAction::Transfer { deposit: 1_000 }]);
+        assert_eq!(contract.total_locked, 0);
+
+        // Once the balance is claimed the claimer is registered and sent the tokens, the key stays until then
+        callback_env(PromiseResult::Successful(vec![]));
+        let drop = ft_drop_info(FT_STORAGE_DEPOSIT + 1_000, 100);
+        assert!(contract.on_account_created_and_claimed(new_account(), funding_key(), drop, false, None, true, false));
+        let receipts = get_created_receipts();
+        assert_eq!(receipts.len(), 3);
+        assert_eq!(receipts[0].receiver_id, token());
+        assert!(matches!(
+            &receipts[0].actions[..],
+            [VmAction::FunctionCall { function_name, deposit: FT_STORAGE_DEPOSIT, .. }] if function_name == "storage_deposit"
+        ));
+        assert_eq!(receipts[1].receiver_id, token());
+        match &receipts[1].actions[..] {
+            [VmAction::FunctionCall { function_name, args, deposit: 1, .. }] => {
+                assert_eq!(function_name, "ft_transfer");
+                let args: serde_json::Value = serde_json::from_slice(args).unwrap();
+                assert_eq!(args, json!({ "receiver_id": new_account(), "amount": "100", "memo": null }));
+            }
+            actions => panic!("Unexpected actions {:?}", actions),
+        }
+        assert_eq!(receipts[2].receiver_id, linkdrop());
+
+        // The key is deleted once the tokens are sent
+        callback_env(PromiseResult::Successful(vec![]));
+        assert!(contract.on_ft_claimed(new_account(), funding_key(), ft_drop_info(FT_STORAGE_DEPOSIT + 1_000, 100)));
+        assert_eq!(
+            get_created_receipts()[0].actions,
+            vec![VmAction::DeleteKey { public_key: funding_key() }]
+        );
+        assert!(contract.accounts.get(&funding_key()).is_none());
+    }
+
+    #[test]
+    fn test_ft_claim_failure_restores_tokens() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        // The tokens stay claimable with the key, without the balance already claimed
+        callback_env(PromiseResult::Failed);
+        assert!(!contract.on_ft_claimed(new_account(), funding_key(), ft_drop_info(FT_STORAGE_DEPOSIT + 1_000, 100)));
+        assert_eq!(contract.accounts.get(&funding_key()).unwrap(), ft_drop_info(0, 100));
+        assert_eq!(contract.total_locked, 0);
+        assert!(get_created_receipts().is_empty());
+    }
+
+    #[test]
+    fn test_ft_claim_failure_merges_tokens() {
+        let mut contract = LinkDrop::new(owner(), None);
+        register_storage(&mut contract);
+        contract.accounts.insert(&key(1), &ft_drop_info(500, 50));
+
+        callback_env(PromiseResult::Failed);
+        contract.on_ft_claimed(new_account(), key(1), ft_drop_info(FT_STORAGE_DEPOSIT, 100));
+        assert_eq!(
+            contract.accounts.get(&key(1)).unwrap(),
+            DropInfo { uses_remaining: 2, amount_per_use: U128(250), ..ft_drop_info(500, 150) }
+        );
+    }
+
+    #[test]
+    fn test_reclaim_expired_refunds_tokens() {
+        let mut contract = LinkDrop::new(owner(), None);
+        contract.accounts.insert(
+            &funding_key(),
+            &DropInfo { expires_at: Some(U64(10)), ..ft_drop_info(1_000, 100) },
+        );
+        contract.total_locked = 1_000;
+
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob())
+            .block_timestamp(20)
+            .context.clone()
+        );
+        contract.reclaim_expired(funding_key()).unwrap();
+
+        // The tokens go back to the funder along with the balance
+        let receipts = get_created_receipts();
+        let token_receipt = receipts.iter().find(|receipt| receipt.receiver_id == token()).unwrap();
+        match &token_receipt.actions[..] {
+            [VmAction::FunctionCall { function_name, args, .. }] => {
+                assert_eq!(function_name, "ft_transfer");
+                let args: serde_json::Value = serde_json::from_slice(args).unwrap();
+                assert_eq!(args["receiver_id"], json!(bob()));
+                assert_eq!(args["amount"], json!("100"));
+            }
+            actions => panic!("Unexpected actions {:?}", actions),
+        }
+    }
+}
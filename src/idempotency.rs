@@ -0,0 +1,178 @@
+use crate::*;
+
+/// Nanoseconds a completed creation is remembered under its idempotency key: 1 day.
+pub const IDEMPOTENCY_KEY_LIFETIME: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+/// Longest idempotency key accepted by `create_account_advanced`.
+pub const MAX_IDEMPOTENCY_KEY_BYTES: usize = 64;
+
+#[near_bindgen]
+impl LinkDrop {
+    /// Returns the account created by `caller_id` with `idempotency_key` in the last `IDEMPOTENCY_KEY_LIFETIME`,
+    /// if any.
+    pub fn get_idempotent_creation(&self, caller_id: AccountId, idempotency_key: String) -> Option<AccountId> {
+        self.idempotency_keys
+            .get(&idempotency_record_key(&caller_id, &idempotency_key))
+            .filter(|creation| !creation.is_expired())
+            .map(|creation| creation.new_account_id)
+    }
+
+    /// Forget the expired creations of `caller_id` among `idempotency_keys`, freeing their storage. Callable by
+    /// anyone. Returns how many were removed.
+    pub fn remove_expired_idempotency_keys(&mut self, caller_id: AccountId, idempotency_keys: Vec<String>) -> u32 {
+        let mut removed = 0;
+        for idempotency_key in idempotency_keys {
+            let record_key = idempotency_record_key(&caller_id, &idempotency_key);
+            if self.idempotency_keys.get(&record_key).is_some_and(|creation| creation.is_expired()) {
+                self.idempotency_keys.remove(&record_key);
+                removed += 1;
+            }
+        }
+        removed
+    }
+}
+
+impl LinkDrop {
+    /// Returns the account already created by the predecessor with `idempotency_key`, dropping the record if it
+    /// expired. Fails if the key is too long or was used for another account than `new_account_id`.
+    pub(crate) fn internal_find_creation(
+        &mut self,
+        idempotency_key: &str,
+        new_account_id: &AccountId,
+    ) -> Result<Option<AccountId>, ContractError> {
+        ensure(idempotency_key.len() <= MAX_IDEMPOTENCY_KEY_BYTES, || {
+            ContractError::InvalidArgument(format!(
+                "Idempotency key is {} bytes long, at most {} bytes are allowed",
+                idempotency_key.len(),
+                MAX_IDEMPOTENCY_KEY_BYTES
+            ))
+        })?;
+        let record_key = idempotency_record_key(&env::predecessor_account_id(), idempotency_key);
+        let creation = match self.idempotency_keys.get(&record_key) {
+            Some(creation) => creation,
+            None => return Ok(None),
+        };
+        if creation.is_expired() {
+            self.idempotency_keys.remove(&record_key);
+            return Ok(None);
+        }
+        ensure(&creation.new_account_id == new_account_id, || {
+            ContractError::InvalidArgument(format!(
+                "Idempotency key {} was already used to create another account",
+                idempotency_key
+            ))
+        })?;
+        Ok(Some(creation.new_account_id))
+    }
+
+    /// Remember that `new_account_id` was created under `record_key`, see `idempotency_record_key`.
+    pub(crate) fn internal_record_creation(&mut self, record_key: &str, new_account_id: AccountId) {
+        let creation = IdempotentCreation {
+            new_account_id,
+            expires_at: env::block_timestamp() + IDEMPOTENCY_KEY_LIFETIME,
+        };
+        self.idempotency_keys.insert(&record_key.to_string(), &creation);
+    }
+}
+
+/// Key of `idempotency_keys` for `idempotency_key` of `caller_id`, so that callers can't collide.
+pub(crate) fn idempotency_record_key(caller_id: &AccountId, idempotency_key: &str) -> String {
+    format!("{}:{}", caller_id, idempotency_key)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[cfg(test)]
+mod tests {
+    use near_sdk::test_utils::{get_created_receipts, get_logs, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use crate::tests::{bob, callback_env, full_key_options, key, linkdrop, new_account, owner};
+
+    use super::*;
+
+    fn set_context(block_timestamp: u64) {
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob())
+            .attached_deposit(1_000)
+            .block_timestamp(block_timestamp)
+            .context.clone()
+        );
+    }
+
+    fn create(contract: &mut LinkDrop, idempotency_key: &str) -> PromiseOrValue<bool> {
+        contract.create_account_advanced(new_account(), full_key_options(vec![key(1)]), None, Some(idempotency_key.to_string()), None, None).unwrap()
+    }
+
+    /// A contract that created `new_account()` for bob under the idempotency key "retry".
+    fn created_contract() -> LinkDrop {
+        set_context(0);
+        let mut contract = LinkDrop::new(owner(), None);
+        drop(create(&mut contract, "retry"));
+
+        callback_env(PromiseResult::Successful(vec![]));
+        drop(contract.on_account_created(new_account(), bob(), bob(), U128(1_000), Some(idempotency_record_key(&bob(), "retry")), None, None));
+        contract
+    }
+
+    #[test]
+    fn test_fresh_idempotency_key() {
+        set_context(0);
+        let mut contract = LinkDrop::new(owner(), None);
+
+        // Nothing is recorded until the creation succeeded
+        assert!(matches!(create(&mut contract, "retry"), PromiseOrValue::Promise(_)));
+        assert!(get_created_receipts().iter().any(|receipt| receipt.receiver_id == new_account()));
+        assert!(contract.get_idempotent_creation(bob(), "retry".to_string()).is_none());
+    }
+
+    #[test]
+    fn test_repeated_idempotency_key() {
+        let mut contract = created_contract();
+        assert_eq!(contract.get_idempotent_creation(bob(), "retry".to_string()), Some(new_account()));
+
+        // The retry is refunded without creating the account again
+        set_context(1);
+        assert!(matches!(create(&mut contract, "retry"), PromiseOrValue::Value(true)));
+        let receipts = get_created_receipts();
+        assert_eq!(receipts.len(), 1);
+        assert_eq!(receipts[0].receiver_id, bob());
+        assert_eq!(get_logs(), vec![format!("Account {} was already created with idempotency key retry", new_account())]);
+    }
+
+    #[test]
+    fn test_expired_idempotency_key() {
+        let mut contract = created_contract();
+
+        // Past the lifetime the key starts a new creation
+        set_context(IDEMPOTENCY_KEY_LIFETIME + 1);
+        assert!(contract.get_idempotent_creation(bob(), "retry".to_string()).is_none());
+        assert!(matches!(create(&mut contract, "retry"), PromiseOrValue::Promise(_)));
+    }
+
+    #[test]
+    fn test_remove_expired_idempotency_keys() {
+        let mut contract = created_contract();
+
+        // Live records stay
+        assert_eq!(contract.remove_expired_idempotency_keys(bob(), vec!["retry".to_string()]), 0);
+        set_context(IDEMPOTENCY_KEY_LIFETIME + 1);
+        assert_eq!(contract.remove_expired_idempotency_keys(bob(), vec!["retry".to_string(), "other".to_string()]), 1);
+        assert!(contract.idempotency_keys.get(&idempotency_record_key(&bob(), "retry")).is_none());
+    }
+
+    #[test]
+    fn test_idempotency_key_other_account() {
+        let mut contract = created_contract();
+
+        set_context(1);
+        let result = contract.create_account_advanced("carol.linkdrop".parse().unwrap(), full_key_options(vec![key(1)]), None, Some("retry".to_string()), None, None);
+        assert_eq!(
+            result.err(),
+            Some(ContractError::InvalidArgument(
+                "Idempotency key retry was already used to create another account".to_string()
+            ))
+        );
+    }
+}
@@ -1,195 +1,6492 @@
+use std::collections::HashSet;
+
 use borsh::{BorshDeserialize, BorshSerialize};
-use near_sdk::collections::{UnorderedMap};
-use near_sdk::json_types::{U128};
+use near_sdk::collections::{LookupMap, UnorderedMap, UnorderedSet, Vector};
+use near_sdk::json_types::{Base58CryptoHash, Base64VecU8, U128, U64};
 use near_sdk::{
-    env, ext_contract, near_bindgen, PanicOnDefault, AccountId, Balance, Promise, PromiseResult, PublicKey, Gas,
+    env, ext_contract, near_bindgen, serde_json, PanicOnDefault, AccountId, Balance, CryptoHash, Promise, PromiseOrValue, PromiseResult, PublicKey, Gas, CurveType,
 };
 
+mod category;
+mod creation_log;
+mod debt;
+mod errors;
+mod events;
+mod ft;
+mod idempotency;
+mod migrate;
 mod models;
+mod multisig;
+mod nft;
+mod owner;
+mod storage;
+use ed25519_dalek::Verifier;
+use errors::*;
+use events::*;
+use ft::*;
+use idempotency::*;
 use models::*;
+use nft::*;
+use storage::*;
 
 #[near_bindgen]
 #[derive(PanicOnDefault, BorshDeserialize, BorshSerialize)]
+/// The borsh layout of this struct is the contract state: adding or reordering fields (as `total_locked` did)
+/// requires migrating the state of existing deployments.
 pub struct LinkDrop {
-    pub accounts: UnorderedMap<PublicKey, Balance>,
+    pub accounts: UnorderedMap<PublicKey, DropInfo>,
+    /// Sum of all the balances stored in `accounts`, kept up to date as keys are funded and claimed.
+    pub total_locked: Balance,
+    /// sha256 hashes of the contracts `create_account_advanced` may deploy. Any contract can be deployed while empty.
+    pub allowed_contract_hashes: UnorderedSet<Vec<u8>>,
+    /// Account allowed to call the privileged methods.
+    pub owner_id: AccountId,
+    /// Account proposed to take over ownership through `accept_owner`.
+    pub proposed_owner_id: Option<AccountId>,
+    /// Whether account creation and linkdrop funding are halted.
+    pub paused: bool,
+    /// Balances funded before drops recorded their funder, moved into `accounts` by `migrate_drops`.
+    pub legacy_accounts: UnorderedMap<PublicKey, Balance>,
+    /// NEP-145 storage balances of the funders, paying for the storage of their linkdrops.
+    pub storage_accounts: LookupMap<AccountId, StorageAccount>,
+    /// yoctoNEAR$ taken out of the deposit of every `create_account_advanced` call.
+    pub creation_fee: Balance,
+    /// Basis points of the deposit left after `creation_fee` also taken out as a fee, rounded down.
+    pub fee_bps: u16,
+    /// Account receiving the creation fees.
+    pub treasury_id: AccountId,
+    /// Public keys of the linkdrops in `accounts`, by funder.
+    pub drops_by_funder: LookupMap<AccountId, UnorderedSet<PublicKey>>,
+    /// Accounts allowed to create accounts through this contract. Anyone can while empty.
+    pub authorized_callers: UnorderedSet<AccountId>,
+    /// Accounts confirming the privileged operations once `required_confirmations` is set.
+    pub council: UnorderedSet<AccountId>,
+    /// Council confirmations needed to execute a privileged operation. The owner acts alone while 0.
+    pub required_confirmations: u32,
+    /// Pending privileged operations, by id.
+    pub requests: UnorderedMap<u64, Request>,
+    /// Id of the next request added to `requests`.
+    pub next_request_id: u64,
+    /// yoctoNEAR$ a new account must at least receive after the creation fee, plus the storage of its contract.
+    /// Not enforced while 0.
+    pub min_new_account_balance: Balance,
+    /// Number of accounts successfully created by this contract, with or without a linkdrop.
+    pub accounts_created: u64,
+    /// yoctoNEAR$ a call creating accounts without a linkdrop can attach at most.
+    pub max_deposit_per_call: Balance,
+    /// yoctoNEAR$ the linkdrops can lock in total, checked when funding them.
+    pub max_total_locked: Balance,
+    /// Accounts created by `create_account_advanced` with an idempotency key, by caller and key.
+    pub idempotency_keys: LookupMap<String, IdempotentCreation>,
+    /// Outcomes of the creations of `create_account_advanced`, oldest first, until pruned by the owner.
+    pub creation_log: Vector<CreationRecord>,
+    /// Creations that failed in a row since the last successful one, see `internal_count_failure`.
+    pub consecutive_failures: u32,
+    /// Consecutive failed creations above which the contract pauses itself. Not enforced while 0.
+    pub max_consecutive_failures: u32,
+    /// Callers whose creations failed since the last successful one, tracked while the circuit breaker is enabled.
+    pub failing_creators: UnorderedSet<AccountId>,
+    /// Leftmost labels of the account ids this contract refuses to create, e.g. `admin`, in lowercase.
+    pub reserved_labels: UnorderedSet<String>,
+    /// Accounts being created through `on_account_created`, with the block height their creation started at.
+    pub creations_in_flight: LookupMap<AccountId, u64>,
+    /// Where the deposits of failed creations go.
+    pub refund_policy: RefundPolicy,
+    /// yoctoNEAR$ of failed creations kept for the treasury under `RefundPolicy::Retain`, not withdrawn yet.
+    pub retained_refunds: Balance,
+    /// yoctoNEAR$ of the deposits over the target balance of in-flight creations, refunded by `on_account_created`.
+    pub pending_refunds: Balance,
+    /// Sum of the `storage_accounts` balances, owed to the funders.
+    pub storage_deposits: Balance,
+    /// Nanoseconds a caller must wait after one of its creations succeeded before creating another account.
+    /// Not enforced while 0.
+    pub per_account_cooldown: u64,
+    /// Block timestamp in nanoseconds of the last successful creation of each caller, while a cooldown is set.
+    pub last_creation_at: LookupMap<AccountId, u64>,
+    /// What the accounts created with a debt still owe to their sponsor, see `repay`.
+    pub debts: LookupMap<AccountId, DebtRecord>,
+    /// Bytes a contract deployed to a new account can take at most.
+    pub max_contract_size: u64,
+    /// Keys whose last use was claimed, refused by `send` and `top_up` until the owner clears them so that a
+    /// stale transaction can't fund a key a past claimer still holds.
+    pub claimed_keys: UnorderedSet<PublicKey>,
+    /// Full access keys added to every account created without a linkdrop on top of the caller's, e.g. a
+    /// recovery key of the operator for custodial flows. None are added while empty.
+    pub default_full_access_keys: Vec<PublicKey>,
+    /// yoctoNEAR$ set aside by the owner with `fund_storage_reserve`, drawn by `send` to pay for the storage of the
+    /// contract once its margin over the storage stake runs low.
+    pub storage_reserve: Balance,
+    /// Nonce the next `claim_with_signature` of each funding key must sign, bumped by every signed claim that goes
+    /// through so that a captured signature can't claim the key again once it's funded anew. 0 when missing.
+    pub claim_nonces: LookupMap<PublicKey, u64>,
+    /// Budgets of the linkdrop categories, charged by `send` for the linkdrops funded under them.
+    pub category_budgets: LookupMap<String, CategoryBudget>,
+    /// Fees charged on the creations of specific callers instead of `creation_fee` and `fee_bps`.
+    pub fee_overrides: UnorderedMap<AccountId, FeeConfig>,
+    /// `CONTRACT_VERSION` of the contract that initialized the state or last migrated it, see `migrate`.
+    pub last_migration: String,
 }
 
-/// Gas attached to the callback from account creation.
+/// Gas attached to the callback from account creation and claim.
 pub const ON_CREATE_ACCOUNT_CALLBACK_GAS: Gas = Gas(13_000_000_000_000);
 
+/// Minimum gas attached to the `on_account_created` callback, enough for the refund, its callback and the bookkeeping.
+pub const MIN_CALLBACK_GAS: Gas = Gas(8_000_000_000_000);
+
+/// Gas attached to the callback returning the outcome of a refunded creation.
+pub const ON_CREATION_REFUNDED_CALLBACK_GAS: Gas = Gas(2_000_000_000_000);
+
+/// Extra callback gas for every access key added to the new account.
+pub const CALLBACK_GAS_PER_KEY: Gas = Gas(100_000_000_000);
+
+/// Extra callback gas when a contract is deployed to the new account.
+pub const CALLBACK_DEPLOY_GAS: Gas = Gas(3_000_000_000_000);
+
+/// Gas attached to the callback collecting the outcomes of `create_accounts`.
+pub const ON_ACCOUNTS_CREATED_CALLBACK_GAS: Gas = Gas(5_000_000_000_000);
+
+/// Extra gas of `create_account_advanced_checked` for the existence probe and its callback.
+pub const ACCOUNT_CHECK_GAS: Gas = Gas(10_000_000_000_000);
+
+/// Gas attached to the `create_account_resolve` callback of `create_account_and_resolve`.
+pub const CREATE_ACCOUNT_RESOLVE_GAS: Gas = Gas(3_000_000_000_000);
+
+/// Gas `create_account_advanced` needs on top of its keys, contract and callback, for its own execution and
+/// the creation receipt.
+pub const CREATION_BASE_GAS: Gas = Gas(20_000_000_000_000);
+
+/// Extra gas `create_account_advanced` needs for every access key added to the new account.
+pub const CREATION_GAS_PER_KEY: Gas = Gas(500_000_000_000);
+
+/// Extra gas `create_account_advanced` needs for every byte of the deployed contract, to read it from the
+/// arguments and deploy it.
+pub const CREATION_GAS_PER_CONTRACT_BYTE: Gas = Gas(100_000_000);
+
+/// Extra gas `create_account_with_contract` needs for the init call on top of the gas attached to it, for the
+/// function call action itself.
+pub const CREATION_INIT_CALL_GAS: Gas = Gas(5_000_000_000_000);
+
+/// Gas attached to the callback from `add_keys` and `change_key`.
+pub const ON_KEYS_ADDED_CALLBACK_GAS: Gas = Gas(5_000_000_000_000);
+
+/// Gas attached to the call notifying the claim callback of a linkdrop, see `set_claim_callback`.
+pub const CLAIM_NOTIFICATION_GAS: Gas = Gas(5_000_000_000_000);
+
+/// Maximum number of accounts created by a single `create_accounts` call, to stay within the gas limit.
+pub const MAX_CREATE_ACCOUNTS_BATCH: u64 = 10;
+
+/// Maximum number of keys a single `batch_reclaim_expired` call goes through, to stay within the gas limit.
+pub const MAX_RECLAIM_BATCH: u64 = 20;
+
+/// Maximum number of keys a single `send_many` call funds, each adding an access key to this contract.
+pub const MAX_SEND_BATCH: u64 = 50;
+
+/// Allowance of the access key added to this contract for every linkdrop. It pays for the gas of the claim
+/// and is reserved out of the deposit attached to `send`.
+pub const ACCESS_KEY_ALLOWANCE: Balance = 20_000_000_000_000_000_000_000;
+
+/// Minimum deposit attached to `send`: the access key allowance plus 0.01 N so the claimed account
+/// can at least cover its own storage.
+pub const MIN_SEND_DEPOSIT: Balance = ACCESS_KEY_ALLOWANCE + 10_000_000_000_000_000_000_000;
+
+/// Maximum number of access keys, full and limited combined, added to an account by `create_account_advanced`.
+pub const MAX_KEYS_PER_ACCOUNT: u64 = 20;
+
+/// Basis points in 100%, the most `fee_bps` can be.
+pub const MAX_FEE_BPS: u16 = 10_000;
+
+/// Number of keys returned by `get_keys` when no limit is given.
+pub const DEFAULT_KEYS_LIMIT: u64 = 50;
+
+/// Maximum number of keys returned by `get_keys`, larger limits are clamped to stay within view gas.
+pub const MAX_KEYS_LIMIT: u64 = 100;
+
+/// Maximum length in bytes of the metadata of a linkdrop.
+pub const MAX_METADATA_BYTES: usize = 256;
+
+/// Maximum length in bytes of the name of a category, see `set_category_budget`.
+pub const MAX_CATEGORY_BYTES: usize = 64;
+
+/// Semantic version of the contract, the one of the crate it was built from.
+pub const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Default `max_contract_size`, the runtime's limit on the size of a contract in bytes.
+pub const DEFAULT_MAX_CONTRACT_SIZE: u64 = 4 * 1024 * 1024;
+
+/// Methods the linkdrop access key is allowed to call on this contract.
+pub const ACCESS_KEY_METHOD_NAMES: &str = "claim,claim_with_keys,create_account_and_claim";
+
+/// Methods of this contract a limited access key added to a new account may be restricted to, when it targets
+/// this contract: the claims, so that a key can't be minted for the privileged methods.
+pub const SELF_CALLABLE_METHODS: &[&str] =
+    &["claim", "claim_with_keys", "claim_with_signature", "create_account_and_claim"];
+
+#[allow(clippy::too_many_arguments)]
 #[ext_contract(ext_self)]
 pub trait ExtLinkDrop {
     /// Callback after plain account creation.
-    fn on_account_created(&mut self, predecessor_account_id: AccountId, amount: U128) -> bool;
+    #[allow(clippy::too_many_arguments)]
+    fn on_account_created(
+        &mut self,
+        new_account_id: AccountId,
+        predecessor_account_id: AccountId,
+        creator_id: AccountId,
+        amount: U128,
+        idempotency_key: Option<String>,
+        debt: Option<U128>,
+        excess: Option<U128>,
+    ) -> PromiseOrValue<CreationOutcome>;
+
+    /// Callback after checking whether the account to create exists.
+    fn on_account_checked(
+        &mut self,
+        new_account_id: AccountId,
+        options: CreateAccountOptions,
+        predecessor_account_id: AccountId,
+        deposit: U128,
+    ) -> PromiseOrValue<CreationOutcome>;
+
+    /// Callback after refunding a failed creation.
+    fn on_creation_refunded(&self, outcome: CreationOutcome) -> CreationOutcome;
+
+    /// Callback after adding keys to an existing account.
+    fn on_keys_added(&self, account_id: AccountId) -> bool;
+
+    /// Callback after swapping a full access key of an existing account.
+    fn on_key_changed(&self, account_id: AccountId) -> bool;
+
+    /// Callback after all the creations of `create_accounts`.
+    fn on_accounts_created(&self) -> Vec<Option<CreationOutcome>>;
+
+    /// Callback after the creation of `create_account_and_resolve` settled.
+    fn create_account_resolve(&self) -> PromiseOrValue<bool>;
 
     /// Callback after creating account and claiming linkdrop.
-    fn on_account_created_and_claimed(&mut self, amount: U128) -> bool;
+    #[allow(clippy::too_many_arguments)]
+    fn on_account_created_and_claimed(
+        &mut self,
+        new_account_id: AccountId,
+        public_key: PublicKey,
+        drop: DropInfo,
+        account_created: bool,
+        beneficiary: Option<(AccountId, U128)>,
+        last_use: bool,
+        signed_claim: bool,
+    ) -> bool;
+
+    /// Callback after sending the tokens of an FT drop to the claimer.
+    fn on_ft_claimed(&mut self, account_id: AccountId, public_key: PublicKey, drop: DropInfo) -> bool;
+
+    /// Callback after sending the NFT of an NFT drop to the claimer.
+    fn on_nft_claimed(&mut self, account_id: AccountId, public_key: PublicKey, drop: DropInfo) -> bool;
+}
+
+/// The NEP-141 and NEP-145 methods of the token contracts of FT drops.
+#[ext_contract(ext_ft)]
+pub trait FungibleToken {
+    fn ft_transfer(&mut self, receiver_id: AccountId, amount: U128, memo: Option<String>);
+
+    fn storage_deposit(&mut self, account_id: Option<AccountId>, registration_only: Option<bool>) -> StorageBalance;
+}
+
+/// The NEP-171 method of the token contracts of NFT drops.
+#[ext_contract(ext_nft)]
+pub trait NonFungibleToken {
+    fn nft_transfer(&mut self, receiver_id: AccountId, token_id: String, approval_id: Option<u64>, memo: Option<String>);
 }
 
 fn is_promise_success() -> bool {
+    all_promises_succeeded(1)
+}
+
+/// Best guess at why the creation transferring `amount` to the new account failed. The runtime only tells the
+/// callback that the promise failed, not why, so short of an `amount` that can't even pay for the storage of a
+/// bare account the reason is `Unknown`.
+fn creation_failure_reason(amount: Balance) -> CreationFailureReason {
+    if amount < ACCOUNT_STORAGE_BYTES as Balance * env::storage_byte_cost() {
+        CreationFailureReason::InsufficientFunds
+    } else {
+        CreationFailureReason::Unknown
+    }
+}
+
+/// Whether all the `n` promises the callback waited on succeeded, for callbacks of promises joined with `and`.
+fn all_promises_succeeded(n: u64) -> bool {
     assert_eq!(
         env::promise_results_count(),
-        1,
-        "Contract expected a result on the callback"
+        n,
+        "Contract expected {} results on the callback",
+        n
     );
-    match env::promise_result(0) {
-        PromiseResult::Successful(_) => true,
+    (0..n).all(|index| matches!(env::promise_result(index), PromiseResult::Successful(_)))
+}
+
+/// Whether `signature` is a valid ed25519 signature of `message` by `public_key`.
+fn verify_signature(public_key: &PublicKey, message: &[u8], signature: &[u8]) -> bool {
+    if public_key.curve_type() != CurveType::ED25519 {
+        return false;
+    }
+    match (
+        ed25519_dalek::PublicKey::from_bytes(&public_key.as_bytes()[1..]),
+        ed25519_dalek::Signature::from_bytes(signature),
+    ) {
+        (Ok(public_key), Ok(signature)) => public_key.verify(message, &signature).is_ok(),
         _ => false,
     }
 }
 
+/// Gas attached to the `on_account_created` callback of a creation with the given options: `MIN_CALLBACK_GAS`
+/// plus the extra gas for the deployment and every access key.
+fn compute_callback_gas(options: &CreateAccountOptions) -> Gas {
+    let keys = options.full_access_keys.as_ref().map_or(0, Vec::len) + options.limited_keys().len();
+    let mut gas = MIN_CALLBACK_GAS + Gas(CALLBACK_GAS_PER_KEY.0 * keys as u64);
+    if options.contract_bytes.is_some() {
+        gas += CALLBACK_DEPLOY_GAS;
+    }
+    gas
+}
+
+/// Add the full and limited access keys of `options` to `promise`. The keys must have passed `check_keys`.
+fn add_access_keys(mut promise: Promise, options: &CreateAccountOptions) -> Promise {
+    // If there are any full access keys in the options, loop through and add them to the promise
+    if let Some(full_access_keys) = &options.full_access_keys {
+        for key in full_access_keys {
+            promise = match options.full_access_key_nonce {
+                Some(nonce) => promise.add_full_access_key_with_nonce(key.clone(), nonce.0),
+                None => promise.add_full_access_key(key.clone()),
+            };
+        }
+    }
+
+    // If there are any function call access keys in the options, loop through and add them to the promise
+    for key_info in options.limited_keys() {
+        promise = match key_info.nonce {
+            Some(nonce) => promise.add_access_key_with_nonce(key_info.public_key, key_info.allowance.0, key_info.receiver_id, key_info.method_names, nonce.0),
+            None => promise.add_access_key(key_info.public_key, key_info.allowance.0, key_info.receiver_id, key_info.method_names),
+        };
+    }
+    promise
+}
+
+/// Gas `create_account_advanced` needs with the given options: `CREATION_BASE_GAS`, the extra gas for every
+/// access key and contract byte, the gas of the init and stake calls plus `CREATION_INIT_CALL_GAS` each and the
+/// callback gas.
+fn estimate_creation_gas(options: &CreateAccountOptions) -> Gas {
+    let keys = options.full_access_keys.as_ref().map_or(0, Vec::len) + options.limited_keys().len();
+    let contract_bytes = options.contract_bytes.as_ref().map_or(0, Vec::len);
+    let init_gas = options.deploy_init.as_ref().map_or(Gas(0), |init| init.gas + CREATION_INIT_CALL_GAS);
+    let stake_gas = options.stake.as_ref().map_or(Gas(0), |stake| stake.gas + CREATION_INIT_CALL_GAS);
+    CREATION_BASE_GAS
+        + Gas(CREATION_GAS_PER_KEY.0 * keys as u64)
+        + Gas(CREATION_GAS_PER_CONTRACT_BYTE.0 * contract_bytes as u64)
+        + init_gas
+        + stake_gas
+        + compute_callback_gas(options)
+}
+
+/// Fails if the access keys of `options` can't all be added to an account: too many or repeated keys, malformed
+/// public keys or limited keys.
+fn check_keys(options: &CreateAccountOptions) -> Result<(), ContractError> {
+    options.check_unique_keys()?;
+    for public_key in options.full_access_keys.iter().flatten() {
+        check_public_key(public_key)?;
+    }
+    for key_info in options.limited_keys() {
+        check_public_key(&key_info.public_key)?;
+    }
+    for (index, key_info) in options.limited_keys().iter().enumerate() {
+        key_info.check(index)?;
+        if key_info.receiver_id == env::current_account_id() {
+            check_self_callable(index, &key_info.method_names)?;
+        }
+    }
+    Ok(())
+}
+
+/// Fails unless `method_names`, those of the limited access key at `index` targeting this contract, are all in
+/// `SELF_CALLABLE_METHODS`. An empty list would allow any method and is refused as well.
+fn check_self_callable(index: usize, method_names: &str) -> Result<(), ContractError> {
+    ensure(!method_names.split(',').all(str::is_empty), || {
+        ContractError::InvalidArgument(format!(
+            "Limited access key at index {} targets this contract, it must be restricted to some of {}",
+            index,
+            SELF_CALLABLE_METHODS.join(",")
+        ))
+    })?;
+    method_names.split(',').filter(|name| !name.is_empty()).try_for_each(|method_name| {
+        ensure(SELF_CALLABLE_METHODS.contains(&method_name), || {
+            ContractError::InvalidArgument(format!(
+                "Limited access key at index {} cannot call {} on this contract, only {}",
+                index,
+                method_name,
+                SELF_CALLABLE_METHODS.join(",")
+            ))
+        })
+    })
+}
+
+/// Fails if staking with `options` out of the `amount` the new account gets, after the deposit of the init call,
+/// leaves less than the storage of the account costs. The staked amount is locked in the pool.
+fn check_stake_leaves_storage(options: &CreateAccountOptions, amount: Balance) -> Result<(), ContractError> {
+    let stake = match &options.stake {
+        Some(stake) => stake,
+        None => return Ok(()),
+    };
+    let init_deposit = options.deploy_init.as_ref().map_or(0, |init| init.deposit.0);
+    let storage_cost = account_storage_bytes(options) as Balance * env::storage_byte_cost();
+    let left = amount.saturating_sub(init_deposit).saturating_sub(stake.amount.0);
+    ensure(amount >= init_deposit + stake.amount.0 && left >= storage_cost, || {
+        ContractError::InsufficientDeposit(format!(
+            "Staking {} yoctoNEAR would leave the new account {} yoctoNEAR, below the {} yoctoNEAR its storage costs",
+            stake.amount.0, left, storage_cost
+        ))
+    })
+}
+
+/// Fails early if this contract can't create `new_account_id`, rather than failing in the promise.
+fn check_can_create_account(new_account_id: &AccountId) -> Result<(), ContractError> {
+    validate_account_id_format(new_account_id)?;
+    ensure(can_create_account(new_account_id, &env::current_account_id()), || {
+        ContractError::InvalidArgument(format!(
+            "Cannot create {}: it must be a direct subaccount of {}",
+            new_account_id,
+            env::current_account_id()
+        ))
+    })
+}
+
+/// Fails unless the prepaid gas covers the creation with `options`, see `estimate_creation_gas`. The error
+/// breaks the gas down between the init call, the stake call, the callback and the creation itself.
+fn check_prepaid_gas(options: &CreateAccountOptions) -> Result<(), ContractError> {
+    let required_gas = estimate_creation_gas(options);
+    ensure(env::prepaid_gas() >= required_gas, || {
+        let init_gas = options.deploy_init.as_ref().map_or(Gas(0), |init| init.gas + CREATION_INIT_CALL_GAS);
+        let stake_gas = options.stake.as_ref().map_or(Gas(0), |stake| stake.gas + CREATION_INIT_CALL_GAS);
+        let callback_gas = compute_callback_gas(options);
+        ContractError::InvalidArgument(format!(
+            "Not enough gas to create the account, attach at least {} gas: {} for the init call, {} for the stake \
+             call, {} for the callback and {} for the creation",
+            required_gas.0,
+            init_gas.0,
+            stake_gas.0,
+            callback_gas.0,
+            required_gas.0 - init_gas.0 - stake_gas.0 - callback_gas.0
+        ))
+    })
+}
+
 #[near_bindgen]
 impl LinkDrop {
-    /// Initializes the contract with an empty map for the accounts
+    /// Initializes the contract owned by `owner_id` with an empty map for the accounts, and optionally the hashes
+    /// of the contracts that can be deployed to new accounts
     #[init]
-    pub fn new() -> Self {
-        Self { 
-            accounts: UnorderedMap::new(b"a") 
+    pub fn new(owner_id: AccountId, allowed_contract_hashes: Option<Vec<Base58CryptoHash>>) -> Self {
+        let mut this = Self { 
+            accounts: UnorderedMap::new(b"d"),
+            total_locked: 0,
+            allowed_contract_hashes: UnorderedSet::new(b"c"),
+            treasury_id: owner_id.clone(),
+            owner_id,
+            proposed_owner_id: None,
+            paused: false,
+            legacy_accounts: UnorderedMap::new(b"a"),
+            storage_accounts: LookupMap::new(b"s"),
+            creation_fee: 0,
+            fee_bps: 0,
+            drops_by_funder: LookupMap::new(b"f"),
+            authorized_callers: UnorderedSet::new(b"w"),
+            council: UnorderedSet::new(b"m"),
+            required_confirmations: 0,
+            requests: UnorderedMap::new(b"r"),
+            next_request_id: 0,
+            min_new_account_balance: 0,
+            accounts_created: 0,
+            max_deposit_per_call: Balance::MAX,
+            max_total_locked: Balance::MAX,
+            idempotency_keys: LookupMap::new(b"i"),
+            creation_log: Vector::new(b"l"),
+            consecutive_failures: 0,
+            max_consecutive_failures: 0,
+            failing_creators: UnorderedSet::new(b"u"),
+            reserved_labels: UnorderedSet::new(b"n"),
+            creations_in_flight: LookupMap::new(b"p"),
+            refund_policy: RefundPolicy::RefundToPredecessor,
+            retained_refunds: 0,
+            pending_refunds: 0,
+            storage_deposits: 0,
+            per_account_cooldown: 0,
+            last_creation_at: LookupMap::new(b"q"),
+            debts: LookupMap::new(b"o"),
+            max_contract_size: DEFAULT_MAX_CONTRACT_SIZE,
+            claimed_keys: UnorderedSet::new(b"t"),
+            default_full_access_keys: vec![],
+            storage_reserve: 0,
+            claim_nonces: LookupMap::new(b"g"),
+            category_budgets: LookupMap::new(b"e"),
+            fee_overrides: UnorderedMap::new(b"v"),
+            last_migration: CONTRACT_VERSION.to_string(),
+        };
+        for code_hash in allowed_contract_hashes.unwrap_or_default() {
+            this.allowed_contract_hashes.insert(&CryptoHash::from(code_hash).to_vec());
         }
+        this
+    }
+
+    /// Allow `create_account_advanced` to deploy the contract with the given sha256 hash.
+    pub fn add_allowed_contract_hash(&mut self, code_hash: Base58CryptoHash) {
+        self.assert_owner_action();
+        self.allowed_contract_hashes.insert(&CryptoHash::from(code_hash).to_vec());
+    }
+
+    /// Stop `create_account_advanced` from deploying the contract with the given sha256 hash.
+    pub fn remove_allowed_contract_hash(&mut self, code_hash: Base58CryptoHash) {
+        self.assert_owner_action();
+        self.allowed_contract_hashes.remove(&CryptoHash::from(code_hash).to_vec());
     }
 
     /// Create new account without linkdrop and deposit passed funds (used for creating sub accounts directly).
+    /// Returns `false` right away, refunding the deposit, when `new_account_id` is known to exist already: it is
+    /// this contract or the predecessor. Refunds go to `refund_to`, the predecessor by default, e.g. for relayers
+    /// paying on behalf of a user. A retry with the `idempotency_key` of a creation that succeeded in the last
+    /// `IDEMPOTENCY_KEY_LIFETIME` returns `true` right away and refunds the deposit. Retries sent while the first
+    /// creation is still in flight aren't caught, they fail as the account exists and get refunded. A sponsor
+    /// paying for the creation of someone else's account can record a `debt` the new account owes it once
+    /// created, paid back through `repay`. Contracts are only deployed by `create_account_with_contract`, options
+    /// with `contract_bytes` are refused. The `default_full_access_keys`, if any, are added along with the caller's.
+    /// With a `target_balance`, the new account gets exactly that much and the rest of the deposit, after the
+    /// creation fees, is refunded to `refund_to` once the account is created.
     #[payable]
+    #[handle_result]
     pub fn create_account_advanced(
         &mut self,
         new_account_id: AccountId,
         options: CreateAccountOptions,
-    ) -> Promise {
-        let is_some_option = options.contract_bytes.is_some() || options.full_access_keys.is_some() || options.limited_access_keys.is_some();
-        assert!(is_some_option, "Cannot create account with no options. Please specify either contract bytes, full access keys, or limited access keys.");
-
-        let amount = env::attached_deposit();
-
-        // Initiate a new promise on the new account we're creating and transfer it any attached deposit
-        let mut promise = Promise::new(new_account_id).create_account().transfer(amount);
-        
-        // If there are any full access keys in the options, loop through and add them to the promise
-        if let Some(full_access_keys) = options.full_access_keys {
-            for key in full_access_keys {
-                promise = promise.add_full_access_key(key.clone());
+        refund_to: Option<AccountId>,
+        idempotency_key: Option<String>,
+        debt: Option<U128>,
+        target_balance: Option<U128>,
+    ) -> Result<PromiseOrValue<bool>, ContractError> {
+        self.check_authorized()?;
+        self.check_not_paused()?;
+        self.check_deposit_within_limit()?;
+        ensure(options.contract_bytes.is_none(), || {
+            ContractError::InvalidArgument(
+                "Cannot deploy a contract with create_account_advanced, use create_account_with_contract".to_string(),
+            )
+        })?;
+        let options = self.with_default_keys(options);
+        let deposit = env::attached_deposit();
+        let refund_to = refund_to.unwrap_or_else(env::predecessor_account_id);
+        if let Some(idempotency_key) = &idempotency_key {
+            if self.internal_find_creation(idempotency_key, &new_account_id)?.is_some() {
+                env::log_str(&format!(
+                    "Account {} was already created with idempotency key {}",
+                    new_account_id, idempotency_key
+                ));
+                if deposit > 0 {
+                    Promise::new(refund_to).transfer(deposit);
+                }
+                return Ok(PromiseOrValue::Value(true));
             }
         }
-
-        // If there are any function call access keys in the options, loop through and add them to the promise
-        if let Some(limited_access_keys) = options.limited_access_keys {
-            for key_info in limited_access_keys {
-                promise = promise.add_access_key(key_info.public_key.clone(), key_info.allowance.0, key_info.receiver_id.clone(), key_info.method_names.clone());
+        if new_account_id == env::current_account_id() || new_account_id == env::predecessor_account_id() {
+            env::log_str(&format!("Account {} already exists", new_account_id));
+            if deposit > 0 {
+                Promise::new(refund_to).transfer(deposit);
             }
+            return Ok(PromiseOrValue::Value(false));
         }
+        self.check_valid_creation(&new_account_id, &options, deposit)?;
+        check_prepaid_gas(&options)?;
+        ensure(debt.is_none_or(|debt| debt.0 > 0), || {
+            ContractError::InvalidArgument("The debt of a sponsored creation must be positive".to_string())
+        })?;
+        if let Some(target_balance) = target_balance {
+            self.check_target_balance(&options, deposit, target_balance.0)?;
+        }
+        let record_key = idempotency_key
+            .map(|idempotency_key| idempotency_record_key(&env::predecessor_account_id(), &idempotency_key));
+        Ok(self
+            .internal_create_account(
+                new_account_id,
+                options,
+                deposit,
+                refund_to,
+                env::predecessor_account_id(),
+                record_key,
+                debt,
+                target_balance,
+            )
+            .into())
+    }
 
-        // If there are any contract bytes, we should deploy the contract to the account
-        if let Some(bytes) = options.contract_bytes {
-            promise = promise.deploy_contract(bytes);
-        };
+    /// Create `new_account_id` with `contract_bytes` deployed to it, initialized with `init` if given, and the
+    /// access keys of `keys`, whose own `contract_bytes` and `deploy_init` must be left empty. The contract must
+    /// be among the allowed contract hashes, if any are set, and at most `max_contract_size` long. The `stake` of
+    /// `keys`, if any, is attached to the `deposit_and_stake` method of the contract after its init call, so the
+    /// contract must implement it and delegate to the pool for the new account. Refunds the deposit to the
+    /// predecessor if the creation fails, like `create_account_advanced`.
+    #[payable]
+    #[handle_result]
+    pub fn create_account_with_contract(
+        &mut self,
+        new_account_id: AccountId,
+        contract_bytes: Base64VecU8,
+        init: Option<InitCall>,
+        keys: CreateAccountOptions,
+    ) -> Result<Promise, ContractError> {
+        self.check_authorized()?;
+        self.check_not_paused()?;
+        self.check_deposit_within_limit()?;
+        ensure(keys.contract_bytes.is_none() && keys.deploy_init.is_none(), || {
+            ContractError::InvalidArgument(
+                "Pass the contract and its init call as arguments, not in the keys".to_string(),
+            )
+        })?;
+        let options = CreateAccountOptions { contract_bytes: Some(contract_bytes.0), deploy_init: init, ..keys };
+        let options = self.with_default_keys(options);
+        let deposit = env::attached_deposit();
+        self.check_valid_creation(&new_account_id, &options, deposit)?;
+        check_prepaid_gas(&options)?;
 
-        // Callback if anything went wrong, refund the predecessor for their attached deposit
-        promise.then(
+        Ok(self.internal_create_account(
+            new_account_id,
+            options,
+            deposit,
+            env::predecessor_account_id(),
+            env::predecessor_account_id(),
+            None,
+            None,
+            None,
+        ))
+    }
+
+    /// Like `create_account_advanced`, for contracts composing the creation in their own promise chains: the
+    /// creation resolves to a plain `true` or `false` through the `create_account_resolve` callback, which a
+    /// calling contract reads with `env::promise_result` in its own callback, rather than to a `CreationOutcome`.
+    /// Callers must attach `CREATE_ACCOUNT_RESOLVE_GAS` on top of the gas of the creation, see
+    /// `get_account_creation_cost`, and leave enough for their own callback.
+    #[payable]
+    #[handle_result]
+    pub fn create_account_and_resolve(
+        &mut self,
+        new_account_id: AccountId,
+        options: CreateAccountOptions,
+    ) -> Result<Promise, ContractError> {
+        self.check_authorized()?;
+        self.check_not_paused()?;
+        self.check_deposit_within_limit()?;
+        ensure(options.contract_bytes.is_none(), || {
+            ContractError::InvalidArgument(
+                "Cannot deploy a contract with create_account_and_resolve, use create_account_with_contract"
+                    .to_string(),
+            )
+        })?;
+        let options = self.with_default_keys(options);
+        let deposit = env::attached_deposit();
+        self.check_valid_creation(&new_account_id, &options, deposit)?;
+        let required_gas = estimate_creation_gas(&options) + CREATE_ACCOUNT_RESOLVE_GAS;
+        ensure(env::prepaid_gas() >= required_gas, || {
+            ContractError::InvalidArgument(format!(
+                "Not enough gas to create the account, attach at least {} gas",
+                required_gas.0
+            ))
+        })?;
+
+        Ok(self
+            .internal_create_account(
+                new_account_id,
+                options,
+                deposit,
+                env::predecessor_account_id(),
+                env::predecessor_account_id(),
+                None,
+                None,
+                None,
+            )
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(CREATE_ACCOUNT_RESOLVE_GAS)
+                    .create_account_resolve()
+            ))
+    }
+
+    /// Like `create_account_advanced`, but first probes `new_account_id` with an empty transfer, which only
+    /// succeeds if the account exists, and refunds the deposit without attempting the creation if so. The probe
+    /// and its callback cost about `ACCOUNT_CHECK_GAS` more than `create_account_advanced`, and the options travel
+    /// through the callback arguments. Options with `contract_bytes` are refused.
+    #[payable]
+    #[handle_result]
+    pub fn create_account_advanced_checked(
+        &mut self,
+        new_account_id: AccountId,
+        options: CreateAccountOptions,
+    ) -> Result<Promise, ContractError> {
+        self.check_authorized()?;
+        self.check_not_paused()?;
+        self.check_deposit_within_limit()?;
+        ensure(options.contract_bytes.is_none(), || {
+            ContractError::InvalidArgument(
+                "Cannot deploy a contract with create_account_advanced_checked, use create_account_with_contract"
+                    .to_string(),
+            )
+        })?;
+        let options = self.with_default_keys(options);
+        let deposit = env::attached_deposit();
+        self.check_valid_creation(&new_account_id, &options, deposit)?;
+        check_prepaid_gas(&options)?;
+
+        let callback_gas = ACCOUNT_CHECK_GAS + compute_callback_gas(&options);
+        Ok(Promise::new(new_account_id.clone()).transfer(0).then(
             Self::ext(env::current_account_id())
-                .with_static_gas(ON_CREATE_ACCOUNT_CALLBACK_GAS)
-                .on_account_created(
+                .with_static_gas(callback_gas)
+                .on_account_checked(new_account_id, options, env::predecessor_account_id(), U128(deposit))
+        ))
+    }
+
+    /// Create several accounts in one transaction, each funded with its own `deposit`. The deposits must add up to
+    /// the attached deposit. Every creation is refunded on its own if it fails, and the outcomes of all the
+    /// creations are returned in order. Options with `contract_bytes` are refused.
+    #[payable]
+    #[handle_result]
+    pub fn create_accounts(&mut self, requests: Vec<CreateAccountRequest>) -> Result<Promise, ContractError> {
+        self.check_authorized()?;
+        self.check_not_paused()?;
+        self.check_deposit_within_limit()?;
+        ensure(!requests.is_empty(), || {
+            ContractError::InvalidArgument("Cannot create accounts without requests".to_string())
+        })?;
+        ensure(requests.len() as u64 <= MAX_CREATE_ACCOUNTS_BATCH, || {
+            ContractError::InvalidArgument(format!(
+                "Cannot create more than {} accounts at once",
+                MAX_CREATE_ACCOUNTS_BATCH
+            ))
+        })?;
+        // The cooldown only starts once a creation succeeds, so it can't hold within a batch
+        ensure(self.per_account_cooldown == 0 || requests.len() == 1, || {
+            ContractError::InvalidArgument("Cannot create several accounts at once while a cooldown is set".to_string())
+        })?;
+        let requests: Vec<CreateAccountRequest> = requests
+            .into_iter()
+            .map(|request| CreateAccountRequest { options: self.with_default_keys(request.options), ..request })
+            .collect();
+        let mut seen = HashSet::new();
+        for request in requests.iter() {
+            // The second creation of an account would fail and clear the in-flight marker of the first
+            ensure(seen.insert(request.new_account_id.clone()), || {
+                ContractError::InvalidArgument(format!("Account {} is requested more than once", request.new_account_id))
+            })?;
+            ensure(request.options.contract_bytes.is_none(), || {
+                ContractError::InvalidArgument(
+                    "Cannot deploy a contract with create_accounts, use create_account_with_contract".to_string(),
+                )
+            })?;
+            self.check_valid_creation(&request.new_account_id, &request.options, request.deposit.0)?;
+            check_prepaid_gas(&request.options)?;
+        }
+        let total_deposit: Balance = requests.iter().map(|request| request.deposit.0).sum();
+        ensure(total_deposit == env::attached_deposit(), || {
+            ContractError::InvalidArgument("The deposits of the requests must add up to the attached deposit".to_string())
+        })?;
+
+        let mut promises = requests
+            .into_iter()
+            .map(|request| {
+                self.internal_create_account(
+                    request.new_account_id,
+                    request.options,
+                    request.deposit.0,
                     env::predecessor_account_id(),
-                    amount.into()
+                    env::predecessor_account_id(),
+                    None,
+                    None,
+                    None,
                 )
-        )
+            });
+        let first = promises.next().unwrap();
+        Ok(promises
+            .fold(first, |joint, promise| joint.and(promise))
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(ON_ACCOUNTS_CREATED_CALLBACK_GAS)
+                    .on_accounts_created()
+            ))
+    }
+
+    /// Add the full and limited access keys of `options` to the existing `account_id`, e.g. to rotate keys after
+    /// a recovery. `contract_bytes` is ignored. Only callable by the account itself or the owner, and never for this
+    /// contract, whose keys control the locked linkdrop balances. The runtime only lets an account manage its own
+    /// keys, so the keys are only added when this contract is allowed to act on `account_id`, and `on_keys_added`
+    /// logs the result either way.
+    #[handle_result]
+    pub fn add_keys(&mut self, account_id: AccountId, options: CreateAccountOptions) -> Result<Promise, ContractError> {
+        self.check_not_paused()?;
+        let predecessor_account_id = env::predecessor_account_id();
+        ensure(predecessor_account_id == account_id || predecessor_account_id == self.owner_id, || {
+            ContractError::Unauthorized("Only the account itself or the owner can add keys to it")
+        })?;
+        ensure(account_id != env::current_account_id(), || {
+            ContractError::Unauthorized("Cannot add keys to this contract")
+        })?;
+        ensure(
+            options.full_access_keys.is_some()
+                || options.limited_access_keys.is_some()
+                || options.templated_access_keys.is_some(),
+            || {
+                ContractError::InvalidArgument(
+                    "Cannot add keys with no options. Please specify either full access keys or limited access keys."
+                        .to_string(),
+                )
+            },
+        )?;
+        check_keys(&options)?;
+
+        Ok(add_access_keys(Promise::new(account_id.clone()), &options).then(
+            Self::ext(env::current_account_id())
+                .with_static_gas(ON_KEYS_ADDED_CALLBACK_GAS)
+                .on_keys_added(account_id)
+        ))
+    }
+
+    /// Replace `old_public_key` of `account_id` with the full access key `new_public_key`, adding the new key and
+    /// deleting the old one in the same receipt so that the account can't end up with neither, e.g. to recover
+    /// from a leaked key. Only callable by the account itself, and only succeeds when this contract is allowed to
+    /// act on `account_id` like `add_keys`. `on_key_changed` logs the result, a failed swap leaves both keys as
+    /// they were.
+    #[handle_result]
+    pub fn change_key(
+        &mut self,
+        account_id: AccountId,
+        old_public_key: PublicKey,
+        new_public_key: PublicKey,
+    ) -> Result<Promise, ContractError> {
+        self.check_not_paused()?;
+        ensure(env::predecessor_account_id() == account_id, || {
+            ContractError::Unauthorized("Only the account itself can change its keys")
+        })?;
+        ensure(old_public_key != new_public_key, || {
+            ContractError::InvalidArgument("The new key must differ from the old one".to_string())
+        })?;
+
+        Ok(Promise::new(account_id.clone())
+            .add_full_access_key(new_public_key)
+            .delete_key(old_public_key)
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(ON_KEYS_ADDED_CALLBACK_GAS)
+                    .on_key_changed(account_id)
+            ))
+    }
+
+    /// Fund a linkdrop claimable with the given public key. The deposit, minus the `ACCESS_KEY_ALLOWANCE`
+    /// reserved for the claim, is added to any balance the predecessor already funded under the key.
+    /// `expires_at` is a block timestamp in nanoseconds and `metadata` off-chain campaign information of at most
+    /// `MAX_METADATA_BYTES`, both only apply when the key is first funded. So do `uses` (1 by default): the
+    /// balance is then split evenly between that many claims, and the deposit must cover `MIN_SEND_DEPOSIT` for
+    /// each of them as the key gets the `ACCESS_KEY_ALLOWANCE` of every claim. And `secret_hash`, the sha256 of a
+    /// secret the claims must give along with the key, e.g. a passphrase printed next to a QR code. And
+    /// `min_claim_interval`, the nanoseconds to wait after a claim of a multi-use key before the next one. And
+    /// `category`, whose budget every funding of the linkdrop is charged to, see `set_category_budget`.
+    #[payable]
+    #[handle_result]
+    #[allow(clippy::too_many_arguments)]
+    pub fn send(
+        &mut self,
+        public_key: PublicKey,
+        expires_at: Option<U64>,
+        metadata: Option<String>,
+        uses: Option<u32>,
+        secret_hash: Option<Base64VecU8>,
+        min_claim_interval: Option<U64>,
+        category: Option<String>,
+    ) -> Result<Promise, ContractError> {
+        self.check_not_paused()?;
+        let options =
+            SendOptions { expires_at, metadata, uses, secret_hash, min_claim_interval, vesting: None, category };
+        self.internal_send(public_key, env::attached_deposit(), options)
+    }
+
+    /// Fund a linkdrop of `amount_each` under each of `keys` like `send` with the default options, e.g. for a
+    /// campaign. The deposit must cover `amount_each` plus the `ACCESS_KEY_ALLOWANCE` of every key, and each key
+    /// up to `MIN_SEND_DEPOSIT`. Keys given more than once are only funded once, and the excess deposit is
+    /// refunded to the predecessor. Returns how many keys were funded.
+    #[payable]
+    #[handle_result]
+    pub fn send_many(&mut self, keys: Vec<PublicKey>, amount_each: U128) -> Result<u32, ContractError> {
+        self.check_not_paused()?;
+        ensure(keys.len() as u64 <= MAX_SEND_BATCH, || {
+            ContractError::InvalidArgument(format!("Cannot fund more than {} keys at once", MAX_SEND_BATCH))
+        })?;
+        let mut seen = HashSet::new();
+        let keys: Vec<PublicKey> = keys.into_iter().filter(|public_key| seen.insert(public_key.clone())).collect();
+        let overflow = || {
+            ContractError::InvalidArgument(format!("Cannot fund {} keys with {} yoctoNEAR each", keys.len(), amount_each.0))
+        };
+        let deposit_each = amount_each.0.checked_add(ACCESS_KEY_ALLOWANCE).ok_or_else(overflow)?;
+        let required_deposit = deposit_each.checked_mul(keys.len() as Balance).ok_or_else(overflow)?;
+        let deposit = env::attached_deposit();
+        ensure(deposit >= required_deposit, || {
+            ContractError::InsufficientDeposit(format!(
+                "Attached deposit must be at least {} yoctoNEAR to fund {} keys",
+                required_deposit,
+                keys.len()
+            ))
+        })?;
+
+        for public_key in keys.iter() {
+            drop(self.internal_send(public_key.clone(), deposit_each, SendOptions::default())?);
+        }
+        if deposit > required_deposit {
+            Promise::new(env::predecessor_account_id()).transfer(deposit - required_deposit);
+        }
+        Ok(keys.len() as u32)
+    }
+
+    /// Fund a linkdrop like `send`, whose balance unlocks over time following `vesting`, e.g. for team members
+    /// and advisors. Every claim sends the part vested since the previous one, the key stays until the whole
+    /// balance is vested and claimed. The vesting and `metadata` only apply when the key is first funded.
+    #[payable]
+    #[handle_result]
+    pub fn send_vesting(
+        &mut self,
+        public_key: PublicKey,
+        vesting: Vesting,
+        metadata: Option<String>,
+    ) -> Result<Promise, ContractError> {
+        self.check_not_paused()?;
+        let options = SendOptions { metadata, vesting: Some(vesting), ..Default::default() };
+        self.internal_send(public_key, env::attached_deposit(), options)
+    }
+
+    /// Add the attached deposit to the balance of the linkdrop funded under `public_key`, spread over its
+    /// remaining uses, keeping its uses, metadata, expiration and the rest of its configuration as they are.
+    /// Unlike `send`, none of the deposit goes to the access key allowance. Only callable by the funder or the
+    /// owner. Returns the new balance.
+    #[payable]
+    #[handle_result]
+    pub fn top_up(&mut self, public_key: PublicKey) -> Result<U128, ContractError> {
+        self.check_not_paused()?;
+        self.check_not_claimed(&public_key)?;
+        let mut drop = self.accounts.get(&public_key).ok_or(ContractError::KeyMissing)?;
+        let predecessor_account_id = env::predecessor_account_id();
+        ensure(predecessor_account_id == drop.funder_id || predecessor_account_id == self.owner_id, || {
+            ContractError::Unauthorized("Only the funder or the owner can top up the linkdrop")
+        })?;
+        ensure(!drop.is_expired(), || ContractError::InvalidArgument("Linkdrop has expired".to_string()))?;
+        let amount = env::attached_deposit();
+        ensure(amount > 0, || ContractError::InsufficientDeposit("Attach the yoctoNEAR to top up".to_string()))?;
+        ensure(self.total_locked + amount <= self.max_total_locked, || {
+            ContractError::InvalidArgument(format!(
+                "Funding the linkdrop would lock more than the maximum of {} yoctoNEAR",
+                self.max_total_locked
+            ))
+        })?;
+
+        if let Some(category) = &drop.category {
+            self.internal_spend_category(category, amount)?;
+        }
+        drop.add_balance(amount);
+        self.accounts.insert(&public_key, &drop);
+        self.total_locked += amount;
+        Ok(drop.balance)
+    }
+
+    /// Notify `receiver_id` of every successful claim of the linkdrop funded under `public_key` by calling its
+    /// `method` with the public key, the claiming account, the funder, the amount and the metadata of the
+    /// linkdrop as JSON args. Best effort: a failing notification doesn't revert the claim. Both `None` stop
+    /// the notifications. The receiver can't be this contract, which would take the notification for one of its
+    /// own callbacks. Only callable by the funder.
+    #[handle_result]
+    pub fn set_claim_callback(
+        &mut self,
+        public_key: PublicKey,
+        receiver_id: Option<AccountId>,
+        method: Option<String>,
+    ) -> Result<(), ContractError> {
+        let mut drop = self.accounts.get(&public_key).ok_or(ContractError::KeyMissing)?;
+        ensure(env::predecessor_account_id() == drop.funder_id, || {
+            ContractError::Unauthorized("Only the funder can set the claim callback of the linkdrop")
+        })?;
+        ensure(receiver_id.is_some() == method.is_some(), || {
+            ContractError::InvalidArgument("The claim callback needs both a receiver and a method".to_string())
+        })?;
+        ensure(receiver_id.as_ref() != Some(&env::current_account_id()), || {
+            ContractError::InvalidArgument("The claim callback can't call this contract".to_string())
+        })?;
+        if let Some(method) = &method {
+            ensure(!method.is_empty(), || {
+                ContractError::InvalidArgument("The method of the claim callback can't be empty".to_string())
+            })?;
+            check_method_names(std::slice::from_ref(method))?;
+        }
+        drop.claim_callback_receiver = receiver_id;
+        drop.claim_callback_method = method;
+        self.accounts.insert(&public_key, &drop);
+        self.check_storage_covered()?;
+        Ok(())
+    }
+
+    /// Create `new_account_id` with `new_account_options` like `create_account_advanced` and fund a linkdrop
+    /// under `funded_key` like `send` in the same call, e.g. to onboard a user along with a drop they can pass
+    /// on. `funded_amount` of the attached deposit goes to the linkdrop, the rest to the new account. The
+    /// linkdrop stays funded if the creation fails, only the creation deposit is refunded. Options with
+    /// `contract_bytes` are refused.
+    #[payable]
+    #[handle_result]
+    pub fn create_account_and_fund_key(
+        &mut self,
+        new_account_id: AccountId,
+        new_account_options: CreateAccountOptions,
+        funded_key: PublicKey,
+        funded_amount: U128,
+    ) -> Result<Promise, ContractError> {
+        self.check_authorized()?;
+        self.check_not_paused()?;
+        self.check_deposit_within_limit()?;
+        let deposit = env::attached_deposit();
+        ensure(funded_amount.0 <= deposit, || {
+            ContractError::InsufficientDeposit(format!(
+                "Attached deposit must cover the funded amount of {} yoctoNEAR",
+                funded_amount.0
+            ))
+        })?;
+        ensure(new_account_options.contract_bytes.is_none(), || {
+            ContractError::InvalidArgument(
+                "Cannot deploy a contract with create_account_and_fund_key, use create_account_with_contract"
+                    .to_string(),
+            )
+        })?;
+        let creation_deposit = deposit - funded_amount.0;
+        let new_account_options = self.with_default_keys(new_account_options);
+        self.check_valid_creation(&new_account_id, &new_account_options, creation_deposit)?;
+        check_prepaid_gas(&new_account_options)?;
+
+        drop(self.internal_send(funded_key, funded_amount.0, SendOptions::default())?);
+        Ok(self.internal_create_account(
+            new_account_id,
+            new_account_options,
+            creation_deposit,
+            env::predecessor_account_id(),
+            env::predecessor_account_id(),
+            None,
+            None,
+            None,
+        ))
+    }
+
+    /// Reclaim the balance of an unclaimed linkdrop, sending it to `recipient_id`. Only callable by the owner.
+    /// The tokens of the linkdrop, fungible or not, go back to the funder.
+    #[handle_result]
+    pub fn delete_key(&mut self, public_key: PublicKey, recipient_id: AccountId) -> Result<Promise, ContractError> {
+        self.check_owner_action()?;
+        self.internal_delete_key(public_key, recipient_id)
+    }
+
+    /// Refund an expired linkdrop to its funder. Only callable by the funder.
+    #[handle_result]
+    pub fn reclaim_expired(&mut self, public_key: PublicKey) -> Result<Promise, ContractError> {
+        let drop = self.accounts.get(&public_key).ok_or(ContractError::KeyMissing)?;
+        ensure(env::predecessor_account_id() == drop.funder_id, || {
+            ContractError::Unauthorized("Only the funder can reclaim the linkdrop")
+        })?;
+        ensure(drop.is_expired(), || ContractError::InvalidArgument("Linkdrop has not expired".to_string()))?;
+        Ok(self.internal_reclaim_expired(public_key, drop))
+    }
+
+    /// Refund the expired linkdrops among `keys` to their funders, freeing their storage. Keys that are missing or
+    /// not expired yet are skipped with a log. Only callable by the owner. Returns how many were refunded.
+    #[handle_result]
+    pub fn batch_reclaim_expired(&mut self, keys: Vec<PublicKey>) -> Result<u32, ContractError> {
+        self.check_owner_action()?;
+        self.internal_batch_reclaim_expired(keys)
+    }
+
+    /// Claim the linkdrop funded under the signer key to the existing `account_id`, with its `secret` if it was
+    /// funded with a secret hash. Only callable through the access key added to this contract for the linkdrop.
+    #[handle_result]
+    pub fn claim(&mut self, account_id: AccountId, secret: Option<String>) -> Result<Promise, ContractError> {
+        ensure(env::predecessor_account_id() == env::current_account_id(), || {
+            ContractError::Unauthorized("Claim only can come from this account")
+        })?;
+        self.check_not_paused()?;
+        // Take the balance out up front, the callback puts it back if the transfer fails
+        let public_key = env::signer_account_pk();
+        let (drop, last_use) = self.internal_take_claim(&public_key, secret)?;
+        let amount = drop.balance.0 - drop.ft_storage_reserve();
+
+        Ok(Promise::new(account_id.clone())
+            .transfer(amount)
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(claim_callback_gas(&drop))
+                    .on_account_created_and_claimed(account_id, public_key, drop, false, None, last_use, false)
+            ))
+    }
+
+    /// Claim the linkdrop funded under the signer key to the existing `account_id` like `claim`, adding the access
+    /// keys of `options` to it in the same receipt. Nothing is deployed, `contract_bytes` and `deploy_init` must be
+    /// left empty. The runtime only lets an account manage its own keys, so like `add_keys` this only succeeds when
+    /// this contract is allowed to act on `account_id`. Otherwise the whole receipt fails, the transfer included,
+    /// and the linkdrop is restored.
+    #[handle_result]
+    pub fn claim_with_keys(
+        &mut self,
+        account_id: AccountId,
+        options: CreateAccountOptions,
+        secret: Option<String>,
+    ) -> Result<Promise, ContractError> {
+        ensure(env::predecessor_account_id() == env::current_account_id(), || {
+            ContractError::Unauthorized("Claim only can come from this account")
+        })?;
+        self.check_not_paused()?;
+        ensure(options.contract_bytes.is_none() && options.deploy_init.is_none(), || {
+            ContractError::InvalidArgument("Cannot deploy a contract to an existing account".to_string())
+        })?;
+        ensure(
+            options.full_access_keys.is_some()
+                || options.limited_access_keys.is_some()
+                || options.templated_access_keys.is_some(),
+            || {
+                ContractError::InvalidArgument(
+                    "Cannot add keys with no options. Please specify either full access keys or limited access keys."
+                        .to_string(),
+                )
+            },
+        )?;
+        check_keys(&options)?;
+        // Take the balance out up front, the callback puts it back if the transfer or the keys fail
+        let public_key = env::signer_account_pk();
+        let (drop, last_use) = self.internal_take_claim(&public_key, secret)?;
+        let amount = drop.balance.0 - drop.ft_storage_reserve();
+
+        Ok(add_access_keys(Promise::new(account_id.clone()).transfer(amount), &options).then(
+            Self::ext(env::current_account_id())
+                .with_static_gas(claim_callback_gas(&drop))
+                .on_account_created_and_claimed(account_id, public_key, drop, false, None, last_use, false)
+        ))
+    }
+
+    /// Create new account and claim the linkdrop funded under the signer key to it, with its `secret` if it was
+    /// funded with a secret hash. Only callable through the access key added to this contract for the linkdrop.
+    /// A `beneficiary`, e.g. a referrer, gets the given share of the claim and the new account the rest.
+    #[handle_result]
+    pub fn create_account_and_claim(
+        &mut self,
+        new_account_id: AccountId,
+        new_public_key: PublicKey,
+        secret: Option<String>,
+        beneficiary: Option<(AccountId, U128)>,
+    ) -> Result<Promise, ContractError> {
+        ensure(env::predecessor_account_id() == env::current_account_id(), || {
+            ContractError::Unauthorized("Create account and claim only can come from this account")
+        })?;
+        self.internal_create_account_and_claim(env::signer_account_pk(), new_account_id, new_public_key, secret, beneficiary, false)
+    }
+
+    /// Create new account and claim the linkdrop funded under `public_key` to it, without the funding key
+    /// signing the transaction: `signature` is the ed25519 signature of
+    /// `"<contract_id>:<new_account_id>:<new_public_key>:<nonce>"` by the funding key, made off-chain so that
+    /// anyone, e.g. a relayer, can submit the claim. The `nonce` must be the current one of the key, see
+    /// `get_claim_nonce`, and only moves on once the claim succeeds. The `secret` of the linkdrop is still needed if
+    /// it was funded with a secret hash.
+    #[handle_result]
+    pub fn claim_with_signature(
+        &mut self,
+        public_key: PublicKey,
+        new_account_id: AccountId,
+        new_public_key: PublicKey,
+        signature: Base64VecU8,
+        secret: Option<String>,
+        nonce: U64,
+    ) -> Result<Promise, ContractError> {
+        let message = format!(
+            "{}:{}:{}:{}",
+            env::current_account_id(),
+            new_account_id,
+            String::from(&new_public_key),
+            nonce.0
+        );
+        ensure(verify_signature(&public_key, message.as_bytes(), &signature.0), || {
+            ContractError::Unauthorized("Invalid signature of the funding key")
+        })?;
+        let claim_nonce = self.get_claim_nonce(public_key.clone()).0;
+        ensure(nonce.0 == claim_nonce, || {
+            ContractError::InvalidArgument(format!(
+                "Stale nonce {} for the signed claim, the current nonce of the key is {}",
+                nonce.0, claim_nonce
+            ))
+        })?;
+        self.internal_create_account_and_claim(public_key, new_account_id, new_public_key, secret, None, true)
     }
 
-    /// Callback after executing `create_account` or `create_account_advanced`.
-    pub fn on_account_created(&mut self, predecessor_account_id: AccountId, amount: U128) -> bool {
+    /// Callback after executing `create_account` or `create_account_advanced`. When the creation failed, the
+    /// outcome is only returned once the refund went through. A successful creation is remembered under
+    /// `idempotency_key`, already namespaced by caller, if any, starts the cooldown of `creator_id`, records
+    /// the `debt` the new account owes to `creator_id`, if any, and refunds the `excess` of the deposit over the
+    /// target balance to `predecessor_account_id`. A failed creation refunds the `excess` along with `amount`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn on_account_created(
+        &mut self,
+        new_account_id: AccountId,
+        predecessor_account_id: AccountId,
+        creator_id: AccountId,
+        amount: U128,
+        idempotency_key: Option<String>,
+        debt: Option<U128>,
+        excess: Option<U128>,
+    ) -> PromiseOrValue<CreationOutcome> {
         assert_eq!(
             env::predecessor_account_id(),
             env::current_account_id(),
             "Callback can only be called from the contract"
         );
-        let creation_succeeded = is_promise_success();
-        if !creation_succeeded {
-            // In case of failure, send funds back.
-            Promise::new(predecessor_account_id).transfer(amount.into());
+        let success = is_promise_success();
+        self.creations_in_flight.remove(&new_account_id);
+        self.pending_refunds -= excess.map_or(0, |excess| excess.0);
+        self.internal_log_creation(&new_account_id, &predecessor_account_id, amount, success);
+        if success {
+            self.accounts_created += 1;
+            self.internal_reset_failures();
+            if self.per_account_cooldown > 0 {
+                self.last_creation_at.insert(&creator_id, &env::block_timestamp());
+            }
+            if let Some(debt) = debt {
+                self.internal_record_debt(&new_account_id, creator_id, debt.0);
+            }
+            if let Some(idempotency_key) = idempotency_key {
+                self.internal_record_creation(&idempotency_key, new_account_id.clone());
+            }
+            EventLog::new(EventLogVariant::AccountCreated(vec![AccountCreatedLog {
+                new_account_id: new_account_id.clone(),
+                amount,
+            }]))
+            .emit();
+            let excess = excess.map_or(0, |excess| excess.0);
+            if excess > 0 {
+                Promise::new(predecessor_account_id).transfer(excess);
+            }
+            PromiseOrValue::Value(CreationOutcome {
+                success: true,
+                new_account_id,
+                refunded: U128(excess),
+                gas_burnt: U64(env::used_gas().0),
+            })
+        } else {
+            // In case of failure, send funds back, unless the refund policy keeps them. Only the amount meant for
+            // the account tells whether it was enough.
+            let reason = creation_failure_reason(amount.0);
+            let amount = U128(amount.0 + excess.map_or(0, |excess| excess.0));
+            let refund_to = match &self.refund_policy {
+                RefundPolicy::RefundToPredecessor => predecessor_account_id.clone(),
+                RefundPolicy::RefundTo { account_id } => account_id.clone(),
+                RefundPolicy::Retain => {
+                    // Nothing is refunded, relayers still get the reason of the failure
+                    EventLog::new(EventLogVariant::CreationFailed(vec![CreationFailedLog {
+                        new_account_id: new_account_id.clone(),
+                        predecessor_account_id: predecessor_account_id.clone(),
+                        refund_to: None,
+                        refunded: U128(0),
+                        reason,
+                    }]))
+                    .emit();
+                    let outcome = self.internal_retain_refund(new_account_id, predecessor_account_id, amount);
+                    self.internal_count_failure(&creator_id);
+                    return PromiseOrValue::Value(outcome);
+                }
+            };
+            EventLog::new(EventLogVariant::CreationFailed(vec![CreationFailedLog {
+                new_account_id: new_account_id.clone(),
+                predecessor_account_id,
+                refund_to: Some(refund_to.clone()),
+                refunded: amount,
+                reason,
+            }]))
+            .emit();
+            self.internal_count_failure(&creator_id);
+            self.internal_refund_creation(new_account_id, refund_to, amount).into()
         }
-        creation_succeeded
     }
 
-    /// Callback after execution `create_account_and_claim`.
-    pub fn on_account_created_and_claimed(&mut self, amount: U128) -> bool {
+    /// Callback after probing `new_account_id` for `create_account_advanced_checked`: creates the account if the
+    /// probe failed as it doesn't exist, refunds the deposit otherwise.
+    pub fn on_account_checked(
+        &mut self,
+        new_account_id: AccountId,
+        options: CreateAccountOptions,
+        predecessor_account_id: AccountId,
+        deposit: U128,
+    ) -> PromiseOrValue<CreationOutcome> {
         assert_eq!(
             env::predecessor_account_id(),
             env::current_account_id(),
             "Callback can only be called from the contract"
         );
-        let creation_succeeded = is_promise_success();
-        if creation_succeeded {
-            Promise::new(env::current_account_id()).delete_key(env::signer_account_pk());
+        if is_promise_success() {
+            env::log_str(&format!("Account {} already exists", new_account_id));
+            EventLog::new(EventLogVariant::CreationFailed(vec![CreationFailedLog {
+                new_account_id: new_account_id.clone(),
+                predecessor_account_id: predecessor_account_id.clone(),
+                refund_to: Some(predecessor_account_id.clone()),
+                refunded: deposit,
+                reason: CreationFailureReason::AccountExists,
+            }]))
+            .emit();
+            self.internal_refund_creation(new_account_id, predecessor_account_id, deposit).into()
         } else {
-            // In case of failure, put the amount back.
-            self.accounts
-                .insert(&env::signer_account_pk(), &amount.into());
+            let creator_id = predecessor_account_id.clone();
+            self.internal_create_account(
+                new_account_id,
+                options,
+                deposit.0,
+                predecessor_account_id,
+                creator_id,
+                None,
+                None,
+                None,
+            )
+            .into()
         }
-        creation_succeeded
     }
 
-    /// Returns the balance associated with given key.
-    pub fn get_key_balance(&self, key: PublicKey) -> U128 {
-        self.accounts.get(&key.into()).expect("Key is missing").into()
+    /// Callback after the refund of a failed creation, passing its outcome through.
+    pub fn on_creation_refunded(&self, outcome: CreationOutcome) -> CreationOutcome {
+        assert_eq!(
+            env::predecessor_account_id(),
+            env::current_account_id(),
+            "Callback can only be called from the contract"
+        );
+        outcome
     }
 
-    /// Returns information associated with a given key.
-    /// Part of the linkdrop NEP
-    #[handle_result]
-    pub fn get_key_information(&self, key: PublicKey) -> Result<KeyInfo, &'static str> {
-        match self.accounts.get(&key) {
-            Some(balance) => Ok(KeyInfo { balance: U128(balance) }),
-            None => Err("Key is missing"),
+    /// Callback after `add_keys`, logging whether the keys were added to `account_id`.
+    pub fn on_keys_added(&self, account_id: AccountId) -> bool {
+        assert_eq!(
+            env::predecessor_account_id(),
+            env::current_account_id(),
+            "Callback can only be called from the contract"
+        );
+        let keys_added = is_promise_success();
+        if keys_added {
+            env::log_str(&format!("Added keys to {}", account_id));
+        } else {
+            env::log_str(&format!("Failed to add keys to {}", account_id));
         }
+        keys_added
     }
-}
-
-#[cfg(not(target_arch = "wasm32"))]
-#[cfg(test)]
-mod tests {
-    use near_sdk::test_utils::{VMContextBuilder};
-    use near_sdk::{testing_env};
-
-    use super::*;
 
-    fn linkdrop() -> AccountId {
+    /// Callback after `change_key`, logging whether the key of `account_id` was changed.
+    pub fn on_key_changed(&self, account_id: AccountId) -> bool {
+        assert_eq!(
+            env::predecessor_account_id(),
+            env::current_account_id(),
+            "Callback can only be called from the contract"
+        );
+        let key_changed = is_promise_success();
+        if key_changed {
+            env::log_str(&format!("Changed the key of {}", account_id));
+        } else {
+            env::log_str(&format!("Failed to change the key of {}", account_id));
+        }
+        key_changed
+    }
+
+    /// Callback after the creation of `create_account_and_resolve` settled, resolving to whether the account was
+    /// created. A creation whose outcome can't be read, e.g. as its callback ran out of gas, resolves to `false`.
+    pub fn create_account_resolve(&self) -> PromiseOrValue<bool> {
+        assert_eq!(
+            env::predecessor_account_id(),
+            env::current_account_id(),
+            "Callback can only be called from the contract"
+        );
+        let created = match env::promise_result(0) {
+            PromiseResult::Successful(value) => {
+                serde_json::from_slice::<CreationOutcome>(&value).is_ok_and(|outcome| outcome.success)
+            }
+            _ => false,
+        };
+        PromiseOrValue::Value(created)
+    }
+
+    /// Callback after all the creations of `create_accounts`, returning the outcome of each `on_account_created`.
+    pub fn on_accounts_created(&self) -> Vec<Option<CreationOutcome>> {
+        assert_eq!(
+            env::predecessor_account_id(),
+            env::current_account_id(),
+            "Callback can only be called from the contract"
+        );
+        (0..env::promise_results_count())
+            .map(|index| match env::promise_result(index) {
+                PromiseResult::Successful(value) => serde_json::from_slice(&value).ok(),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Callback after execution `create_account_and_claim` or `claim`, with the drop taken out of `accounts` for
+    /// the claim. The tokens of an FT drop are only sent once the balance was claimed. `account_created` tells
+    /// the claims creating `new_account_id` apart from the ones to an existing account. The `beneficiary`
+    /// share of the claim is only sent once the account was created, so that a failed claim restores it all.
+    /// `last_use` tells whether the claim took the last use of the linkdrop, as the key may have been claimed
+    /// again or funded anew while the claim was in flight. A successful `signed_claim`, made through
+    /// `claim_with_signature`, moves the claim nonce of the key on.
+    #[allow(clippy::too_many_arguments)]
+    pub fn on_account_created_and_claimed(
+        &mut self,
+        new_account_id: AccountId,
+        public_key: PublicKey,
+        drop: DropInfo,
+        account_created: bool,
+        beneficiary: Option<(AccountId, U128)>,
+        last_use: bool,
+        signed_claim: bool,
+    ) -> bool {
+        assert_eq!(
+            env::predecessor_account_id(),
+            env::current_account_id(),
+            "Callback can only be called from the contract"
+        );
+        let creation_succeeded = is_promise_success();
+        if creation_succeeded && account_created {
+            self.accounts_created += 1;
+        }
+        if creation_succeeded && signed_claim {
+            // A failed claim leaves the signature usable. Replaying it can't claim twice, as it names the account
+            // to create and only one creation of it can succeed.
+            let claim_nonce = self.claim_nonces.get(&public_key).unwrap_or(0);
+            self.claim_nonces.insert(&public_key, &(claim_nonce + 1));
+        }
+        if let Some((beneficiary_id, amount)) = beneficiary.filter(|_| creation_succeeded) {
+            Promise::new(beneficiary_id).transfer(amount.0);
+        }
+        if !creation_succeeded {
+            // In case of failure, put the drop back, on top of anything funded since.
+            self.internal_restore_drop(public_key, drop);
+        } else if drop.ft.is_some() {
+            // The key is only done with once the tokens are sent as well
+            let storage_deposit = drop.ft_storage_reserve();
+            self.internal_claim_ft(new_account_id, public_key, drop, storage_deposit);
+        } else if drop.nft.is_some() {
+            self.internal_claim_nft(new_account_id, public_key, drop);
+        } else {
+            self.internal_finish_claim(new_account_id, public_key, &drop, last_use);
+        }
+        creation_succeeded
+    }
+
+    /// Returns whether this contract started creating `account_id` and its `on_account_created` callback didn't
+    /// settle it yet. Creating the account again is refused meanwhile.
+    pub fn is_creation_in_flight(&self, account_id: AccountId) -> bool {
+        self.creations_in_flight.contains_key(&account_id)
+    }
+
+    /// Returns the semantic version of the contract, `CONTRACT_VERSION`.
+    pub fn get_version(&self) -> String {
+        CONTRACT_VERSION.to_string()
+    }
+
+    /// Returns how many accounts this contract successfully created, with or without a linkdrop.
+    pub fn get_total_accounts_created(&self) -> u64 {
+        self.accounts_created
+    }
+
+    /// Returns the nonce the next `claim_with_signature` of the funding key `key` must sign.
+    pub fn get_claim_nonce(&self, key: PublicKey) -> U64 {
+        U64(self.claim_nonces.get(&key).unwrap_or(0))
+    }
+
+    /// Returns the balance associated with given key, if any.
+    pub fn get_key_balance(&self, key: PublicKey) -> Option<U128> {
+        self.accounts.get(&key).map(|drop| drop.balance)
+    }
+
+    /// Returns the balance associated with given key, panicking with "Key is missing" like the linkdrop NEP.
+    pub fn get_key_balance_or_panic(&self, key: PublicKey) -> U128 {
+        self.get_key_balance(key).expect("Key is missing")
+    }
+
+    /// Returns information associated with a given key.
+    /// Part of the linkdrop NEP
+    #[handle_result]
+    pub fn get_key_information(&self, key: PublicKey) -> Result<KeyInfo, &'static str> {
+        match self.accounts.get(&key) {
+            Some(drop) => Ok(KeyInfo::new(key, drop)),
+            None => Err("Key is missing"),
+        }
+    }
+
+    /// Returns the information of each of `keys`, in the same order, with `None` for missing keys.
+    /// At most `MAX_KEYS_LIMIT` keys can be requested at once.
+    #[handle_result]
+    pub fn get_keys_info(&self, keys: Vec<PublicKey>) -> Result<Vec<Option<KeyInfo>>, ContractError> {
+        ensure(keys.len() as u64 <= MAX_KEYS_LIMIT, || {
+            ContractError::InvalidArgument(format!("At most {} keys can be requested at once", MAX_KEYS_LIMIT))
+        })?;
+        Ok(keys
+            .into_iter()
+            .map(|key| self.accounts.get(&key).map(|drop| KeyInfo::new(key, drop)))
+            .collect())
+    }
+
+    /// Returns whether a linkdrop is funded under the given key, without failing on missing keys.
+    pub fn get_key_status(&self, key: PublicKey) -> KeyStatus {
+        match self.accounts.get(&key) {
+            Some(drop) => KeyStatus::Funded { balance: drop.balance },
+            None => KeyStatus::NotFound,
+        }
+    }
+
+    /// Returns the number of funded keys.
+    pub fn get_key_total_supply(&self) -> u64 {
+        self.accounts.len()
+    }
+
+    /// Returns the sum of the balances of all the funded keys.
+    pub fn get_total_locked_balance(&self) -> U128 {
+        U128(self.total_locked)
+    }
+
+    /// Runs the checks `create_account_advanced` makes on `options`, returning the first error rather than
+    /// panicking so that frontends can show it before sending the transaction.
+    #[handle_result]
+    pub fn validate_options(&self, options: CreateAccountOptions) -> Result<(), String> {
+        self.check_options(&options).map_err(|error| error.to_string())
+    }
+
+    /// Returns the minimum deposit to attach to `create_account_advanced` with `options`: the creation fees plus
+    /// the storage of the new account, its keys and its contract, and the gas to attach along with it. Nothing
+    /// is left for the new account while `fee_bps` is 100%, the deposit is then `Balance::MAX`.
+    pub fn get_account_creation_cost(&self, options: CreateAccountOptions) -> AccountCreationCost {
+        let net = (account_storage_bytes(&options) as Balance * env::storage_byte_cost()).max(self.min_balance_of(&options));
+        // The percentage fee is taken out of the amount covering `net`, rounded down
+        let kept_bps = (MAX_FEE_BPS - self.fee_bps) as Balance;
+        let gross = match kept_bps {
+            0 => Balance::MAX - self.creation_fee,
+            _ => (net * MAX_FEE_BPS as Balance).div_ceil(kept_bps),
+        };
+        AccountCreationCost {
+            deposit: U128(self.creation_fee + gross),
+            gas: U64(estimate_creation_gas(&options).0),
+        }
+    }
+
+    /// Returns whether `create_account_and_claim` with the linkdrop funded under `key` can create and fund
+    /// `new_account_id`, and the shortfall if its next claim doesn't cover the storage of the new account. Claims
+    /// don't pay the creation fee. A missing, expired or exhausted linkdrop, or an account this contract can't
+    /// create, is reported as the `reason` the claim would fail.
+    pub fn can_claim(&self, key: PublicKey, new_account_id: AccountId) -> ClaimFeasibility {
+        let options = CreateAccountOptions {
+            full_access_keys: Some(vec![key.clone()]),
+            full_access_key_nonce: None,
+            limited_access_keys: None,
+            templated_access_keys: None,
+            contract_bytes: None,
+            deploy_init: None,
+            stake: None,
+        };
+        let required =
+            (account_storage_bytes(&options) as Balance * env::storage_byte_cost()).max(self.min_balance_of(&options));
+        match self.check_claimable(&key, &new_account_id) {
+            Ok((claimable, _)) => ClaimFeasibility {
+                feasible: claimable >= required,
+                claimable: U128(claimable),
+                required: U128(required),
+                shortfall: U128(required.saturating_sub(claimable)),
+                reason: None,
+            },
+            Err(error) => ClaimFeasibility {
+                feasible: false,
+                claimable: U128(0),
+                required: U128(required),
+                shortfall: U128(0),
+                reason: Some(error.to_string()),
+            },
+        }
+    }
+
+    /// Returns what `create_account_and_claim` with the linkdrop funded under `key` and `beneficiary` would send
+    /// to `new_account_id` right now, after the share of the `beneficiary` and the storage kept to register it with
+    /// the token contract of an FT drop. Claims don't pay the creation fees, and the part of a multi-use or vesting
+    /// linkdrop a claim sends is the one of its next use. An invalid claim, e.g. of an expired linkdrop, is
+    /// reported with the `reason` it would fail. The secret isn't checked.
+    pub fn simulate_claim(
+        &self,
+        key: PublicKey,
+        new_account_id: AccountId,
+        beneficiary: Option<(AccountId, U128)>,
+    ) -> ClaimSimulation {
+        let beneficiary_amount = beneficiary.map_or(0, |(_, amount)| amount.0);
+        let simulation = self.check_claimable(&key, &new_account_id).and_then(|(claimable, reserve)| {
+            ensure(beneficiary_amount <= claimable, || {
+                ContractError::InvalidArgument(format!(
+                    "The beneficiary amount of {} yoctoNEAR exceeds the claimed balance of {} yoctoNEAR",
+                    beneficiary_amount, claimable
+                ))
+            })?;
+            Ok((claimable, reserve))
+        });
+        match simulation {
+            Ok((claimable, reserve)) => ClaimSimulation {
+                valid: true,
+                amount: U128(claimable - beneficiary_amount),
+                beneficiary_amount: U128(beneficiary_amount),
+                ft_storage_reserve: U128(reserve),
+                reason: None,
+            },
+            Err(error) => ClaimSimulation {
+                valid: false,
+                amount: U128(0),
+                beneficiary_amount: U128(0),
+                ft_storage_reserve: U128(0),
+                reason: Some(error.to_string()),
+            },
+        }
+    }
+
+    /// Returns whether this contract is allowed to create `new_account_id`: a direct subaccount of it, or a
+    /// top-level account when deployed at the registrar, whose leftmost label isn't reserved. The deposit,
+    /// cooldown and creations in flight aren't checked.
+    pub fn can_create(&self, new_account_id: AccountId) -> bool {
+        check_can_create_account(&new_account_id).and_then(|_| self.check_not_reserved(&new_account_id)).is_ok()
+    }
+
+    /// Returns the operational state of this deployment in one call, for monitoring.
+    pub fn health_check(&self) -> ContractHealth {
+        ContractHealth {
+            paused: self.paused,
+            total_locked: U128(self.total_locked),
+            accounts_created: self.accounts_created,
+            storage_usage: self.get_storage_usage(),
+            available_balance: self.get_available_balance(),
+            consecutive_failures: self.consecutive_failures,
+        }
+    }
+
+    /// Returns what this deployment supports, along with its current limits and fee.
+    pub fn get_contract_features(&self) -> ContractFeatures {
+        ContractFeatures {
+            ft_drops: true,
+            nft_drops: true,
+            secrets: true,
+            expiration: true,
+            multi_use: true,
+            signed_claims: true,
+            vesting: true,
+            max_keys_per_account: MAX_KEYS_PER_ACCOUNT,
+            creation_fee: U128(self.creation_fee),
+            fee_bps: self.fee_bps,
+            max_contract_size: U64(self.max_contract_size),
+        }
+    }
+
+    /// Describe what `create_account_advanced` would schedule to create `new_account_id` with `options`, without
+    /// validating them: see `validate_options`. The account isn't created if it is this contract, views can't
+    /// tell whether it is the predecessor of the future call.
+    pub fn describe_creation_plan(&self, new_account_id: AccountId, options: CreateAccountOptions) -> CreationPlan {
+        CreationPlan {
+            creates_account: new_account_id != env::current_account_id(),
+            full_access_keys: options.full_access_keys.as_ref().map_or(0, |keys| keys.len() as u32),
+            limited_access_keys: options
+                .limited_keys()
+                .into_iter()
+                .map(|key_info| PlannedAccessKey {
+                    public_key: key_info.public_key,
+                    allowance: key_info.allowance,
+                    receiver_id: key_info.receiver_id,
+                    method_names: key_info
+                        .method_names
+                        .split(',')
+                        .filter(|name| !name.is_empty())
+                        .map(str::to_string)
+                        .collect(),
+                })
+                .collect(),
+            contract_hash: options.contract_bytes.map(|bytes| Base58CryptoHash::from(env::sha256_array(&bytes))),
+            init_method: options.deploy_init.map(|init| init.method),
+        }
+    }
+
+    /// Paginate through the keys funded by `funder_id`, with the same defaults as `get_keys`.
+    pub fn get_drops_by_funder(
+        &self,
+        funder_id: AccountId,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<KeyInfo> {
+        let drops = match self.drops_by_funder.get(&funder_id) {
+            Some(drops) => drops,
+            None => return vec![],
+        };
+        let from_index = from_index.unwrap_or(0);
+        let limit = limit.unwrap_or(DEFAULT_KEYS_LIMIT).min(MAX_KEYS_LIMIT);
+
+        drops
+            .iter()
+            .skip(from_index as usize)
+            .take(limit as usize)
+            .filter_map(|public_key| {
+                let drop = self.accounts.get(&public_key)?;
+                Some(KeyInfo::new(public_key, drop))
+            })
+            .collect()
+    }
+
+    /// Paginate through the funded keys, starting at `from_index` (0 by default) and returning
+    /// at most `limit` keys (`DEFAULT_KEYS_LIMIT` by default, capped at `MAX_KEYS_LIMIT`).
+    pub fn get_keys(&self, from_index: Option<u64>, limit: Option<u64>) -> Vec<KeyInfo> {
+        let from_index = from_index.unwrap_or(0);
+        let limit = limit.unwrap_or(DEFAULT_KEYS_LIMIT).min(MAX_KEYS_LIMIT);
+
+        // Read the page by index, rather than iterating over every key before it
+        let keys = self.accounts.keys_as_vector();
+        let values = self.accounts.values_as_vector();
+        (from_index..keys.len().min(from_index.saturating_add(limit)))
+            .filter_map(|index| Some(KeyInfo::new(keys.get(index)?, values.get(index)?)))
+            .collect()
+    }
+}
+
+impl LinkDrop {
+    /// Put a drop taken out for a claim back under `public_key`, on top of anything funded since.
+    pub(crate) fn internal_restore_drop(&mut self, public_key: PublicKey, drop: DropInfo) {
+        let amount = drop.balance.0;
+        let restored = match self.accounts.get(&public_key) {
+            Some(mut restored) => {
+                // A vesting linkdrop claimed in part kept its use, only the claimed amount goes back. The key may
+                // have been funded anew meanwhile, with less claimed or no vesting at all.
+                if drop.uses_remaining == 0 && restored.vesting.is_some() {
+                    restored.claimed.0 = restored.claimed.0.saturating_sub(amount);
+                }
+                restored.uses_remaining += drop.uses_remaining;
+                restored.add_balance(amount);
+                let holds_nft = restored.nft.is_some();
+                match (&mut restored.ft, drop.ft) {
+                    (Some(ft), Some(dropped)) if ft.contract_id == dropped.contract_id => ft.amount.0 += dropped.amount.0,
+                    (ft @ None, dropped) if !holds_nft => *ft = dropped,
+                    (_, Some(dropped)) => {
+                        internal_refund_ft(&dropped, &drop.funder_id);
+                    }
+                    (_, None) => {}
+                }
+                // The linkdrop funded since may already hold tokens
+                if let Some(dropped) = drop.nft {
+                    if restored.ft.is_none() && restored.nft.is_none() {
+                        restored.nft = Some(dropped);
+                    } else {
+                        internal_refund_nft(&dropped, &drop.funder_id);
+                    }
+                }
+                restored
+            }
+            None => {
+                self.internal_index_drop(&drop.funder_id, &public_key);
+                let mut restored = DropInfo { balance: U128(0), ..drop };
+                restored.add_balance(amount);
+                restored
+            }
+        };
+        self.accounts.insert(&public_key, &restored);
+        self.total_locked += amount;
+    }
+
+    /// Remove the linkdrop funded under `public_key` and its key, sending its balance to `recipient_id` and its
+    /// tokens, fungible or not, back to the funder.
+    pub(crate) fn internal_delete_key(
+        &mut self,
+        public_key: PublicKey,
+        recipient_id: AccountId,
+    ) -> Result<Promise, ContractError> {
+        let drop = self.accounts.remove(&public_key).ok_or(ContractError::KeyMissing)?;
+        self.internal_unindex_drop(&drop.funder_id, &public_key);
+        let balance = drop.balance.0;
+        self.total_locked -= balance;
+        self.internal_release_drop_storage(&drop.funder_id);
+        if let Some(category) = &drop.category {
+            self.internal_refund_category(category, balance);
+        }
+        if let Some(ft) = &drop.ft {
+            internal_refund_ft(ft, &drop.funder_id);
+        }
+        if let Some(nft) = &drop.nft {
+            internal_refund_nft(nft, &drop.funder_id);
+        }
+
+        // The key can't claim anything anymore
+        Promise::new(env::current_account_id()).delete_key(public_key);
+        Ok(Promise::new(recipient_id).transfer(balance))
+    }
+
+    /// Record `public_key` among the linkdrops of `funder_id` in `drops_by_funder`.
+    pub(crate) fn internal_index_drop(&mut self, funder_id: &AccountId, public_key: &PublicKey) {
+        let mut drops = self.drops_by_funder.get(funder_id).unwrap_or_else(|| {
+            let mut prefix = b"fk".to_vec();
+            prefix.extend(env::sha256(funder_id.as_bytes()));
+            UnorderedSet::new(prefix)
+        });
+        drops.insert(public_key);
+        self.drops_by_funder.insert(funder_id, &drops);
+    }
+
+    /// Remove `public_key` from the linkdrops of `funder_id` in `drops_by_funder`.
+    pub(crate) fn internal_unindex_drop(&mut self, funder_id: &AccountId, public_key: &PublicKey) {
+        if let Some(mut drops) = self.drops_by_funder.get(funder_id) {
+            drops.remove(public_key);
+            if drops.is_empty() {
+                self.drops_by_funder.remove(funder_id);
+            } else {
+                self.drops_by_funder.insert(funder_id, &drops);
+            }
+        }
+    }
+
+    /// Refund the expired linkdrops among `keys` to their funders, see `batch_reclaim_expired`.
+    pub(crate) fn internal_batch_reclaim_expired(&mut self, keys: Vec<PublicKey>) -> Result<u32, ContractError> {
+        ensure(keys.len() as u64 <= MAX_RECLAIM_BATCH, || {
+            ContractError::InvalidArgument(format!("Cannot reclaim more than {} linkdrops at once", MAX_RECLAIM_BATCH))
+        })?;
+        let mut reclaimed = 0;
+        for public_key in keys {
+            match self.accounts.get(&public_key) {
+                Some(expired) if expired.is_expired() => {
+                    env::log_str(&format!("Refunded {} to {}", String::from(&public_key), expired.funder_id));
+                    drop(self.internal_reclaim_expired(public_key, expired));
+                    reclaimed += 1;
+                }
+                Some(_) => env::log_str(&format!("Skipped {}, not expired", String::from(&public_key))),
+                None => env::log_str(&format!("Skipped {}, missing", String::from(&public_key))),
+            }
+        }
+        Ok(reclaimed)
+    }
+
+    /// Remove the expired linkdrop `drop` funded under `public_key` and its key, refunding its balance and tokens
+    /// to the funder.
+    fn internal_reclaim_expired(&mut self, public_key: PublicKey, drop: DropInfo) -> Promise {
+        self.accounts.remove(&public_key);
+        self.internal_unindex_drop(&drop.funder_id, &public_key);
+        self.total_locked -= drop.balance.0;
+        self.internal_release_drop_storage(&drop.funder_id);
+        if let Some(category) = &drop.category {
+            self.internal_refund_category(category, drop.balance.0);
+        }
+        if let Some(ft) = &drop.ft {
+            internal_refund_ft(ft, &drop.funder_id);
+        }
+        if let Some(nft) = &drop.nft {
+            internal_refund_nft(nft, &drop.funder_id);
+        }
+
+        Promise::new(env::current_account_id()).delete_key(public_key);
+        Promise::new(drop.funder_id).transfer(drop.balance.0)
+    }
+
+    /// Fund a linkdrop under `public_key` with `deposit` from the predecessor, see `send`.
+    fn internal_send(
+        &mut self,
+        public_key: PublicKey,
+        deposit: Balance,
+        options: SendOptions,
+    ) -> Result<Promise, ContractError> {
+        let SendOptions { expires_at, metadata, uses, secret_hash, min_claim_interval, vesting, category } = options;
+        check_public_key(&public_key)?;
+        self.check_not_claimed(&public_key)?;
+        let existing = self.accounts.get(&public_key);
+        let uses = if existing.is_some() { 1 } else { uses.unwrap_or(1) };
+        ensure(uses > 0, || ContractError::InvalidArgument("A linkdrop needs at least one use".to_string()))?;
+        let min_deposit = MIN_SEND_DEPOSIT * uses as Balance;
+        ensure(deposit >= min_deposit, || {
+            ContractError::InsufficientDeposit(format!("Attached deposit must be at least {} yoctoNEAR", min_deposit))
+        })?;
+
+        let funder_id = env::predecessor_account_id();
+        let allowance = ACCESS_KEY_ALLOWANCE * uses as Balance;
+        let amount = deposit - allowance;
+        if let Some(expires_at) = expires_at {
+            ensure(expires_at.0 > env::block_timestamp(), || {
+                ContractError::InvalidArgument("Expiration must be in the future".to_string())
+            })?;
+        }
+        if let Some(metadata) = &metadata {
+            ensure(metadata.len() <= MAX_METADATA_BYTES, || {
+                ContractError::InvalidArgument(format!(
+                    "Metadata is {} bytes long, at most {} bytes are allowed",
+                    metadata.len(),
+                    MAX_METADATA_BYTES
+                ))
+            })?;
+        }
+        if let Some(secret_hash) = &secret_hash {
+            ensure(secret_hash.0.len() == 32, || {
+                ContractError::InvalidArgument("The secret hash must be a sha256 hash".to_string())
+            })?;
+        }
+        if let Some(vesting) = &vesting {
+            vesting.check_valid()?;
+        }
+        ensure(self.total_locked + amount <= self.max_total_locked, || {
+            ContractError::InvalidArgument(format!(
+                "Funding the linkdrop would lock more than the maximum of {} yoctoNEAR",
+                self.max_total_locked
+            ))
+        })?;
+        if let Some(drop) = &existing {
+            ensure(drop.funder_id == funder_id, || {
+                ContractError::Unauthorized("Key is already funded by another account")
+            })?;
+        }
+        let category = existing.as_ref().map_or(category, |drop| drop.category.clone());
+        if let Some(category) = &category {
+            self.internal_spend_category(category, amount)?;
+        }
+        let mut drop = match existing {
+            Some(drop) => drop,
+            None => {
+                self.internal_charge_drop_storage(&funder_id)?;
+                self.internal_index_drop(&funder_id, &public_key);
+                DropInfo {
+                    balance: U128(0),
+                    funder_id: funder_id.clone(),
+                    expires_at,
+                    ft: None,
+                    nft: None,
+                    metadata,
+                    secret_hash: secret_hash.map(|secret_hash| secret_hash.0),
+                    uses_remaining: uses,
+                    amount_per_use: U128(0),
+                    last_claim_timestamp: 0,
+                    min_claim_interval: min_claim_interval.map_or(0, |interval| interval.0),
+                    vesting,
+                    claimed: U128(0),
+                    category,
+                    claim_callback_receiver: None,
+                    claim_callback_method: None,
+                }
+            }
+        };
+        drop.add_balance(amount);
+        self.accounts.insert(&public_key, &drop);
+        self.total_locked += amount;
+        self.internal_draw_storage_reserve();
+        self.check_storage_covered()?;
+
+        // Let the key sign the claims on this contract
+        Ok(Promise::new(env::current_account_id()).add_access_key(
+            public_key,
+            allowance,
+            env::current_account_id(),
+            ACCESS_KEY_METHOD_NAMES.to_string(),
+        ))
+    }
+
+    /// Create `new_account_id` with the full access key `new_public_key` and send it the next use of the
+    /// linkdrop funded under `public_key`, less the share of the `beneficiary` if any. The caller is responsible
+    /// for authorizing the claim.
+    fn internal_create_account_and_claim(
+        &mut self,
+        public_key: PublicKey,
+        new_account_id: AccountId,
+        new_public_key: PublicKey,
+        secret: Option<String>,
+        beneficiary: Option<(AccountId, U128)>,
+        signed_claim: bool,
+    ) -> Result<Promise, ContractError> {
+        self.check_not_paused()?;
+        // Take the balance out up front, the callback puts it back if the creation fails
+        let (drop, last_use) = self.internal_take_claim(&public_key, secret)?;
+        let amount = drop.balance.0 - drop.ft_storage_reserve();
+        let beneficiary_amount = beneficiary.as_ref().map_or(0, |(_, amount)| amount.0);
+        ensure(beneficiary_amount <= amount, || {
+            ContractError::InvalidArgument(format!(
+                "The beneficiary amount of {} yoctoNEAR exceeds the claimed balance of {} yoctoNEAR",
+                beneficiary_amount, amount
+            ))
+        })?;
+        check_can_create_account(&new_account_id)?;
+        self.check_not_reserved(&new_account_id)?;
+
+        Ok(Promise::new(new_account_id.clone())
+            .create_account()
+            .add_full_access_key(new_public_key)
+            .transfer(amount - beneficiary_amount)
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(claim_callback_gas(&drop))
+                    .on_account_created_and_claimed(new_account_id, public_key, drop, true, beneficiary, last_use, signed_claim)
+            ))
+    }
+
+    /// Take the next use of the linkdrop funded under `public_key` out of `accounts` for a claim, removing the
+    /// linkdrop along with its last use. Returns the claimed use, which the claim callback puts back on failure,
+    /// and whether it was the last one. Checks `secret` against the secret hash of the linkdrop, if any. A use of
+    /// a vesting linkdrop only takes the part vested so far, and isn't the last one until the whole balance is.
+    pub(crate) fn internal_take_claim(
+        &mut self,
+        public_key: &PublicKey,
+        secret: Option<String>,
+    ) -> Result<(DropInfo, bool), ContractError> {
+        let mut drop = self.accounts.get(public_key).ok_or(ContractError::KeyMissing)?;
+        ensure(!drop.is_expired(), || ContractError::InvalidArgument("Linkdrop has expired".to_string()))?;
+        if let Some(secret_hash) = &drop.secret_hash {
+            let secret =
+                secret.ok_or(ContractError::Unauthorized("The linkdrop can only be claimed with its secret"))?;
+            ensure(&env::sha256(secret.as_bytes()) == secret_hash, || {
+                ContractError::Unauthorized("Wrong secret for the linkdrop")
+            })?;
+        }
+        ensure(drop.uses_remaining > 0, || ContractError::InvalidArgument("Linkdrop has no uses left".to_string()))?;
+        ensure(!drop.is_rate_limited(), || {
+            ContractError::InvalidArgument(format!(
+                "Linkdrop was claimed less than {} nanoseconds ago",
+                drop.min_claim_interval
+            ))
+        })?;
+        if let Some(vesting) = &drop.vesting {
+            ensure(!vesting.is_before_cliff(), || {
+                ContractError::InvalidArgument(format!("Linkdrop is vesting until its cliff at {}", vesting.cliff.0))
+            })?;
+        }
+        let amount = drop.claim_amount();
+        ensure(amount > 0 || drop.vesting.is_none(), || {
+            ContractError::InvalidArgument("Nothing has vested since the last claim".to_string())
+        })?;
+        self.total_locked -= amount;
+        // A vesting linkdrop keeps its use until the whole balance is vested
+        let vested_in_part = drop.vesting.is_some() && amount < drop.balance.0;
+        if !vested_in_part {
+            drop.uses_remaining -= 1;
+        }
+        // Tokens go with the last use
+        let claimed = DropInfo {
+            balance: U128(amount),
+            uses_remaining: if vested_in_part { 0 } else { 1 },
+            amount_per_use: U128(amount),
+            ft: drop.ft.clone().filter(|_| drop.uses_remaining == 0),
+            nft: drop.nft.clone().filter(|_| drop.uses_remaining == 0),
+            ..drop.clone()
+        };
+        let last_use = drop.uses_remaining == 0;
+        if last_use {
+            self.accounts.remove(public_key);
+            self.internal_unindex_drop(&drop.funder_id, public_key);
+        } else {
+            drop.balance.0 -= amount;
+            if drop.vesting.is_some() {
+                drop.claimed.0 += amount;
+            }
+            self.accounts.insert(public_key, &drop);
+        }
+        Ok((claimed, last_use))
+    }
+
+    /// Wrap up a successful claim of `drop`, the use claimed of the drop funded under `public_key`, emit the
+    /// event and notify its claim callback, if any. Once the `last_use` is claimed, release its storage, delete
+    /// the key and add it to `claimed_keys`, otherwise start the claim interval. Whatever is under the key now
+    /// was funded after the claim took the last use, and keeps the key.
+    pub(crate) fn internal_finish_claim(
+        &mut self,
+        account_id: AccountId,
+        public_key: PublicKey,
+        drop: &DropInfo,
+        last_use: bool,
+    ) {
+        if let Some((receiver_id, method)) = drop.claim_callback() {
+            // A separate promise, its failure doesn't affect the claim
+            let args = serde_json::json!({
+                "public_key": public_key,
+                "account_id": account_id,
+                "funder_id": drop.funder_id,
+                "amount": drop.balance,
+                "metadata": drop.metadata,
+            });
+            Promise::new(receiver_id).function_call(method, args.to_string().into_bytes(), 0, CLAIM_NOTIFICATION_GAS);
+        }
+        if last_use {
+            self.internal_release_drop_storage(&drop.funder_id);
+            if self.accounts.get(&public_key).is_none() {
+                Promise::new(env::current_account_id()).delete_key(public_key.clone());
+                self.claimed_keys.insert(&public_key);
+            }
+        } else if let Some(mut drop) = self.accounts.get(&public_key) {
+            drop.last_claim_timestamp = env::block_timestamp();
+            self.accounts.insert(&public_key, &drop);
+        }
+        EventLog::new(EventLogVariant::LinkdropClaimed(vec![LinkdropClaimedLog {
+            account_id,
+            public_key,
+        }]))
+        .emit();
+    }
+
+    /// Fails if the last use of the linkdrop funded under `public_key` was claimed, see `claimed_keys`.
+    fn check_not_claimed(&self, public_key: &PublicKey) -> Result<(), ContractError> {
+        ensure(!self.claimed_keys.contains(public_key), || {
+            ContractError::InvalidArgument("Key was already claimed, the owner must clear its tombstone".to_string())
+        })
+    }
+
+    /// The checks of `internal_create_account_and_claim` without taking the claim, returning the yoctoNEAR$ the
+    /// next claim of the linkdrop funded under `public_key` would send to `new_account_id`, and those it would keep
+    /// to register it with the token contract of an FT drop. The secret isn't checked.
+    fn check_claimable(
+        &self,
+        public_key: &PublicKey,
+        new_account_id: &AccountId,
+    ) -> Result<(Balance, Balance), ContractError> {
+        self.check_not_paused()?;
+        let drop = self.accounts.get(public_key).ok_or(ContractError::KeyMissing)?;
+        ensure(!drop.is_expired(), || ContractError::InvalidArgument("Linkdrop has expired".to_string()))?;
+        ensure(drop.uses_remaining > 0, || ContractError::InvalidArgument("Linkdrop has no uses left".to_string()))?;
+        ensure(!drop.is_rate_limited(), || {
+            ContractError::InvalidArgument(format!(
+                "Linkdrop was claimed less than {} nanoseconds ago",
+                drop.min_claim_interval
+            ))
+        })?;
+        if let Some(vesting) = &drop.vesting {
+            ensure(!vesting.is_before_cliff(), || {
+                ContractError::InvalidArgument(format!("Linkdrop is vesting until its cliff at {}", vesting.cliff.0))
+            })?;
+        }
+        check_can_create_account(new_account_id)?;
+        self.check_not_reserved(new_account_id)?;
+        // The tokens, and the storage reserved for them, only go with the last use
+        let amount = drop.claim_amount();
+        let reserve = if drop.uses_remaining == 1 { drop.ft_storage_reserve().min(amount) } else { 0 };
+        Ok((amount - reserve, reserve))
+    }
+
+    /// Fails if creating `new_account_id` with `options` and `deposit` is bound to fail.
+    fn check_valid_creation(
+        &self,
+        new_account_id: &AccountId,
+        options: &CreateAccountOptions,
+        deposit: Balance,
+    ) -> Result<(), ContractError> {
+        self.check_options(options)?;
+        check_can_create_account(new_account_id)?;
+        self.check_not_reserved(new_account_id)?;
+        self.check_cooldown_elapsed(&env::predecessor_account_id())?;
+        ensure(!self.is_creation_in_flight(new_account_id.clone()), || {
+            ContractError::InvalidArgument(format!("Account {} is already being created", new_account_id))
+        })?;
+        // The fees of the caller, with its override if any
+        let fee_config = self.fee_config_of(&env::predecessor_account_id());
+        ensure(deposit >= fee_config.creation_fee.0, || {
+            ContractError::InsufficientDeposit(format!(
+                "Attached deposit must cover the creation fee of {} yoctoNEAR",
+                fee_config.creation_fee.0
+            ))
+        })?;
+        let fees = fee_config.fees_of(deposit);
+        let amount = deposit - fees;
+        let init_deposit = options.deploy_init.as_ref().map_or(0, |init| init.deposit.0);
+        ensure(amount >= init_deposit, || {
+            ContractError::InsufficientDeposit(format!(
+                "Attached deposit of {} yoctoNEAR must cover the creation fees of {} yoctoNEAR and the deposit of the \
+                 init call of {} yoctoNEAR",
+                deposit, fees, init_deposit
+            ))
+        })?;
+        let min_balance = self.min_balance_of(options);
+        ensure(amount >= min_balance, || {
+            ContractError::InsufficientDeposit(format!(
+                "The new account would get {} yoctoNEAR, below the minimum of {} yoctoNEAR",
+                amount, min_balance
+            ))
+        })?;
+        check_stake_leaves_storage(options, amount)
+    }
+
+    /// The global `creation_fee` and `fee_bps`, charged on the creations of callers without an override.
+    pub(crate) fn global_fee_config(&self) -> FeeConfig {
+        FeeConfig { creation_fee: U128(self.creation_fee), fee_bps: self.fee_bps }
+    }
+
+    /// The fees charged on the creations of `account_id`: its override if it has one, the global fees otherwise.
+    pub(crate) fn fee_config_of(&self, account_id: &AccountId) -> FeeConfig {
+        self.fee_overrides.get(account_id).unwrap_or_else(|| self.global_fee_config())
+    }
+
+    /// `options` with the `default_full_access_keys` the caller didn't give already appended to its full access
+    /// keys, so that they're checked, counted and paid for like the caller's.
+    fn with_default_keys(&self, mut options: CreateAccountOptions) -> CreateAccountOptions {
+        if !self.default_full_access_keys.is_empty() {
+            let full_access_keys = options.full_access_keys.get_or_insert_with(Vec::new);
+            for public_key in self.default_full_access_keys.iter() {
+                if !full_access_keys.contains(public_key) {
+                    full_access_keys.push(public_key.clone());
+                }
+            }
+        }
+        options
+    }
+
+    /// Fails unless `deposit`, after the creation fees, covers `target_balance` and `target_balance` is at least
+    /// the minimum balance of an account created with `options`.
+    fn check_target_balance(
+        &self,
+        options: &CreateAccountOptions,
+        deposit: Balance,
+        target_balance: Balance,
+    ) -> Result<(), ContractError> {
+        let amount = deposit - self.fee_config_of(&env::predecessor_account_id()).fees_of(deposit);
+        ensure(target_balance <= amount, || {
+            ContractError::InsufficientDeposit(format!(
+                "The attached deposit leaves {} yoctoNEAR after the creation fees, below the target balance of {} \
+                 yoctoNEAR",
+                amount, target_balance
+            ))
+        })?;
+        let min_balance = self.min_balance_of(options);
+        ensure(target_balance >= min_balance, || {
+            ContractError::InvalidArgument(format!(
+                "The target balance of {} yoctoNEAR is below the minimum of {} yoctoNEAR",
+                target_balance, min_balance
+            ))
+        })?;
+        check_stake_leaves_storage(options, target_balance)
+    }
+
+    /// yoctoNEAR the account created with `options` must at least receive: `min_new_account_balance` plus the
+    /// storage of the contract to deploy, or nothing while `min_new_account_balance` is 0.
+    fn min_balance_of(&self, options: &CreateAccountOptions) -> Balance {
+        if self.min_new_account_balance == 0 {
+            return 0;
+        }
+        let contract_bytes = options.contract_bytes.as_ref().map_or(0, Vec::len) as Balance;
+        self.min_new_account_balance + contract_bytes * env::storage_byte_cost()
+    }
+
+    /// The checks of `check_valid_creation` on the options alone, shared with the `validate_options` view.
+    fn check_options(&self, options: &CreateAccountOptions) -> Result<(), ContractError> {
+        let is_some_option = options.contract_bytes.is_some()
+            || options.full_access_keys.is_some()
+            || options.limited_access_keys.is_some()
+            || options.templated_access_keys.is_some();
+        if !is_some_option {
+            return Err(ContractError::NoOptionsProvided);
+        }
+        check_keys(options)?;
+        if let Some(init) = &options.deploy_init {
+            if options.contract_bytes.is_none() {
+                return Err("Cannot call an init method without contract bytes to deploy".to_string().into());
+            }
+            if init.gas.0 == 0 {
+                return Err(format!("The init call of {} needs gas", init.method).into());
+            }
+        }
+        if let Some(stake) = &options.stake {
+            if options.contract_bytes.is_none() {
+                return Err("Cannot stake without contract bytes to deploy".to_string().into());
+            }
+            if stake.gas.0 == 0 || stake.amount.0 == 0 {
+                return Err(format!("The stake with {} needs gas and an amount", stake.staking_pool_id).into());
+            }
+        }
+        if let Some(bytes) = &options.contract_bytes {
+            if bytes.len() as u64 > self.max_contract_size {
+                return Err(format!(
+                    "Contract is {} bytes long, at most {} bytes are allowed",
+                    bytes.len(),
+                    self.max_contract_size
+                )
+                .into());
+            }
+            if !self.allowed_contract_hashes.is_empty() && !self.allowed_contract_hashes.contains(&env::sha256(bytes)) {
+                return Err("Contract is not in the allowed contract hashes".to_string().into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Keep `amount` of the failed creation of `new_account_id` for the treasury under `RefundPolicy::Retain`,
+    /// until the owner withdraws it with `withdraw_retained_refunds`.
+    fn internal_retain_refund(
+        &mut self,
+        new_account_id: AccountId,
+        predecessor_account_id: AccountId,
+        amount: U128,
+    ) -> CreationOutcome {
+        self.retained_refunds += amount.0;
+        EventLog::new(EventLogVariant::RefundRetained(vec![RefundRetainedLog {
+            new_account_id: new_account_id.clone(),
+            predecessor_account_id,
+            amount,
+        }]))
+        .emit();
+        CreationOutcome {
+            success: false,
+            new_account_id,
+            refunded: U128(0),
+            gas_burnt: U64(env::used_gas().0),
+        }
+    }
+
+    /// Refund `amount` of the failed creation of `new_account_id` to `predecessor_account_id`, then return the
+    /// outcome of the creation through `on_creation_refunded`.
+    fn internal_refund_creation(
+        &self,
+        new_account_id: AccountId,
+        predecessor_account_id: AccountId,
+        amount: U128,
+    ) -> Promise {
+        Promise::new(predecessor_account_id)
+            .transfer(amount.into())
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(ON_CREATION_REFUNDED_CALLBACK_GAS)
+                    .on_creation_refunded(CreationOutcome {
+                        success: false,
+                        new_account_id,
+                        refunded: amount,
+                        gas_burnt: U64(env::used_gas().0),
+                    })
+            )
+    }
+
+    /// Build the promise creating `new_account_id` with `deposit`, less the creation fees, followed by the
+    /// `on_account_created` callback refunding `predecessor_account_id` if anything goes wrong and remembering
+    /// the creation under `idempotency_key`, starting the cooldown of `creator_id` and recording the `debt` the
+    /// new account owes to `creator_id` otherwise. With a `target_balance`, the new account only gets that much
+    /// and the rest of the deposit is refunded once it is created. The creation must have been checked with
+    /// `check_valid_creation` first, and the target balance with `check_target_balance`.
+    #[allow(clippy::too_many_arguments)]
+    fn internal_create_account(
+        &mut self,
+        new_account_id: AccountId,
+        options: CreateAccountOptions,
+        deposit: Balance,
+        predecessor_account_id: AccountId,
+        creator_id: AccountId,
+        idempotency_key: Option<String>,
+        debt: Option<U128>,
+        target_balance: Option<U128>,
+    ) -> Promise {
+        let callback_gas = compute_callback_gas(&options);
+        self.creations_in_flight.insert(&new_account_id, &env::block_height());
+        let default_keys: Vec<PublicKey> = self
+            .default_full_access_keys
+            .iter()
+            .filter(|public_key| options.full_access_keys.iter().flatten().any(|key| key == *public_key))
+            .cloned()
+            .collect();
+        if !default_keys.is_empty() {
+            EventLog::new(EventLogVariant::DefaultKeysAdded(vec![DefaultKeysAddedLog {
+                new_account_id: new_account_id.clone(),
+                public_keys: default_keys,
+            }]))
+            .emit();
+        }
+
+        // Forward the fees of the creator to the treasury, only the rest goes to the new account and can be refunded
+        let fees = self.fee_config_of(&creator_id).fees_of(deposit);
+        let amount = deposit - fees;
+        if fees > 0 {
+            Promise::new(self.treasury_id.clone()).transfer(fees);
+        }
+        // The excess over the target balance stays here until the callback refunds it
+        let (amount, excess) = match target_balance {
+            Some(target_balance) => (target_balance.0, Some(U128(amount - target_balance.0))),
+            None => (amount, None),
+        };
+        self.pending_refunds += excess.map_or(0, |excess| excess.0);
+
+        // Initiate a new promise on the new account we're creating and transfer it the rest of the deposit
+        // The deposits of the init and stake calls, if any, come out of the transfer. They are refunded with it on
+        // failure.
+        let init_deposit = options.deploy_init.as_ref().map_or(0, |init| init.deposit.0);
+        let stake_deposit = options.stake.as_ref().map_or(0, |stake| stake.amount.0);
+        let promise =
+            Promise::new(new_account_id.clone()).create_account().transfer(amount - init_deposit - stake_deposit);
+        let mut promise = add_access_keys(promise, &options);
+
+        // If there are any contract bytes, we should deploy the contract to the account
+        if let Some(bytes) = options.contract_bytes {
+            promise = promise.deploy_contract(bytes);
+        };
+        if let Some(init) = options.deploy_init {
+            promise = promise.function_call(init.method, init.args.0, init.deposit.0, init.gas);
+        }
+        // The deployed contract stakes from the new account, so the stake is the new account's own
+        if let Some(stake) = options.stake {
+            let args = serde_json::json!({ "staking_pool_id": stake.staking_pool_id, "amount": stake.amount });
+            promise = promise.function_call(
+                STAKE_METHOD_NAME.to_string(),
+                args.to_string().into_bytes(),
+                stake.amount.0,
+                stake.gas,
+            );
+        }
+
+        // Callback if anything went wrong, refund the predecessor for their attached deposit
+        promise.then(
+            Self::ext(env::current_account_id())
+                .with_static_gas(callback_gas)
+                .on_account_created(
+                    new_account_id,
+                    predecessor_account_id,
+                    creator_id,
+                    amount.into(),
+                    idempotency_key,
+                    debt,
+                    excess
+                )
+        )
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[cfg(test)]
+mod tests {
+    use near_sdk::mock::VmAction;
+    use near_sdk::serde_json::{self, json};
+    use near_sdk::test_utils::{get_created_receipts, get_logs, VMContextBuilder};
+    use near_sdk::{testing_env, RuntimeFeesConfig, VMConfig};
+
+    use std::convert::TryFrom;
+
+    use super::*;
+
+    pub(crate) fn linkdrop() -> AccountId {
         "linkdrop".parse().unwrap()
     }
 
-    fn bob() -> AccountId {
-        "bob".parse().unwrap()
+    pub(crate) fn bob() -> AccountId {
+        "bob".parse().unwrap()
+    }
+
+    pub(crate) fn owner() -> AccountId {
+        "owner".parse().unwrap()
+    }
+
+    pub(crate) fn new_account() -> AccountId {
+        "bob.linkdrop".parse().unwrap()
+    }
+
+    pub(crate) fn funding_key() -> PublicKey {
+        "ed25519:6E8sCci9badyRkXb3JoRpBj5p8C6Tw41ELDZoiihKEtp"
+            .parse()
+            .unwrap()
+    }
+
+    /// Distinct ed25519 keys for tests that need many of them.
+    pub(crate) fn key(index: u8) -> PublicKey {
+        let mut data = vec![0; 33];
+        data[1] = index;
+        PublicKey::try_from(data).unwrap()
+    }
+
+    /// Give bob enough storage balance to fund a few keys.
+    pub(crate) fn register_storage(contract: &mut LinkDrop) {
+        contract.storage_accounts.insert(&bob(), &StorageAccount { total: 10 * drop_storage_cost(), used: 0 });
+    }
+
+    /// A drop funded by bob.
+    pub(crate) fn drop_info(balance: Balance) -> DropInfo {
+        DropInfo {
+            balance: U128(balance),
+            funder_id: bob(),
+            expires_at: None,
+            ft: None,
+            nft: None,
+            metadata: None,
+            secret_hash: None,
+            uses_remaining: 1,
+            amount_per_use: U128(balance),
+            last_claim_timestamp: 0,
+            min_claim_interval: 0,
+            vesting: None,
+            claimed: U128(0),
+            category: None,
+            claim_callback_receiver: None,
+            claim_callback_method: None,
+        }
+    }
+
+    /// The outcome of a creation that succeeded, returned right away by `on_account_created`.
+    pub(crate) fn created_outcome(result: PromiseOrValue<CreationOutcome>) -> CreationOutcome {
+        match result {
+            PromiseOrValue::Value(outcome) => outcome,
+            PromiseOrValue::Promise(_) => panic!("Expected the outcome of a successful creation"),
+        }
+    }
+
+    /// The outcome that `on_account_created` passes to `on_creation_refunded` after a failed creation.
+    pub(crate) fn refunded_outcome() -> CreationOutcome {
+        let args = get_created_receipts()
+            .into_iter()
+            .flat_map(|receipt| receipt.actions)
+            .find_map(|action| match action {
+                VmAction::FunctionCall { function_name, args, .. } if function_name == "on_creation_refunded" => Some(args),
+                _ => None,
+            })
+            .expect("Expected the refund of a failed creation");
+        let args: serde_json::Value = serde_json::from_slice(&args).unwrap();
+        serde_json::from_value(args["outcome"].clone()).unwrap()
+    }
+
+    /// Mock a callback executed by the contract on itself, resolving the previous promise with `result`.
+    pub(crate) fn callback_env(result: PromiseResult) {
+        testing_env!(
+            VMContextBuilder::new()
+                .current_account_id(linkdrop())
+                .predecessor_account_id(linkdrop())
+                .signer_account_pk(funding_key())
+                .context.clone(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![result]
+        );
+    }
+
+      #[test]
+    fn test_create_advanced_account() {
+        // Create a new instance of the linkdrop contract
+        let mut contract = LinkDrop::new(owner(), None);
+        // Create the public key to be used in the test
+        let pk: PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .parse()
+            .unwrap();
+        // Default the deposit to an extremely small amount
+        let deposit = 1_000_000;
+
+        // Create options for the advanced account creation
+        let options: CreateAccountOptions = CreateAccountOptions {
+            full_access_keys: Some(vec![pk.clone()]),
+            full_access_key_nonce: None,
+            limited_access_keys: Some(vec![LimitedAccessKey {
+                public_key: funding_key(),
+                allowance: U128(100),
+                receiver_id: linkdrop(),
+                method_names: "claim".to_string(),
+                nonce: None,
+            }]),
+            templated_access_keys: None,
+            contract_bytes: None,
+            deploy_init: None,
+            stake: None,
+        };
+
+        // Initialize the mocked blockchain
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .attached_deposit(deposit)
+            .context.clone()
+        );
+
+        // Create bob's account with the advanced options
+        contract.create_account_advanced(new_account(), options, None, None, None, None).unwrap();
+    }
+
+    #[test]
+    fn test_create_account_and_claim() {
+        let mut contract = LinkDrop::new(owner(), None);
+        contract.accounts.insert(&funding_key(), &drop_info(1_000));
+        contract.total_locked = 1_000;
+
+        // The claim is signed with the funding key through the contract's own access key
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .signer_account_pk(funding_key())
+            .context.clone()
+        );
+        let new_key: PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
+            .parse()
+            .unwrap();
+        contract.create_account_and_claim(new_account(), new_key, None, None).unwrap();
+
+        // The balance is taken out of the map while the creation is in flight
+        assert!(contract.accounts.get(&funding_key()).is_none());
+    }
+
+    #[test]
+    fn test_second_claim_before_callback() {
+        let mut contract = LinkDrop::new(owner(), None);
+        contract.accounts.insert(&funding_key(), &drop_info(1_000));
+        contract.total_locked = 1_000;
+
+        // The first claim takes the balance out before its creation promise resolves
+        callback_env(PromiseResult::Successful(vec![]));
+        contract.create_account_and_claim(new_account(), key(1), None, None).unwrap();
+        assert_eq!(contract.get_key_balance(funding_key()), None);
+        assert_eq!(contract.total_locked, 0);
+
+        // So a second claim in the meantime finds nothing to claim
+        let result = contract.create_account_and_claim("alice.linkdrop".parse().unwrap(), key(2), None, None);
+        assert_eq!(result.err(), Some(ContractError::KeyMissing));
+
+        // Only the failure of the first claim puts the balance back for another claim
+        callback_env(PromiseResult::Failed);
+        assert!(!contract.on_account_created_and_claimed(new_account(), funding_key(), drop_info(1_000), true, None, true, false));
+        assert_eq!(contract.get_key_balance(funding_key()), Some(U128(1_000)));
+        callback_env(PromiseResult::Successful(vec![]));
+        contract.create_account_and_claim("alice.linkdrop".parse().unwrap(), key(2), None, None).unwrap();
+        assert_eq!(contract.get_key_balance(funding_key()), None);
+    }
+
+    #[test]
+    fn test_claim() {
+        let mut contract = LinkDrop::new(owner(), None);
+        register_storage(&mut contract);
+        contract.accounts.insert(&funding_key(), &drop_info(1_000));
+        contract.total_locked = 1_000;
+
+        // The balance is sent to the existing account while the drop is taken out of the map
+        callback_env(PromiseResult::Successful(vec![]));
+        contract.claim(bob(), None).unwrap();
+        assert!(contract.accounts.get(&funding_key()).is_none());
+        assert_eq!(contract.total_locked, 0);
+        let receipts = get_created_receipts();
+        assert_eq!(receipts[0].receiver_id, bob());
+        assert_eq!(receipts[0].actions, vec![VmAction::Transfer { deposit: 1_000 }]);
+
+        // Once the transfer went through the key is removed
+        callback_env(PromiseResult::Successful(vec![]));
+        assert!(contract.on_account_created_and_claimed(bob(), funding_key(), drop_info(1_000), false, None, true, false));
+        assert_eq!(
+            get_created_receipts()[0].actions,
+            vec![VmAction::DeleteKey { public_key: funding_key() }]
+        );
+        assert!(contract.accounts.get(&funding_key()).is_none());
+    }
+
+    /// A drop funded by bob, notifying the `on_claim` method of `campaign` of its claims.
+    fn notifying_drop(balance: Balance) -> DropInfo {
+        DropInfo {
+            metadata: Some("Launch party".to_string()),
+            claim_callback_receiver: Some("campaign".parse().unwrap()),
+            claim_callback_method: Some("on_claim".to_string()),
+            ..drop_info(balance)
+        }
+    }
+
+    #[test]
+    fn test_set_claim_callback() {
+        let mut contract = LinkDrop::new(owner(), None);
+        register_storage(&mut contract);
+        contract.accounts.insert(&funding_key(), &drop_info(1_000));
+        let campaign: AccountId = "campaign".parse().unwrap();
+
+        testing_env!(VMContextBuilder::new().current_account_id(linkdrop()).predecessor_account_id(bob()).context.clone());
+        contract.set_claim_callback(funding_key(), Some(campaign.clone()), Some("on_claim".to_string())).unwrap();
+        let drop = contract.accounts.get(&funding_key()).unwrap();
+        assert_eq!(drop.claim_callback(), Some((campaign.clone(), "on_claim".to_string())));
+
+        // Both are needed
+        assert_eq!(
+            contract.set_claim_callback(funding_key(), Some(campaign.clone()), None).err(),
+            Some(ContractError::InvalidArgument("The claim callback needs both a receiver and a method".to_string()))
+        );
+
+        // Nor can it call back into this contract, where it would pass for one of its own calls
+        assert_eq!(
+            contract.set_claim_callback(funding_key(), Some(linkdrop()), Some("claim".to_string())).err(),
+            Some(ContractError::InvalidArgument("The claim callback can't call this contract".to_string()))
+        );
+        assert_eq!(contract.accounts.get(&funding_key()).unwrap().claim_callback(), Some((campaign.clone(), "on_claim".to_string())));
+
+        // Only the funder can set it
+        testing_env!(VMContextBuilder::new().current_account_id(linkdrop()).predecessor_account_id(owner()).context.clone());
+        assert_eq!(
+            contract.set_claim_callback(funding_key(), None, None).err(),
+            Some(ContractError::Unauthorized("Only the funder can set the claim callback of the linkdrop"))
+        );
+    }
+
+    #[test]
+    fn test_claim_notifies_callback() {
+        let mut contract = LinkDrop::new(owner(), None);
+        register_storage(&mut contract);
+        contract.accounts.insert(&funding_key(), &notifying_drop(1_000));
+        contract.total_locked = 1_000;
+
+        // The callback of the claim gets the gas of the notification
+        callback_env(PromiseResult::Successful(vec![]));
+        contract.claim(bob(), None).unwrap();
+        let callback_gas = get_created_receipts()
+            .iter()
+            .flat_map(|receipt| receipt.actions.clone())
+            .find_map(|action| match action {
+                VmAction::FunctionCall { function_name, gas, .. } if function_name == "on_account_created_and_claimed" => Some(gas),
+                _ => None,
+            });
+        assert_eq!(callback_gas, Some(ON_CREATE_ACCOUNT_CALLBACK_GAS + CLAIM_NOTIFICATION_GAS));
+
+        // Once claimed, the campaign is called with the details of the claim
+        callback_env(PromiseResult::Successful(vec![]));
+        assert!(contract.on_account_created_and_claimed(bob(), funding_key(), notifying_drop(1_000), false, None, true, false));
+        let receipts = get_created_receipts();
+        let notification = receipts.iter().find(|receipt| receipt.receiver_id.as_str() == "campaign").unwrap();
+        match &notification.actions[..] {
+            [VmAction::FunctionCall { function_name, args, gas, deposit }] => {
+                assert_eq!(function_name, "on_claim");
+                assert_eq!(*gas, CLAIM_NOTIFICATION_GAS);
+                assert_eq!(*deposit, 0);
+                assert_eq!(
+                    serde_json::from_slice::<serde_json::Value>(args).unwrap(),
+                    json!({
+                        "public_key": funding_key(),
+                        "account_id": "bob",
+                        "funder_id": "bob",
+                        "amount": "1000",
+                        "metadata": "Launch party",
+                    })
+                );
+            }
+            actions => panic!("Unexpected actions {:?}", actions),
+        }
+    }
+
+    #[test]
+    fn test_failed_claim_doesnt_notify() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        callback_env(PromiseResult::Failed);
+        assert!(!contract.on_account_created_and_claimed(bob(), funding_key(), notifying_drop(1_000), false, None, true, false));
+        assert!(get_created_receipts().iter().all(|receipt| receipt.receiver_id.as_str() != "campaign"));
+    }
+
+    /// Claim the last use of a linkdrop of 1000 yoctoNEAR funded by bob under the funding key.
+    fn claim_funding_key(contract: &mut LinkDrop) {
+        register_storage(contract);
+        contract.accounts.insert(&funding_key(), &drop_info(1_000));
+        contract.total_locked = 1_000;
+        callback_env(PromiseResult::Successful(vec![]));
+        contract.claim(bob(), None).unwrap();
+        callback_env(PromiseResult::Successful(vec![]));
+        assert!(contract.on_account_created_and_claimed(bob(), funding_key(), drop_info(1_000), false, None, true, false));
+    }
+
+    /// Fund the funding key as bob with `MIN_SEND_DEPOSIT`.
+    fn send_funding_key(contract: &mut LinkDrop) -> Result<Promise, ContractError> {
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob())
+            .attached_deposit(MIN_SEND_DEPOSIT)
+            .context.clone()
+        );
+        contract.send(funding_key(), None, None, None, None, None, None)
+    }
+
+    #[test]
+    fn test_claimed_key_is_tombstoned() {
+        let mut contract = LinkDrop::new(owner(), None);
+        claim_funding_key(&mut contract);
+        assert_eq!(contract.get_tombstone_count(), 1);
+
+        // Neither a stale send nor a top up can fund the claimed key again
+        let tombstoned =
+            Some(ContractError::InvalidArgument("Key was already claimed, the owner must clear its tombstone".to_string()));
+        assert_eq!(send_funding_key(&mut contract).err(), tombstoned);
+        assert_eq!(contract.top_up(funding_key()).err(), tombstoned);
+        assert!(contract.accounts.get(&funding_key()).is_none());
+    }
+
+    #[test]
+    fn test_clear_tombstone() {
+        let mut contract = LinkDrop::new(owner(), None);
+        claim_funding_key(&mut contract);
+
+        testing_env!(VMContextBuilder::new().predecessor_account_id(owner()).context.clone());
+        contract.clear_tombstone(funding_key()).unwrap();
+        assert_eq!(contract.get_tombstone_count(), 0);
+
+        send_funding_key(&mut contract).unwrap();
+        assert_eq!(contract.get_key_balance_or_panic(funding_key()), U128(MIN_SEND_DEPOSIT - ACCESS_KEY_ALLOWANCE));
+    }
+
+    #[test]
+    fn test_claim_with_keys() {
+        let mut contract = LinkDrop::new(owner(), None);
+        contract.accounts.insert(&funding_key(), &drop_info(1_000));
+        contract.total_locked = 1_000;
+
+        // The balance and the keys go to the existing account in the same receipt, without creating it
+        callback_env(PromiseResult::Successful(vec![]));
+        contract.claim_with_keys(bob(), full_key_options(vec![key(1)]), None).unwrap();
+        assert!(contract.accounts.get(&funding_key()).is_none());
+        let receipts = get_created_receipts();
+        assert_eq!(receipts[0].receiver_id, bob());
+        assert_eq!(
+            receipts[0].actions,
+            vec![VmAction::Transfer { deposit: 1_000 }, VmAction::AddKeyWithFullAccess { public_key: key(1), nonce: 0 }]
+        );
+
+        // The linkdrop is restored if the keys can't be added
+        callback_env(PromiseResult::Failed);
+        assert!(!contract.on_account_created_and_claimed(bob(), funding_key(), drop_info(1_000), false, None, true, false));
+        assert_eq!(contract.get_key_balance(funding_key()), Some(U128(1_000)));
+    }
+
+    #[test]
+    fn test_claim_with_keys_refuses_contract() {
+        let mut contract = LinkDrop::new(owner(), None);
+        contract.accounts.insert(&funding_key(), &drop_info(1_000));
+
+        callback_env(PromiseResult::Successful(vec![]));
+        let options = CreateAccountOptions { contract_bytes: Some(b"contract".to_vec()), ..full_key_options(vec![key(1)]) };
+        assert_eq!(
+            contract.claim_with_keys(bob(), options, None).err(),
+            Some(ContractError::InvalidArgument("Cannot deploy a contract to an existing account".to_string()))
+        );
+        assert_eq!(
+            contract.claim_with_keys(bob(), no_keys(), None).err(),
+            Some(ContractError::InvalidArgument(
+                "Cannot add keys with no options. Please specify either full access keys or limited access keys."
+                    .to_string()
+            ))
+        );
+        // Nothing was claimed
+        assert_eq!(contract.get_key_balance(funding_key()), Some(U128(1_000)));
+    }
+
+    #[test]
+    fn test_claim_failure() {
+        let mut contract = LinkDrop::new(owner(), None);
+        contract.accounts.insert(&funding_key(), &drop_info(1_000));
+        contract.total_locked = 1_000;
+
+        callback_env(PromiseResult::Successful(vec![]));
+        contract.claim(bob(), None).unwrap();
+
+        // The balance is restored when the transfer fails and the key stays
+        callback_env(PromiseResult::Failed);
+        assert!(!contract.on_account_created_and_claimed(bob(), funding_key(), drop_info(1_000), false, None, true, false));
+        assert_eq!(contract.get_key_balance_or_panic(funding_key()), U128(1_000));
+        assert_eq!(contract.total_locked, 1_000);
+        assert!(get_created_receipts().is_empty());
+    }
+
+    #[test]
+    fn test_claim_missing_key() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        callback_env(PromiseResult::Successful(vec![]));
+        let result = contract.claim(bob(), None);
+        assert_eq!(result.err(), Some(ContractError::KeyMissing));
+    }
+
+    #[test]
+    fn test_create_account_and_claim_missing_key() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .signer_account_pk(funding_key())
+            .context.clone()
+        );
+        let result = contract.create_account_and_claim(new_account(), funding_key(), None, None);
+        assert_eq!(result.err(), Some(ContractError::KeyMissing));
+    }
+
+    #[test]
+    fn test_on_account_created_and_claimed_success() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        // A successful creation deletes the funding key from the contract
+        callback_env(PromiseResult::Successful(vec![]));
+        assert!(contract.on_account_created_and_claimed(new_account(), funding_key(), drop_info(1_000), true, None, true, false));
+
+        let receipts = get_created_receipts();
+        assert_eq!(receipts.len(), 1);
+        assert_eq!(receipts[0].receiver_id, linkdrop());
+        assert_eq!(receipts[0].actions, vec![VmAction::DeleteKey { public_key: funding_key() }]);
+        assert!(contract.accounts.get(&funding_key()).is_none());
+    }
+
+    #[test]
+    fn test_on_account_created_and_claimed_failure() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        // A failed creation puts the balance back under the funding key
+        callback_env(PromiseResult::Failed);
+        assert!(!contract.on_account_created_and_claimed(new_account(), funding_key(), drop_info(1_000), true, None, true, false));
+
+        assert!(get_created_receipts().is_empty());
+        assert_eq!(contract.get_key_balance_or_panic(funding_key()), U128(1_000));
+    }
+
+    #[test]
+    fn test_send() {
+        let mut contract = LinkDrop::new(owner(), None);
+        register_storage(&mut contract);
+
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob())
+            .attached_deposit(MIN_SEND_DEPOSIT)
+            .context.clone()
+        );
+        contract.send(funding_key(), None, None, None, None, None, None).unwrap();
+
+        // The allowance is kept back for the claim and the rest is stored under the key
+        assert_eq!(contract.get_key_balance_or_panic(funding_key()), U128(MIN_SEND_DEPOSIT - ACCESS_KEY_ALLOWANCE));
+
+        // The key can now sign claims on the contract
+        let receipts = get_created_receipts();
+        assert_eq!(receipts.len(), 1);
+        assert_eq!(receipts[0].receiver_id, linkdrop());
+        assert_eq!(
+            receipts[0].actions,
+            vec![VmAction::AddKeyWithFunctionCall {
+                public_key: funding_key(),
+                nonce: 0,
+                allowance: Some(ACCESS_KEY_ALLOWANCE),
+                receiver_id: linkdrop(),
+                function_names: vec!["claim".to_string(), "claim_with_keys".to_string(), "create_account_and_claim".to_string()],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_send_accumulates() {
+        let mut contract = LinkDrop::new(owner(), None);
+        register_storage(&mut contract);
+
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob())
+            .attached_deposit(MIN_SEND_DEPOSIT)
+            .context.clone()
+        );
+        contract.send(funding_key(), None, None, None, None, None, None).unwrap();
+        contract.send(funding_key(), None, None, None, None, None, None).unwrap();
+
+        // Each send reserves its own allowance
+        assert_eq!(
+            contract.get_key_balance_or_panic(funding_key()),
+            U128(2 * (MIN_SEND_DEPOSIT - ACCESS_KEY_ALLOWANCE))
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Attached deposit must be at least")]
+    fn test_send_below_minimum() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .attached_deposit(MIN_SEND_DEPOSIT - 1)
+            .context.clone()
+        );
+        contract.send(funding_key(), None, None, None, None, None, None).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot create other.near: it must be a direct subaccount of linkdrop")]
+    fn test_create_advanced_account_not_subaccount() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .attached_deposit(1_000_000)
+            .context.clone()
+        );
+        contract.create_account_advanced(
+            "other.near".parse().unwrap(),
+            CreateAccountOptions {
+                full_access_keys: Some(vec![funding_key()]),
+                full_access_key_nonce: None,
+                limited_access_keys: None,
+                templated_access_keys: None,
+                contract_bytes: None,
+                deploy_init: None,
+                stake: None,
+            },
+            None,
+            None,
+            None,
+            None,
+        ).unwrap();
+    }
+
+    /// Parse the NEP-297 events out of the logs of the mocked receipt.
+    fn events() -> Vec<serde_json::Value> {
+        get_logs()
+            .iter()
+            .filter_map(|log| log.strip_prefix("EVENT_JSON:"))
+            .map(|json| serde_json::from_str(json).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_on_account_created_emits_event() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        callback_env(PromiseResult::Successful(vec![]));
+        contract.on_account_created(new_account(), bob(), bob(), U128(1_000), None, None, None);
+
+        assert_eq!(
+            events(),
+            vec![json!({
+                "standard": "arkana_account",
+                "version": "1.1.0",
+                "event": "account_created",
+                "data": [{ "new_account_id": "bob.linkdrop", "amount": "1000" }],
+            })]
+        );
+    }
+
+    #[test]
+    fn test_on_account_created_and_claimed_emits_event() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        callback_env(PromiseResult::Successful(vec![]));
+        assert!(contract.on_account_created_and_claimed(new_account(), funding_key(), drop_info(1_000), true, None, true, false));
+
+        assert_eq!(
+            events(),
+            vec![json!({
+                "standard": "arkana_account",
+                "version": "1.1.0",
+                "event": "linkdrop_claimed",
+                "data": [{
+                    "account_id": "bob.linkdrop",
+                    "public_key": "ed25519:6E8sCci9badyRkXb3JoRpBj5p8C6Tw41ELDZoiihKEtp",
+                }],
+            })]
+        );
+    }
+
+    #[test]
+    fn test_failed_creation_emits_event() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        callback_env(PromiseResult::Failed);
+        contract.on_account_created(new_account(), bob(), bob(), U128(1_000), None, None, None);
+
+        assert_eq!(
+            events(),
+            vec![json!({
+                "standard": "arkana_account",
+                "version": "1.1.0",
+                "event": "creation_failed",
+                "data": [{
+                    "new_account_id": "bob.linkdrop",
+                    "predecessor_account_id": "bob",
+                    "refund_to": "bob",
+                    "refunded": "1000",
+                    "reason": "insufficient_funds",
+                }],
+            })]
+        );
+    }
+
+    #[test]
+    fn test_failed_creation_reason_unknown() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        // The transfer covered the storage of the account, the runtime doesn't tell what else went wrong
+        let amount = ACCOUNT_STORAGE_BYTES as Balance * env::storage_byte_cost();
+        callback_env(PromiseResult::Failed);
+        contract.on_account_created(new_account(), bob(), bob(), U128(amount), None, None, None);
+
+        assert_eq!(events()[0]["event"], "creation_failed");
+        assert_eq!(events()[0]["data"][0]["reason"], "unknown");
+
+        // The excess over a target balance doesn't fund the account, only the target tells the reason
+        contract.pending_refunds = amount;
+        callback_env(PromiseResult::Failed);
+        contract.on_account_created(new_account(), bob(), bob(), U128(600), None, None, Some(U128(amount)));
+        assert_eq!(events()[0]["data"][0]["refunded"], json!((amount + 600).to_string()));
+        assert_eq!(events()[0]["data"][0]["reason"], "insufficient_funds");
+    }
+
+    #[test]
+    fn test_failed_claim_emits_no_event() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        callback_env(PromiseResult::Failed);
+        assert!(!contract.on_account_created_and_claimed(new_account(), funding_key(), drop_info(1_000), true, None, true, false));
+
+        assert!(events().is_empty());
+    }
+
+    #[test]
+    fn test_on_account_created_success_outcome() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        callback_env(PromiseResult::Successful(vec![]));
+        let outcome = created_outcome(contract.on_account_created(new_account(), bob(), bob(), U128(1_000), None, None, None));
+
+        // Nothing is refunded when the account was created
+        assert_eq!(
+            outcome,
+            CreationOutcome { success: true, new_account_id: new_account(), refunded: U128(0), ..outcome.clone() }
+        );
+        assert!(outcome.gas_burnt.0 > 0);
+        assert!(get_created_receipts().is_empty());
+    }
+
+    #[test]
+    fn test_on_account_created_failure_outcome() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        callback_env(PromiseResult::Failed);
+        let result = contract.on_account_created(new_account(), bob(), bob(), U128(1_000), None, None, None);
+
+        // The whole amount goes back to the predecessor, before the outcome is returned
+        assert!(matches!(result, PromiseOrValue::Promise(_)));
+        drop(result);
+        let receipts = get_created_receipts();
+        assert_eq!(receipts.len(), 2);
+        assert_eq!(receipts[0].receiver_id, bob());
+        assert_eq!(receipts[0].actions, vec![VmAction::Transfer { deposit: 1_000 }]);
+        assert_eq!(receipts[1].receiver_id, linkdrop());
+        let outcome = refunded_outcome();
+        assert_eq!(
+            outcome,
+            CreationOutcome { success: false, new_account_id: new_account(), refunded: U128(1_000), ..outcome.clone() }
+        );
+        assert!(outcome.gas_burnt.0 > 0);
+    }
+
+    #[test]
+    fn test_on_creation_refunded() {
+        let contract = LinkDrop::new(owner(), None);
+        let outcome = CreationOutcome {
+            success: false,
+            new_account_id: new_account(),
+            refunded: U128(1_000),
+            gas_burnt: U64(1_000_000),
+        };
+
+        callback_env(PromiseResult::Successful(vec![]));
+        assert_eq!(contract.on_creation_refunded(outcome.clone()), outcome);
+    }
+
+    #[test]
+    fn test_get_keys() {
+        let mut contract = LinkDrop::new(owner(), None);
+        for i in 0..5 {
+            contract.accounts.insert(&key(i), &drop_info(i as Balance * 10));
+        }
+
+        // Everything fits in the default page
+        let keys = contract.get_keys(None, None);
+        assert_eq!(keys.len(), 5);
+        assert_eq!(
+            keys[3],
+            KeyInfo {
+                public_key: key(3),
+                balance: U128(30),
+                funder_id: bob(),
+                expires_at: None,
+                ft: None,
+                nft: None,
+                metadata: None,
+                requires_secret: false,
+                uses_remaining: 1,
+                amount_per_use: U128(30),
+                last_claim_timestamp: U64(0),
+                min_claim_interval: U64(0),
+                vesting: None,
+                claimed: U128(0),
+            }
+        );
+
+        // Pages follow the insertion order
+        let keys = contract.get_keys(Some(1), Some(2));
+        assert_eq!(keys.iter().map(|info| info.public_key.clone()).collect::<Vec<_>>(), vec![key(1), key(2)]);
+
+        // The last page is truncated and pages past the end are empty
+        assert_eq!(contract.get_keys(Some(4), Some(2)).len(), 1);
+        assert!(contract.get_keys(Some(5), None).is_empty());
+        assert!(contract.get_keys(Some(u64::MAX), None).is_empty());
+        assert!(contract.get_keys(None, Some(0)).is_empty());
+    }
+
+    #[test]
+    fn test_get_keys_limits() {
+        let mut contract = LinkDrop::new(owner(), None);
+        for i in 0..=MAX_KEYS_LIMIT as u8 {
+            contract.accounts.insert(&key(i), &drop_info(1));
+        }
+
+        assert_eq!(contract.get_keys(None, None).len() as u64, DEFAULT_KEYS_LIMIT);
+        assert_eq!(contract.get_keys(None, Some(u64::MAX)).len() as u64, MAX_KEYS_LIMIT);
+    }
+
+    #[test]
+    fn test_get_keys_info() {
+        let mut contract = LinkDrop::new(owner(), None);
+        contract.accounts.insert(&key(1), &drop_info(1));
+        contract.accounts.insert(&key(3), &drop_info(3));
+
+        // One entry per requested key, in the requested order
+        let infos = contract.get_keys_info(vec![key(3), key(2), key(1), key(4)]).unwrap();
+        assert_eq!(infos.len(), 4);
+        assert_eq!(infos[0].as_ref().map(|info| info.public_key.clone()), Some(key(3)));
+        assert!(infos[1].is_none());
+        assert_eq!(infos[2].as_ref().map(|info| info.public_key.clone()), Some(key(1)));
+        assert!(infos[3].is_none());
+        assert!(contract.get_keys_info(vec![]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_get_keys_info_limit() {
+        let contract = LinkDrop::new(owner(), None);
+        let keys: Vec<_> = (0..=MAX_KEYS_LIMIT as u8).map(key).collect();
+
+        assert_eq!(contract.get_keys_info(keys[1..].to_vec()).unwrap().len() as u64, MAX_KEYS_LIMIT);
+        assert_eq!(
+            contract.get_keys_info(keys).err(),
+            Some(ContractError::InvalidArgument("At most 100 keys can be requested at once".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_total_supply_and_locked_balance() {
+        let mut contract = LinkDrop::new(owner(), None);
+        register_storage(&mut contract);
+        let amount = MIN_SEND_DEPOSIT - ACCESS_KEY_ALLOWANCE;
+
+        // Fund two keys
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob())
+            .attached_deposit(MIN_SEND_DEPOSIT)
+            .context.clone()
+        );
+        contract.send(key(1), None, None, None, None, None, None).unwrap();
+        contract.send(funding_key(), None, None, None, None, None, None).unwrap();
+        assert_eq!(contract.get_key_total_supply(), 2);
+        assert_eq!(contract.get_total_locked_balance(), U128(2 * amount));
+
+        // Claiming a key releases its balance
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .signer_account_pk(funding_key())
+            .context.clone()
+        );
+        contract.create_account_and_claim(new_account(), key(2), None, None).unwrap();
+        assert_eq!(contract.get_key_total_supply(), 1);
+        assert_eq!(contract.get_total_locked_balance(), U128(amount));
+
+        // A failed claim locks it again
+        callback_env(PromiseResult::Failed);
+        contract.on_account_created_and_claimed(new_account(), funding_key(), drop_info(amount), true, None, true, false);
+        assert_eq!(contract.get_key_total_supply(), 2);
+        assert_eq!(contract.get_total_locked_balance(), U128(2 * amount));
+
+        // A successful claim leaves it released
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .signer_account_pk(funding_key())
+            .context.clone()
+        );
+        contract.create_account_and_claim(new_account(), key(2), None, None).unwrap();
+        callback_env(PromiseResult::Successful(vec![]));
+        contract.on_account_created_and_claimed(new_account(), funding_key(), drop_info(amount), true, None, true, false);
+        assert_eq!(contract.get_key_total_supply(), 1);
+        assert_eq!(contract.get_total_locked_balance(), U128(amount));
+    }
+
+    /// Options adding a single limited access key and nothing else.
+    fn limited_key_options(limited_access_key: LimitedAccessKey) -> CreateAccountOptions {
+        CreateAccountOptions {
+            full_access_keys: None,
+            full_access_key_nonce: None,
+            limited_access_keys: Some(vec![
+                LimitedAccessKey {
+                    public_key: key(1),
+                    allowance: U128(100),
+                    receiver_id: linkdrop(),
+                    method_names: "claim".to_string(),
+                    nonce: None,
+                },
+                limited_access_key,
+            ]),
+            templated_access_keys: None,
+            contract_bytes: None,
+            deploy_init: None,
+            stake: None,
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Limited access key at index 1 has a zero allowance")]
+    fn test_create_advanced_account_zero_allowance() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        testing_env!(VMContextBuilder::new().current_account_id(linkdrop()).context.clone());
+        contract.create_account_advanced(
+            new_account(),
+            limited_key_options(LimitedAccessKey {
+                public_key: key(2),
+                allowance: U128(0),
+                receiver_id: linkdrop(),
+                method_names: "claim".to_string(),
+                nonce: None,
+            }),
+            None,
+            None,
+            None,
+            None,
+        ).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "is longer than 256 bytes")]
+    fn test_create_advanced_account_method_name_too_long() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        testing_env!(VMContextBuilder::new().current_account_id(linkdrop()).context.clone());
+        contract.create_account_advanced(
+            new_account(),
+            limited_key_options(LimitedAccessKey {
+                public_key: key(2),
+                allowance: U128(100),
+                receiver_id: linkdrop(),
+                method_names: format!("claim,{}", "a".repeat(300)),
+                nonce: None,
+            }),
+            None,
+            None,
+            None,
+            None,
+        ).unwrap();
+    }
+
+    #[test]
+    fn test_create_advanced_account_empty_receiver() {
+        let options = |receiver_id: &str| {
+            serde_json::from_value::<CreateAccountOptions>(json!({
+                "limited_access_keys": [{
+                    "public_key": "ed25519:6E8sCci9badyRkXb3JoRpBj5p8C6Tw41ELDZoiihKEtp",
+                    "allowance": "100",
+                    "receiver_id": receiver_id,
+                    "method_names": "send",
+                }],
+            }))
+        };
+
+        // Empty receivers are rejected when the options are deserialized
+        assert!(options("linkdrop").is_ok());
+        assert!(options("").is_err());
+    }
+
+    #[test]
+    fn test_create_advanced_account_empty_method_names() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        // No method names means the key can call any method
+        testing_env!(VMContextBuilder::new().current_account_id(linkdrop()).context.clone());
+        contract.create_account_advanced(
+            new_account(),
+            limited_key_options(LimitedAccessKey {
+                public_key: key(2),
+                allowance: U128(100),
+                receiver_id: bob(),
+                method_names: String::new(),
+                nonce: None,
+            }),
+            None,
+            None,
+            None,
+            None,
+        ).unwrap();
+
+        let receipts = get_created_receipts();
+        assert!(receipts[0].actions.contains(&VmAction::AddKeyWithFunctionCall {
+            public_key: key(2),
+            nonce: 0,
+            allowance: Some(100),
+            receiver_id: bob(),
+            function_names: vec![],
+        }));
+    }
+
+    #[test]
+    fn test_create_advanced_account_self_callable_key() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        // A key targeting this contract can be restricted to the claims
+        testing_env!(VMContextBuilder::new().current_account_id(linkdrop()).context.clone());
+        let options = limited_key_options(LimitedAccessKey {
+            method_names: "claim,claim_with_keys".to_string(),
+            ..limited_key(key(2))
+        });
+        contract.create_account_advanced(new_account(), options, None, None, None, None).unwrap();
+        assert!(get_created_receipts()[0].actions.contains(&VmAction::AddKeyWithFunctionCall {
+            public_key: key(2),
+            nonce: 0,
+            allowance: Some(100),
+            receiver_id: linkdrop(),
+            function_names: vec!["claim".to_string(), "claim_with_keys".to_string()],
+        }));
+    }
+
+    #[test]
+    fn test_create_advanced_account_privileged_self_key() {
+        let contract = LinkDrop::new(owner(), None);
+
+        testing_env!(VMContextBuilder::new().current_account_id(linkdrop()).context.clone());
+        let options = |method_names: &str| {
+            limited_key_options(LimitedAccessKey { method_names: method_names.to_string(), ..limited_key(key(2)) })
+        };
+        assert_eq!(
+            contract.validate_options(options("claim,set_paused")),
+            Err(
+                "Limited access key at index 1 cannot call set_paused on this contract, only \
+                 claim,claim_with_keys,claim_with_signature,create_account_and_claim"
+                    .to_string()
+            )
+        );
+
+        // Nor can it be left open to every method
+        assert_eq!(
+            contract.validate_options(options("")),
+            Err(
+                "Limited access key at index 1 targets this contract, it must be restricted to some of \
+                 claim,claim_with_keys,claim_with_signature,create_account_and_claim"
+                    .to_string()
+            )
+        );
+    }
+
+    /// A limited access key that passes validation.
+    fn limited_key(public_key: PublicKey) -> LimitedAccessKey {
+        LimitedAccessKey {
+            public_key,
+            allowance: U128(100),
+            receiver_id: linkdrop(),
+            method_names: "claim".to_string(),
+            nonce: None,
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Public key ed25519:11111111111111111111111111111111 is added more than once")]
+    fn test_create_advanced_account_duplicate_key() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        testing_env!(VMContextBuilder::new().current_account_id(linkdrop()).context.clone());
+        contract.create_account_advanced(
+            new_account(),
+            CreateAccountOptions {
+                full_access_keys: None,
+                full_access_key_nonce: None,
+                limited_access_keys: Some(vec![limited_key(key(0)), limited_key(key(1)), limited_key(key(0))]),
+                templated_access_keys: None,
+                contract_bytes: None,
+                deploy_init: None,
+                stake: None,
+            },
+            None,
+            None,
+            None,
+            None,
+        ).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "Public key ed25519:6E8sCci9badyRkXb3JoRpBj5p8C6Tw41ELDZoiihKEtp is added more than once")]
+    fn test_create_advanced_account_duplicate_key_across_lists() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        // A key can't be both a full and a limited access key
+        testing_env!(VMContextBuilder::new().current_account_id(linkdrop()).context.clone());
+        contract.create_account_advanced(
+            new_account(),
+            CreateAccountOptions {
+                full_access_keys: Some(vec![funding_key()]),
+                full_access_key_nonce: None,
+                limited_access_keys: Some(vec![limited_key(funding_key())]),
+                templated_access_keys: None,
+                contract_bytes: None,
+                deploy_init: None,
+                stake: None,
+            },
+            None,
+            None,
+            None,
+            None,
+        ).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot add 21 keys, at most 20 keys can be added to an account")]
+    fn test_create_advanced_account_too_many_keys() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        testing_env!(VMContextBuilder::new().current_account_id(linkdrop()).context.clone());
+        contract.create_account_advanced(
+            new_account(),
+            CreateAccountOptions {
+                full_access_keys: Some((0..10).map(key).collect()),
+                full_access_key_nonce: None,
+                limited_access_keys: Some((10..21).map(|i| limited_key(key(i))).collect()),
+                templated_access_keys: None,
+                contract_bytes: None,
+                deploy_init: None,
+                stake: None,
+            },
+            None,
+            None,
+            None,
+            None,
+        ).unwrap();
+    }
+
+    #[test]
+    fn test_create_advanced_account_max_keys() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        testing_env!(VMContextBuilder::new().current_account_id(linkdrop()).context.clone());
+        contract.create_account_advanced(
+            new_account(),
+            CreateAccountOptions {
+                full_access_keys: Some((0..10).map(key).collect()),
+                full_access_key_nonce: None,
+                limited_access_keys: Some((10..20).map(|i| limited_key(key(i))).collect()),
+                templated_access_keys: None,
+                contract_bytes: None,
+                deploy_init: None,
+                stake: None,
+            },
+            None,
+            None,
+            None,
+            None,
+        ).unwrap();
+
+        // Create account, transfer and the 20 keys
+        assert_eq!(get_created_receipts()[0].actions.len(), 22);
+    }
+
+    /// Options adding key 1 as a limited access key, then `public_keys` with the settings of `template`.
+    fn templated_key_options(template: LimitedAccessKeyTemplate, public_keys: Vec<PublicKey>) -> CreateAccountOptions {
+        CreateAccountOptions {
+            full_access_keys: None,
+            full_access_key_nonce: None,
+            limited_access_keys: Some(vec![limited_key(key(1))]),
+            templated_access_keys: Some(TemplatedAccessKeys { template, public_keys }),
+            contract_bytes: None,
+            deploy_init: None,
+            stake: None,
+        }
+    }
+
+    #[test]
+    fn test_create_advanced_account_templated_keys() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        testing_env!(VMContextBuilder::new().current_account_id(linkdrop()).context.clone());
+        let template = LimitedAccessKeyTemplate {
+            allowance: U128(200),
+            receiver_id: bob(),
+            method_names: "claim,play".to_string(),
+        };
+        contract.create_account_advanced(new_account(), templated_key_options(template, vec![key(2), key(3)]), None, None, None, None).unwrap();
+
+        // Every templated key is added with the shared settings, after the other limited keys
+        let added: Vec<VmAction> = get_created_receipts()[0]
+            .actions
+            .iter()
+            .filter(|action| matches!(action, VmAction::AddKeyWithFunctionCall { .. }))
+            .cloned()
+            .collect();
+        let templated_key = |public_key| VmAction::AddKeyWithFunctionCall {
+            public_key,
+            nonce: 0,
+            allowance: Some(200),
+            receiver_id: bob(),
+            function_names: vec!["claim".to_string(), "play".to_string()],
+        };
+        assert_eq!(added.len(), 3);
+        assert_eq!(added[1..], [templated_key(key(2)), templated_key(key(3))]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Limited access key at index 1 has a zero allowance")]
+    fn test_create_advanced_account_templated_zero_allowance() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        testing_env!(VMContextBuilder::new().current_account_id(linkdrop()).context.clone());
+        let template = LimitedAccessKeyTemplate {
+            allowance: U128(0),
+            receiver_id: bob(),
+            method_names: "claim".to_string(),
+        };
+        contract.create_account_advanced(new_account(), templated_key_options(template, vec![key(2), key(3)]), None, None, None, None).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "Public key ed25519:4uQeVj5tqViQh7yWWGStvkEG1Zmhx6uasJtWCJziofM is added more than once")]
+    fn test_create_advanced_account_templated_duplicate_key() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        testing_env!(VMContextBuilder::new().current_account_id(linkdrop()).context.clone());
+        let template = LimitedAccessKeyTemplate {
+            allowance: U128(200),
+            receiver_id: bob(),
+            method_names: "claim".to_string(),
+        };
+        contract.create_account_advanced(new_account(), templated_key_options(template, vec![key(1)]), None, None, None, None).unwrap();
+    }
+
+    /// Options deploying the given contract and nothing else.
+    fn contract_options(contract_bytes: &[u8]) -> CreateAccountOptions {
+        CreateAccountOptions {
+            full_access_keys: None,
+            full_access_key_nonce: None,
+            limited_access_keys: None,
+            templated_access_keys: None,
+            contract_bytes: Some(contract_bytes.to_vec()),
+            deploy_init: None,
+            stake: None,
+        }
+    }
+
+    /// Options adding no key, for the creations that only deploy a contract.
+    fn no_keys() -> CreateAccountOptions {
+        CreateAccountOptions {
+            full_access_keys: None,
+            full_access_key_nonce: None,
+            limited_access_keys: None,
+            templated_access_keys: None,
+            contract_bytes: None,
+            deploy_init: None,
+            stake: None,
+        }
+    }
+
+    /// Create `new_account()` with `contract_bytes` deployed to it and no keys.
+    fn create_with_contract(contract: &mut LinkDrop, contract_bytes: &[u8]) -> Result<Promise, ContractError> {
+        let keys = no_keys();
+        contract.create_account_with_contract(new_account(), Base64VecU8(contract_bytes.to_vec()), None, keys)
+    }
+
+    fn code_hash(contract_bytes: &[u8]) -> Base58CryptoHash {
+        CryptoHash::try_from(env::sha256(contract_bytes)).unwrap().into()
+    }
+
+    #[test]
+    fn test_create_advanced_account_refuses_contract() {
+        testing_env!(VMContextBuilder::new().current_account_id(linkdrop()).context.clone());
+        let mut contract = LinkDrop::new(owner(), None);
+
+        let result = contract.create_account_advanced(new_account(), contract_options(b"contract"), None, None, None, None);
+        assert_eq!(
+            result.err(),
+            Some(ContractError::InvalidArgument(
+                "Cannot deploy a contract with create_account_advanced, use create_account_with_contract".to_string()
+            ))
+        );
+        assert!(get_created_receipts().is_empty());
+    }
+
+    #[test]
+    fn test_create_account_with_contract() {
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .attached_deposit(1_000)
+            .context.clone()
+        );
+        let mut contract = LinkDrop::new(owner(), None);
+
+        // The keys are added along with the contract
+        let contract_bytes = Base64VecU8(b"contract".to_vec());
+        contract.create_account_with_contract(new_account(), contract_bytes, None, full_key_options(vec![key(1)])).unwrap();
+        let actions = &get_created_receipts()[0].actions;
+        assert!(actions.contains(&VmAction::DeployContract { code: b"contract".to_vec() }));
+        assert!(actions.iter().any(|action| matches!(action, VmAction::AddKeyWithFullAccess { .. })));
+        assert!(contract.is_creation_in_flight(new_account()));
+    }
+
+    #[test]
+    fn test_create_account_with_contract_in_keys() {
+        testing_env!(VMContextBuilder::new().current_account_id(linkdrop()).context.clone());
+        let mut contract = LinkDrop::new(owner(), None);
+
+        let result = contract.create_account_with_contract(
+            new_account(),
+            Base64VecU8(b"contract".to_vec()),
+            None,
+            contract_options(b"other"),
+        );
+        assert_eq!(
+            result.err(),
+            Some(ContractError::InvalidArgument(
+                "Pass the contract and its init call as arguments, not in the keys".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_create_account_with_oversized_contract() {
+        testing_env!(VMContextBuilder::new().current_account_id(linkdrop()).context.clone());
+        let mut contract = LinkDrop::new(owner(), None);
+
+        assert_eq!(
+            create_with_contract(&mut contract, &vec![0; DEFAULT_MAX_CONTRACT_SIZE as usize + 1]).err(),
+            Some(ContractError::InvalidArgument(format!(
+                "Contract is {} bytes long, at most {} bytes are allowed",
+                DEFAULT_MAX_CONTRACT_SIZE + 1,
+                DEFAULT_MAX_CONTRACT_SIZE
+            )))
+        );
+    }
+
+    #[test]
+    fn test_create_advanced_account_allowed_contract() {
+        testing_env!(VMContextBuilder::new().current_account_id(linkdrop()).context.clone());
+        let mut contract = LinkDrop::new(owner(), Some(vec![code_hash(b"allowed")]));
+
+        create_with_contract(&mut contract, b"allowed").unwrap();
+
+        assert!(get_created_receipts()[0]
+            .actions
+            .contains(&VmAction::DeployContract { code: b"allowed".to_vec() }));
+    }
+
+    #[test]
+    #[should_panic(expected = "Contract is not in the allowed contract hashes")]
+    fn test_create_advanced_account_disallowed_contract() {
+        testing_env!(VMContextBuilder::new().current_account_id(linkdrop()).context.clone());
+        let mut contract = LinkDrop::new(owner(), Some(vec![code_hash(b"allowed")]));
+
+        create_with_contract(&mut contract, b"disallowed").unwrap();
+    }
+
+    #[test]
+    fn test_create_advanced_account_empty_allowlist() {
+        testing_env!(VMContextBuilder::new().current_account_id(linkdrop()).context.clone());
+        let mut contract = LinkDrop::new(owner(), None);
+
+        // Any contract can be deployed while nothing is allowlisted
+        create_with_contract(&mut contract, b"anything").unwrap();
+
+        assert!(get_created_receipts()[0]
+            .actions
+            .contains(&VmAction::DeployContract { code: b"anything".to_vec() }));
+    }
+
+    #[test]
+    #[should_panic(expected = "Contract is not in the allowed contract hashes")]
+    fn test_remove_allowed_contract_hash() {
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(owner())
+            .context.clone()
+        );
+        let mut contract = LinkDrop::new(owner(), None);
+
+        contract.add_allowed_contract_hash(code_hash(b"allowed"));
+        contract.add_allowed_contract_hash(code_hash(b"removed"));
+        contract.remove_allowed_contract_hash(code_hash(b"removed"));
+
+        create_with_contract(&mut contract, b"allowed").unwrap();
+        create_with_contract(&mut contract, b"removed").unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the owner can call this method")]
+    fn test_add_allowed_contract_hash_not_owner() {
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob())
+            .context.clone()
+        );
+        let mut contract = LinkDrop::new(owner(), None);
+
+        contract.add_allowed_contract_hash(code_hash(b"allowed"));
+    }
+
+    #[test]
+    fn test_delete_key() {
+        let mut contract = LinkDrop::new(owner(), None);
+        register_storage(&mut contract);
+
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob())
+            .attached_deposit(MIN_SEND_DEPOSIT)
+            .context.clone()
+        );
+        contract.send(funding_key(), None, None, None, None, None, None).unwrap();
+
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(owner())
+            .context.clone()
+        );
+        contract.delete_key(funding_key(), bob()).unwrap();
+
+        // The drop is gone and its balance goes to the recipient
+        let balance = MIN_SEND_DEPOSIT - ACCESS_KEY_ALLOWANCE;
+        assert!(contract.accounts.get(&funding_key()).is_none());
+        assert_eq!(contract.get_total_locked_balance(), U128(0));
+        let receipts = get_created_receipts();
+        assert_eq!(receipts.len(), 2);
+        assert_eq!(receipts[0].receiver_id, linkdrop());
+        assert_eq!(receipts[0].actions, vec![VmAction::DeleteKey { public_key: funding_key() }]);
+        assert_eq!(receipts[1].receiver_id, bob());
+        assert_eq!(receipts[1].actions, vec![VmAction::Transfer { deposit: balance }]);
+    }
+
+    #[test]
+    fn test_delete_missing_key() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(owner())
+            .context.clone()
+        );
+        let result = contract.delete_key(funding_key(), bob());
+        assert_eq!(result.err(), Some(ContractError::KeyMissing));
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the owner can call this method")]
+    fn test_delete_key_not_owner() {
+        let mut contract = LinkDrop::new(owner(), None);
+        contract.accounts.insert(&funding_key(), &drop_info(1_000));
+
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob())
+            .context.clone()
+        );
+        contract.delete_key(funding_key(), bob()).unwrap();
+    }
+
+    #[test]
+    fn test_send_records_funder() {
+        let mut contract = LinkDrop::new(owner(), None);
+        register_storage(&mut contract);
+
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob())
+            .attached_deposit(MIN_SEND_DEPOSIT)
+            .context.clone()
+        );
+        contract.send(funding_key(), None, None, None, None, None, None).unwrap();
+
+        let info = contract.get_key_information(funding_key()).unwrap();
+        assert_eq!(info.funder_id, bob());
+        assert_eq!(info.balance, U128(MIN_SEND_DEPOSIT - ACCESS_KEY_ALLOWANCE));
+    }
+
+    #[test]
+    #[should_panic(expected = "Key is already funded by another account")]
+    fn test_send_other_funder() {
+        let mut contract = LinkDrop::new(owner(), None);
+        contract.accounts.insert(&funding_key(), &drop_info(1_000));
+
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(owner())
+            .attached_deposit(MIN_SEND_DEPOSIT)
+            .context.clone()
+        );
+        contract.send(funding_key(), None, None, None, None, None, None).unwrap();
+    }
+
+    #[test]
+    fn test_failed_claim_restores_funder() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        callback_env(PromiseResult::Failed);
+        contract.on_account_created_and_claimed(
+            new_account(),
+            funding_key(),
+            DropInfo { funder_id: owner(), ..drop_info(1_000) },
+            true,
+            None,
+            true,
+            false,
+        );
+
+        let info = contract.get_key_information(funding_key()).unwrap();
+        assert_eq!(info.funder_id, owner());
+        assert_eq!(info.balance, U128(1_000));
+    }
+
+    /// Fund the funding key as bob with a linkdrop expiring at `expires_at`.
+    fn send_expiring(contract: &mut LinkDrop, expires_at: u64) {
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob())
+            .attached_deposit(MIN_SEND_DEPOSIT)
+            .context.clone()
+        );
+        contract.send(funding_key(), Some(U64(expires_at)), None, None, None, None, None).unwrap();
+    }
+
+    /// Sign a claim with the funding key at the given block timestamp.
+    fn claim_at(contract: &mut LinkDrop, block_timestamp: u64) {
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .signer_account_pk(funding_key())
+            .block_timestamp(block_timestamp)
+            .context.clone()
+        );
+        contract.create_account_and_claim(new_account(), key(1), None, None).unwrap();
+    }
+
+    /// Reclaim the linkdrop of the funding key as `predecessor` at the given block timestamp.
+    fn reclaim_at(contract: &mut LinkDrop, predecessor: AccountId, block_timestamp: u64) {
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(predecessor)
+            .block_timestamp(block_timestamp)
+            .context.clone()
+        );
+        contract.reclaim_expired(funding_key()).unwrap();
+    }
+
+    #[test]
+    fn test_claim_before_expiration() {
+        let mut contract = LinkDrop::new(owner(), None);
+        register_storage(&mut contract);
+        send_expiring(&mut contract, 1_000);
+
+        // Claimable up to and including the expiration timestamp
+        claim_at(&mut contract, 1_000);
+        assert!(contract.accounts.get(&funding_key()).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "Linkdrop has expired")]
+    fn test_claim_after_expiration() {
+        let mut contract = LinkDrop::new(owner(), None);
+        register_storage(&mut contract);
+        send_expiring(&mut contract, 1_000);
+
+        claim_at(&mut contract, 1_001);
+    }
+
+    #[test]
+    fn test_claim_never_expires() {
+        let mut contract = LinkDrop::new(owner(), None);
+        register_storage(&mut contract);
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob())
+            .attached_deposit(MIN_SEND_DEPOSIT)
+            .context.clone()
+        );
+        contract.send(funding_key(), None, None, None, None, None, None).unwrap();
+
+        claim_at(&mut contract, u64::MAX);
+        assert!(contract.accounts.get(&funding_key()).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "Expiration must be in the future")]
+    fn test_send_expired() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .attached_deposit(MIN_SEND_DEPOSIT)
+            .block_timestamp(1_000)
+            .context.clone()
+        );
+        contract.send(funding_key(), Some(U64(1_000)), None, None, None, None, None).unwrap();
+    }
+
+    #[test]
+    fn test_reclaim_expired() {
+        let mut contract = LinkDrop::new(owner(), None);
+        register_storage(&mut contract);
+        send_expiring(&mut contract, 1_000);
+
+        reclaim_at(&mut contract, bob(), 1_001);
+
+        // The funder gets the balance back
+        assert!(contract.accounts.get(&funding_key()).is_none());
+        assert_eq!(contract.get_total_locked_balance(), U128(0));
+        let receipts = get_created_receipts();
+        assert_eq!(receipts[1].receiver_id, bob());
+        assert_eq!(
+            receipts[1].actions,
+            vec![VmAction::Transfer { deposit: MIN_SEND_DEPOSIT - ACCESS_KEY_ALLOWANCE }]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Linkdrop has not expired")]
+    fn test_reclaim_not_expired() {
+        let mut contract = LinkDrop::new(owner(), None);
+        register_storage(&mut contract);
+        send_expiring(&mut contract, 1_000);
+
+        reclaim_at(&mut contract, bob(), 1_000);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the funder can reclaim the linkdrop")]
+    fn test_reclaim_expired_not_funder() {
+        let mut contract = LinkDrop::new(owner(), None);
+        register_storage(&mut contract);
+        send_expiring(&mut contract, 1_000);
+
+        reclaim_at(&mut contract, owner(), 1_001);
+    }
+
+    #[test]
+    fn test_batch_reclaim_expired() {
+        let mut contract = LinkDrop::new(owner(), None);
+        register_storage(&mut contract);
+        send_expiring(&mut contract, 1_000);
+        contract.accounts.insert(&key(1), &DropInfo { expires_at: Some(U64(2_000)), ..drop_info(500) });
+        contract.total_locked += 500;
+
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(owner())
+            .block_timestamp(1_001)
+            .context.clone()
+        );
+        assert_eq!(contract.batch_reclaim_expired(vec![funding_key(), key(1), key(2)]), Ok(1));
+        assert_eq!(
+            get_logs(),
+            vec![
+                format!("Refunded {} to {}", String::from(&funding_key()), bob()),
+                format!("Skipped {}, not expired", String::from(&key(1))),
+                format!("Skipped {}, missing", String::from(&key(2))),
+            ]
+        );
+
+        // Only the expired linkdrop is refunded to its funder
+        assert!(contract.accounts.get(&funding_key()).is_none());
+        assert!(contract.accounts.get(&key(1)).is_some());
+        assert_eq!(contract.get_total_locked_balance(), U128(500));
+        let receipts = get_created_receipts();
+        assert_eq!(receipts.len(), 2);
+        assert_eq!(receipts[0].actions, vec![VmAction::DeleteKey { public_key: funding_key() }]);
+        assert_eq!(receipts[1].receiver_id, bob());
+        assert_eq!(
+            receipts[1].actions,
+            vec![VmAction::Transfer { deposit: MIN_SEND_DEPOSIT - ACCESS_KEY_ALLOWANCE }]
+        );
+    }
+
+    #[test]
+    fn test_batch_reclaim_expired_not_owner() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        testing_env!(VMContextBuilder::new().predecessor_account_id(bob()).context.clone());
+        assert_eq!(
+            contract.batch_reclaim_expired(vec![funding_key()]),
+            Err(ContractError::Unauthorized("Only the owner can call this method"))
+        );
+
+        // Nor can the owner alone while the council confirms privileged operations
+        contract.required_confirmations = 1;
+        testing_env!(VMContextBuilder::new().predecessor_account_id(owner()).context.clone());
+        assert_eq!(
+            contract.batch_reclaim_expired(vec![funding_key()]),
+            Err(ContractError::Unauthorized(
+                "This operation needs the confirmation of the council, propose it with add_request"
+            ))
+        );
+    }
+
+    #[test]
+    fn test_batch_reclaim_expired_too_many() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        testing_env!(VMContextBuilder::new().predecessor_account_id(owner()).context.clone());
+        assert_eq!(
+            contract.batch_reclaim_expired((0..21).map(key).collect()),
+            Err(ContractError::InvalidArgument("Cannot reclaim more than 20 linkdrops at once".to_string()))
+        );
+    }
+
+    fn create_request(new_account_id: &str, deposit: Balance) -> CreateAccountRequest {
+        CreateAccountRequest {
+            new_account_id: new_account_id.parse().unwrap(),
+            options: CreateAccountOptions {
+                full_access_keys: Some(vec![funding_key()]),
+                full_access_key_nonce: None,
+                limited_access_keys: None,
+                templated_access_keys: None,
+                contract_bytes: None,
+                deploy_init: None,
+                stake: None,
+            },
+            deposit: U128(deposit),
+        }
+    }
+
+    #[test]
+    fn test_create_accounts_duplicate() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        testing_env!(VMContextBuilder::new().current_account_id(linkdrop()).attached_deposit(300).context.clone());
+        assert_eq!(
+            contract.create_accounts(vec![create_request("alice.linkdrop", 100), create_request("alice.linkdrop", 200)]).err(),
+            Some(ContractError::InvalidArgument("Account alice.linkdrop is requested more than once".to_string()))
+        );
+        assert!(get_created_receipts().is_empty());
+        assert!(!contract.is_creation_in_flight("alice.linkdrop".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_create_accounts_refuses_contract() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        testing_env!(VMContextBuilder::new().current_account_id(linkdrop()).attached_deposit(300).context.clone());
+        let deploying = CreateAccountRequest { options: contract_options(b"contract"), ..create_request("carol.linkdrop", 200) };
+        assert_eq!(
+            contract.create_accounts(vec![create_request("alice.linkdrop", 100), deploying]).err(),
+            Some(ContractError::InvalidArgument(
+                "Cannot deploy a contract with create_accounts, use create_account_with_contract".to_string()
+            ))
+        );
+        assert!(get_created_receipts().is_empty());
+    }
+
+    #[test]
+    fn test_create_accounts() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob())
+            .attached_deposit(300)
+            .context.clone()
+        );
+        contract.create_accounts(vec![create_request("alice.linkdrop", 100), create_request("carol.linkdrop", 200)]).unwrap();
+
+        // Each account is created with its own deposit and callback
+        let receipts = get_created_receipts();
+        for (account_id, deposit) in [("alice.linkdrop", 100), ("carol.linkdrop", 200)] {
+            let account_id: AccountId = account_id.parse().unwrap();
+            let creation_receipt = receipts.iter().find(|receipt| receipt.receiver_id == account_id).unwrap();
+            assert!(creation_receipt.actions.contains(&VmAction::Transfer { deposit }));
+        }
+        let callbacks: Vec<_> = receipts
+            .iter()
+            .flat_map(|receipt| receipt.actions.iter())
+            .filter_map(|action| match action {
+                VmAction::FunctionCall { function_name, .. } => Some(function_name.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(callbacks, vec!["on_account_created", "on_account_created", "on_accounts_created"]);
+    }
+
+    #[test]
+    fn test_create_accounts_partial_failure() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        // Only the failed creation is refunded
+        callback_env(PromiseResult::Successful(vec![]));
+        let outcome = created_outcome(contract.on_account_created("alice.linkdrop".parse().unwrap(), bob(), bob(), U128(100), None, None, None));
+        assert_eq!(outcome.refunded, U128(0));
+        callback_env(PromiseResult::Failed);
+        contract.on_account_created("carol.linkdrop".parse().unwrap(), bob(), bob(), U128(200), None, None, None);
+        assert_eq!(refunded_outcome().refunded, U128(200));
+        assert_eq!(
+            get_created_receipts()[0].actions,
+            vec![VmAction::Transfer { deposit: 200 }]
+        );
+    }
+
+    #[test]
+    fn test_on_accounts_created() {
+        let contract = LinkDrop::new(owner(), None);
+        let created = CreationOutcome {
+            success: true,
+            new_account_id: "alice.linkdrop".parse().unwrap(),
+            refunded: U128(0),
+            gas_burnt: U64(1_000_000),
+        };
+        let refunded = CreationOutcome {
+            success: false,
+            new_account_id: "carol.linkdrop".parse().unwrap(),
+            refunded: U128(200),
+            gas_burnt: U64(1_000_000),
+        };
+
+        testing_env!(
+            VMContextBuilder::new()
+                .current_account_id(linkdrop())
+                .predecessor_account_id(linkdrop())
+                .context.clone(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![
+                PromiseResult::Successful(serde_json::to_vec(&created).unwrap()),
+                PromiseResult::Successful(serde_json::to_vec(&refunded).unwrap()),
+                PromiseResult::Failed,
+            ]
+        );
+        assert_eq!(contract.on_accounts_created(), vec![Some(created), Some(refunded), None]);
+    }
+
+    #[test]
+    fn test_create_account_and_resolve() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob())
+            .attached_deposit(1_000)
+            .context.clone()
+        );
+        contract.create_account_and_resolve(new_account(), full_key_options(vec![key(1)])).unwrap();
+
+        // The outcome of the creation goes through the resolve callback
+        let callbacks: Vec<_> = get_created_receipts()
+            .into_iter()
+            .flat_map(|receipt| receipt.actions)
+            .filter_map(|action| match action {
+                VmAction::FunctionCall { function_name, gas, .. } => Some((function_name, gas)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(callbacks[0].0, "on_account_created");
+        assert_eq!(callbacks[1], ("create_account_resolve".to_string(), CREATE_ACCOUNT_RESOLVE_GAS));
+    }
+
+    #[test]
+    fn test_create_account_resolve() {
+        let contract = LinkDrop::new(owner(), None);
+        let outcome = |success| CreationOutcome {
+            success,
+            new_account_id: new_account(),
+            refunded: U128(if success { 0 } else { 1_000 }),
+            gas_burnt: U64(1_000_000),
+        };
+
+        for (result, created) in [
+            (PromiseResult::Successful(serde_json::to_vec(&outcome(true)).unwrap()), true),
+            (PromiseResult::Successful(serde_json::to_vec(&outcome(false)).unwrap()), false),
+            (PromiseResult::Failed, false),
+        ] {
+            callback_env(result);
+            assert!(matches!(contract.create_account_resolve(), PromiseOrValue::Value(value) if value == created));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "The deposits of the requests must add up to the attached deposit")]
+    fn test_create_accounts_deposit_mismatch() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .attached_deposit(250)
+            .context.clone()
+        );
+        contract.create_accounts(vec![create_request("alice.linkdrop", 100), create_request("carol.linkdrop", 200)]).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot create more than 10 accounts at once")]
+    fn test_create_accounts_too_many() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .attached_deposit(11)
+            .context.clone()
+        );
+        let requests = (0..=MAX_CREATE_ACCOUNTS_BATCH)
+            .map(|index| create_request(&format!("account{}.linkdrop", index), 1))
+            .collect();
+        contract.create_accounts(requests).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot create several accounts at once while a cooldown is set")]
+    fn test_create_accounts_during_cooldown() {
+        let mut contract = LinkDrop::new(owner(), None);
+        testing_env!(VMContextBuilder::new().predecessor_account_id(owner()).context.clone());
+        contract.set_per_account_cooldown(U64(100)).unwrap();
+
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .attached_deposit(300)
+            .context.clone()
+        );
+        contract.create_accounts(vec![create_request("alice.linkdrop", 100), create_request("carol.linkdrop", 200)]).unwrap();
+    }
+
+    #[test]
+    fn test_compute_callback_gas() {
+        let keys_only = CreateAccountOptions {
+            full_access_keys: Some(vec![key(1), key(2)]),
+            full_access_key_nonce: None,
+            limited_access_keys: None,
+            templated_access_keys: None,
+            contract_bytes: None,
+            deploy_init: None,
+            stake: None,
+        };
+        let with_deploy = CreateAccountOptions {
+            full_access_keys: Some(vec![key(1), key(2)]),
+            full_access_key_nonce: None,
+            limited_access_keys: None,
+            templated_access_keys: None,
+            contract_bytes: Some(vec![0; 10]),
+            deploy_init: None,
+            stake: None,
+        };
+        let keys_only_gas = compute_callback_gas(&keys_only);
+        assert!(keys_only_gas >= MIN_CALLBACK_GAS);
+        assert!(keys_only_gas < compute_callback_gas(&with_deploy));
+        assert!(keys_only_gas < ON_CREATE_ACCOUNT_CALLBACK_GAS);
+    }
+
+    fn rotated_keys() -> CreateAccountOptions {
+        CreateAccountOptions {
+            full_access_keys: Some(vec![key(1), key(2)]),
+            full_access_key_nonce: None,
+            limited_access_keys: Some(vec![limited_key(key(3))]),
+            templated_access_keys: None,
+            contract_bytes: Some(vec![0; 10]),
+            deploy_init: None,
+            stake: None,
+        }
+    }
+
+    #[test]
+    fn test_add_keys() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        for predecessor_account_id in [bob(), owner()] {
+            testing_env!(
+                VMContextBuilder::new()
+                .current_account_id(linkdrop())
+                .predecessor_account_id(predecessor_account_id)
+                .context.clone()
+            );
+            contract.add_keys(bob(), rotated_keys()).unwrap();
+
+            // The keys are added to the existing account, without creating or deploying anything
+            let receipts = get_created_receipts();
+            let keys_receipt = receipts.iter().find(|receipt| receipt.receiver_id == bob()).unwrap();
+            assert_eq!(keys_receipt.actions.len(), 3);
+            assert!(keys_receipt.actions.iter().all(|action| matches!(
+                action,
+                VmAction::AddKeyWithFullAccess { .. } | VmAction::AddKeyWithFunctionCall { .. }
+            )));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the account itself or the owner can add keys to it")]
+    fn test_add_keys_unauthorized() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(new_account())
+            .context.clone()
+        );
+        contract.add_keys(bob(), rotated_keys()).unwrap();
+    }
+
+    #[test]
+    fn test_add_keys_to_contract() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        // Not even the owner can add a key controlling the locked balances
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(owner())
+            .context.clone()
+        );
+        assert_eq!(
+            contract.add_keys(linkdrop(), rotated_keys()).err(),
+            Some(ContractError::Unauthorized("Cannot add keys to this contract"))
+        );
+        assert!(get_created_receipts().is_empty());
+    }
+
+    #[test]
+    fn test_add_keys_with_nonce() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob())
+            .context.clone()
+        );
+        let options = CreateAccountOptions {
+            full_access_key_nonce: Some(U64(7)),
+            limited_access_keys: Some(vec![LimitedAccessKey { nonce: Some(U64(9)), ..limited_key(key(3)) }]),
+            ..rotated_keys()
+        };
+        contract.add_keys(bob(), options).unwrap();
+
+        // Every key starts at the given nonce
+        let receipts = get_created_receipts();
+        let keys_receipt = receipts.iter().find(|receipt| receipt.receiver_id == bob()).unwrap();
+        assert_eq!(
+            keys_receipt.actions[..2],
+            [
+                VmAction::AddKeyWithFullAccess { public_key: key(1), nonce: 7 },
+                VmAction::AddKeyWithFullAccess { public_key: key(2), nonce: 7 },
+            ]
+        );
+        assert!(matches!(
+            &keys_receipt.actions[2],
+            VmAction::AddKeyWithFunctionCall { public_key, nonce: 9, .. } if public_key == &key(3)
+        ));
+    }
+
+    #[test]
+    fn test_on_keys_added() {
+        let contract = LinkDrop::new(owner(), None);
+
+        callback_env(PromiseResult::Successful(vec![]));
+        assert!(contract.on_keys_added(bob()));
+        assert_eq!(get_logs(), vec!["Added keys to bob"]);
+
+        callback_env(PromiseResult::Failed);
+        assert!(!contract.on_keys_added(bob()));
+        assert_eq!(get_logs(), vec!["Failed to add keys to bob"]);
+    }
+
+    #[test]
+    fn test_change_key() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob())
+            .context.clone()
+        );
+        contract.change_key(bob(), key(1), key(2)).unwrap();
+
+        // Both actions go in the same receipt, so they succeed or fail together
+        let receipts = get_created_receipts();
+        let key_receipt = receipts.iter().find(|receipt| receipt.receiver_id == bob()).unwrap();
+        assert_eq!(
+            key_receipt.actions,
+            vec![
+                VmAction::AddKeyWithFullAccess { public_key: key(2), nonce: 0 },
+                VmAction::DeleteKey { public_key: key(1) },
+            ]
+        );
+
+        callback_env(PromiseResult::Failed);
+        assert!(!contract.on_key_changed(bob()));
+        assert_eq!(get_logs(), vec!["Failed to change the key of bob"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the account itself can change its keys")]
+    fn test_change_key_unauthorized() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        // Not even the owner
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(owner())
+            .context.clone()
+        );
+        contract.change_key(bob(), key(1), key(2)).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "The new key must differ from the old one")]
+    fn test_change_key_to_itself() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob())
+            .context.clone()
+        );
+        contract.change_key(bob(), key(1), key(1)).unwrap();
+    }
+
+    #[test]
+    fn test_create_advanced_account_secp256k1_key() {
+        let mut contract = LinkDrop::new(owner(), None);
+        let secp256k1_key = PublicKey::try_from([vec![1], vec![1; 64]].concat()).unwrap();
+
+        testing_env!(VMContextBuilder::new().current_account_id(linkdrop()).context.clone());
+        contract.create_account_advanced(new_account(), full_key_options(vec![secp256k1_key.clone()]), None, None, None, None).unwrap();
+        assert!(get_created_receipts()[0]
+            .actions
+            .contains(&VmAction::AddKeyWithFullAccess { public_key: secp256k1_key, nonce: 0 }));
+    }
+
+    #[test]
+    fn test_creation_in_flight() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        for result in [PromiseResult::Successful(vec![]), PromiseResult::Failed] {
+            testing_env!(
+                VMContextBuilder::new()
+                .current_account_id(linkdrop())
+                .predecessor_account_id(bob())
+                .attached_deposit(1_000)
+                .context.clone()
+            );
+            contract.create_account_advanced(new_account(), full_key_options(vec![key(1)]), None, None, None, None).unwrap();
+            assert!(contract.is_creation_in_flight(new_account()));
+
+            // The callback settles the creation whatever its outcome
+            callback_env(result);
+            drop(contract.on_account_created(new_account(), bob(), bob(), U128(1_000), None, None, None));
+            assert!(!contract.is_creation_in_flight(new_account()));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Account bob.linkdrop is already being created")]
+    fn test_create_advanced_account_in_flight() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob())
+            .attached_deposit(1_000)
+            .context.clone()
+        );
+        contract.create_account_advanced(new_account(), full_key_options(vec![key(1)]), None, None, None, None).unwrap();
+        contract.create_account_advanced(new_account(), full_key_options(vec![key(1)]), None, None, None, None).unwrap();
+    }
+
+    #[test]
+    fn test_create_advanced_account_returns_promise() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob())
+            .attached_deposit(1_000)
+            .context.clone()
+        );
+        let result = contract.create_account_advanced(new_account(), full_key_options(vec![key(1)]), None, None, None, None);
+        assert!(matches!(result, Ok(PromiseOrValue::Promise(_))));
+    }
+
+    #[test]
+    fn test_create_advanced_account_already_exists() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        for new_account_id in [linkdrop(), new_account()] {
+            testing_env!(
+                VMContextBuilder::new()
+                .current_account_id(linkdrop())
+                .predecessor_account_id(new_account())
+                .attached_deposit(1_000)
+                .context.clone()
+            );
+            let result = contract.create_account_advanced(new_account_id.clone(), full_key_options(vec![key(1)]), None, None, None, None);
+
+            // Nothing is created and the deposit goes straight back
+            assert!(matches!(result, Ok(PromiseOrValue::Value(false))));
+            assert_eq!(get_logs(), vec![format!("Account {} already exists", new_account_id)]);
+            let receipts = get_created_receipts();
+            assert_eq!(receipts.len(), 1);
+            assert_eq!(receipts[0].receiver_id, new_account());
+            assert_eq!(receipts[0].actions, vec![VmAction::Transfer { deposit: 1_000 }]);
+        }
+    }
+
+    #[test]
+    fn test_get_key_balance() {
+        let mut contract = LinkDrop::new(owner(), None);
+        contract.accounts.insert(&funding_key(), &drop_info(1_000));
+
+        assert_eq!(contract.get_key_balance(funding_key()), Some(U128(1_000)));
+        assert_eq!(contract.get_key_balance(key(1)), None);
+        assert_eq!(contract.get_key_balance_or_panic(funding_key()), U128(1_000));
+    }
+
+    #[test]
+    #[should_panic(expected = "Key is missing")]
+    fn test_get_key_balance_or_panic_missing_key() {
+        let contract = LinkDrop::new(owner(), None);
+
+        contract.get_key_balance_or_panic(key(1));
+    }
+
+    #[test]
+    fn test_get_key_status() {
+        let mut contract = LinkDrop::new(owner(), None);
+        contract.accounts.insert(&funding_key(), &drop_info(1_000));
+
+        assert_eq!(contract.get_key_status(funding_key()), KeyStatus::Funded { balance: U128(1_000) });
+        assert_eq!(contract.get_key_status(key(1)), KeyStatus::NotFound);
+        assert_eq!(
+            serde_json::to_value(contract.get_key_status(funding_key())).unwrap(),
+            json!({ "status": "funded", "balance": "1000" })
+        );
+        assert_eq!(
+            serde_json::to_value(contract.get_key_status(key(1))).unwrap(),
+            json!({ "status": "not_found" })
+        );
+    }
+
+    /// The yoctoNEAR$ a claim must send to create an account with a full access key.
+    fn claim_storage_cost() -> Balance {
+        account_storage_bytes(&full_key_options(vec![funding_key()])) as Balance * env::storage_byte_cost()
+    }
+
+    #[test]
+    fn test_can_claim() {
+        testing_env!(VMContextBuilder::new().current_account_id(linkdrop()).context.clone());
+        let mut contract = LinkDrop::new(owner(), None);
+        contract.accounts.insert(&funding_key(), &drop_info(claim_storage_cost() + 1));
+
+        assert_eq!(
+            contract.can_claim(funding_key(), new_account()),
+            ClaimFeasibility {
+                feasible: true,
+                claimable: U128(claim_storage_cost() + 1),
+                required: U128(claim_storage_cost()),
+                shortfall: U128(0),
+                reason: None,
+            }
+        );
+        // Only direct subaccounts of this contract can claim it
+        let feasibility = contract.can_claim(funding_key(), "bob.near".parse().unwrap());
+        assert!(!feasibility.feasible);
+        assert_eq!(feasibility.reason, Some("Cannot create bob.near: it must be a direct subaccount of linkdrop".to_string()));
+    }
+
+    #[test]
+    fn test_can_claim_shortfall() {
+        testing_env!(VMContextBuilder::new().current_account_id(linkdrop()).context.clone());
+        let mut contract = LinkDrop::new(owner(), None);
+        // Only one of the two uses is claimed at once
+        let drop = DropInfo { uses_remaining: 2, amount_per_use: U128(claim_storage_cost() - 100), ..drop_info(2 * claim_storage_cost() - 200) };
+        contract.accounts.insert(&funding_key(), &drop);
+
+        let feasibility = contract.can_claim(funding_key(), new_account());
+        assert!(!feasibility.feasible);
+        assert_eq!(feasibility.claimable, U128(claim_storage_cost() - 100));
+        assert_eq!(feasibility.shortfall, U128(100));
+        assert_eq!(feasibility.reason, None);
+    }
+
+    #[test]
+    fn test_simulate_claim() {
+        testing_env!(VMContextBuilder::new().current_account_id(linkdrop()).context.clone());
+        let mut contract = LinkDrop::new(owner(), None);
+        contract.accounts.insert(&funding_key(), &drop_info(1_000));
+
+        assert_eq!(
+            contract.simulate_claim(funding_key(), new_account(), None),
+            ClaimSimulation {
+                valid: true,
+                amount: U128(1_000),
+                beneficiary_amount: U128(0),
+                ft_storage_reserve: U128(0),
+                reason: None,
+            }
+        );
+        // Only the next use of a multi-use linkdrop is claimed
+        contract.accounts.insert(&key(1), &multi_use_drop(3_000, 3));
+        assert_eq!(contract.simulate_claim(key(1), new_account(), None).amount, U128(1_000));
+    }
+
+    #[test]
+    fn test_simulate_claim_deductions() {
+        testing_env!(VMContextBuilder::new().current_account_id(linkdrop()).context.clone());
+        let mut contract = LinkDrop::new(owner(), None);
+        let ft = FtDropData { contract_id: bob(), amount: U128(10) };
+        contract.accounts.insert(&funding_key(), &DropInfo { ft: Some(ft), ..drop_info(FT_STORAGE_DEPOSIT + 1_000) });
+
+        // The FT registration and the beneficiary share come out of the claim
+        assert_eq!(
+            contract.simulate_claim(funding_key(), new_account(), Some((bob(), U128(100)))),
+            ClaimSimulation {
+                valid: true,
+                amount: U128(900),
+                beneficiary_amount: U128(100),
+                ft_storage_reserve: U128(FT_STORAGE_DEPOSIT),
+                reason: None,
+            }
+        );
+        let simulation = contract.simulate_claim(funding_key(), new_account(), Some((bob(), U128(1_001))));
+        assert!(!simulation.valid);
+        assert_eq!(
+            simulation.reason,
+            Some("The beneficiary amount of 1001 yoctoNEAR exceeds the claimed balance of 1000 yoctoNEAR".to_string())
+        );
+    }
+
+    #[test]
+    fn test_simulate_expired_claim() {
+        testing_env!(VMContextBuilder::new().current_account_id(linkdrop()).block_timestamp(100).context.clone());
+        let mut contract = LinkDrop::new(owner(), None);
+        contract.accounts.insert(&funding_key(), &DropInfo { expires_at: Some(U64(99)), ..drop_info(1_000) });
+
+        assert_eq!(
+            contract.simulate_claim(funding_key(), new_account(), None),
+            ClaimSimulation {
+                valid: false,
+                amount: U128(0),
+                beneficiary_amount: U128(0),
+                ft_storage_reserve: U128(0),
+                reason: Some("Linkdrop has expired".to_string()),
+            }
+        );
+    }
+
+    #[test]
+    fn test_can_claim_expired_or_exhausted() {
+        testing_env!(VMContextBuilder::new().current_account_id(linkdrop()).block_timestamp(100).context.clone());
+        let mut contract = LinkDrop::new(owner(), None);
+        contract.accounts.insert(&key(1), &DropInfo { expires_at: Some(U64(99)), ..drop_info(claim_storage_cost()) });
+        contract.accounts.insert(&key(2), &DropInfo { uses_remaining: 0, ..drop_info(claim_storage_cost()) });
+
+        for (public_key, reason) in [
+            (key(1), "Linkdrop has expired"),
+            (key(2), "Linkdrop has no uses left"),
+            (key(3), "Key is missing"),
+        ] {
+            let feasibility = contract.can_claim(public_key, new_account());
+            assert!(!feasibility.feasible);
+            assert_eq!(feasibility.claimable, U128(0));
+            assert_eq!(feasibility.reason, Some(reason.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_create_advanced_account_checked() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob())
+            .attached_deposit(1_000)
+            .context.clone()
+        );
+        contract.create_account_advanced_checked(new_account(), full_key_options(vec![key(1)])).unwrap();
+
+        // Only the probe goes out before the callback
+        let receipts = get_created_receipts();
+        assert_eq!(receipts.len(), 2);
+        assert_eq!(receipts[0].receiver_id, new_account());
+        assert_eq!(receipts[0].actions, vec![VmAction::Transfer { deposit: 0 }]);
+        assert!(matches!(
+            &receipts[1].actions[..],
+            [VmAction::FunctionCall { function_name, .. }] if function_name == "on_account_checked"
+        ));
+    }
+
+    #[test]
+    fn test_create_advanced_account_checked_refuses_contract() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        testing_env!(VMContextBuilder::new().current_account_id(linkdrop()).attached_deposit(1_000).context.clone());
+        assert_eq!(
+            contract.create_account_advanced_checked(new_account(), contract_options(b"contract")).err(),
+            Some(ContractError::InvalidArgument(
+                "Cannot deploy a contract with create_account_advanced_checked, use create_account_with_contract"
+                    .to_string()
+            ))
+        );
+        assert!(get_created_receipts().is_empty());
+    }
+
+    #[test]
+    fn test_create_advanced_account_checked_validates_first() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        testing_env!(VMContextBuilder::new().current_account_id(linkdrop()).context.clone());
+        let result = contract.create_account_advanced_checked(
+            new_account(),
+            CreateAccountOptions {
+                full_access_keys: None,
+                full_access_key_nonce: None,
+                limited_access_keys: None,
+                templated_access_keys: None,
+                contract_bytes: None,
+                deploy_init: None,
+                stake: None,
+            },
+        );
+        assert_eq!(result.err(), Some(ContractError::NoOptionsProvided));
+    }
+
+    #[test]
+    fn test_on_account_checked_not_found() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        // The probe failed, the account is created with the deposit
+        callback_env(PromiseResult::Failed);
+        drop(contract.on_account_checked(new_account(), contract_options(&[1, 2, 3]), bob(), U128(1_000)));
+        let receipts = get_created_receipts();
+        assert_eq!(receipts[0].receiver_id, new_account());
+        assert!(receipts[0].actions.contains(&VmAction::CreateAccount));
+        assert!(receipts[0].actions.contains(&VmAction::Transfer { deposit: 1_000 }));
+    }
+
+    #[test]
+    fn test_on_account_checked_exists() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        // The probe went through, nothing is created and the deposit goes back
+        callback_env(PromiseResult::Successful(vec![]));
+        drop(contract.on_account_checked(new_account(), contract_options(&[1, 2, 3]), bob(), U128(1_000)));
+        assert_eq!(get_logs()[0], format!("Account {} already exists", new_account()));
+        let receipts = get_created_receipts();
+        assert_eq!(receipts[0].receiver_id, bob());
+        assert_eq!(receipts[0].actions, vec![VmAction::Transfer { deposit: 1_000 }]);
+        let outcome = refunded_outcome();
+        assert_eq!(
+            outcome,
+            CreationOutcome { success: false, new_account_id: new_account(), refunded: U128(1_000), ..outcome.clone() }
+        );
+    }
+
+    #[test]
+    fn test_on_account_checked_exists_emits_event() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        // Relayers are told not to retry the same account id
+        callback_env(PromiseResult::Successful(vec![]));
+        drop(contract.on_account_checked(new_account(), contract_options(&[1, 2, 3]), bob(), U128(1_000)));
+        assert_eq!(
+            events(),
+            vec![json!({
+                "standard": "arkana_account",
+                "version": "1.1.0",
+                "event": "creation_failed",
+                "data": [{
+                    "new_account_id": "bob.linkdrop",
+                    "predecessor_account_id": "bob",
+                    "refund_to": "bob",
+                    "refunded": "1000",
+                    "reason": "account_exists",
+                }],
+            })]
+        );
+
+        // An account that doesn't exist yet is created without any failure
+        callback_env(PromiseResult::Failed);
+        drop(contract.on_account_checked(new_account(), contract_options(&[1, 2, 3]), bob(), U128(1_000)));
+        assert!(events().is_empty());
+    }
+
+    /// Fund `public_key` as `funder_id`, registering the funder's storage first.
+    fn send_as(contract: &mut LinkDrop, funder_id: AccountId, public_key: PublicKey) {
+        contract.storage_accounts.insert(&funder_id, &StorageAccount { total: 10 * drop_storage_cost(), used: 0 });
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(funder_id)
+            .attached_deposit(MIN_SEND_DEPOSIT)
+            .context.clone()
+        );
+        contract.send(public_key, None, None, None, None, None, None).unwrap();
+    }
+
+    fn funder_keys(contract: &LinkDrop, funder_id: AccountId) -> Vec<PublicKey> {
+        contract
+            .get_drops_by_funder(funder_id, None, None)
+            .into_iter()
+            .map(|info| info.public_key)
+            .collect()
+    }
+
+    #[test]
+    fn test_get_drops_by_funder() {
+        let mut contract = LinkDrop::new(owner(), None);
+        send_as(&mut contract, bob(), key(1));
+        send_as(&mut contract, bob(), key(2));
+        send_as(&mut contract, owner(), key(3));
+
+        // Each funder only sees their own drops
+        assert_eq!(funder_keys(&contract, bob()), vec![key(1), key(2)]);
+        assert_eq!(funder_keys(&contract, owner()), vec![key(3)]);
+        assert!(funder_keys(&contract, new_account()).is_empty());
+
+        let drops = contract.get_drops_by_funder(bob(), Some(1), Some(1));
+        assert_eq!(drops.len(), 1);
+        assert_eq!(drops[0].public_key, key(2));
+        assert_eq!(drops[0].balance, U128(MIN_SEND_DEPOSIT - ACCESS_KEY_ALLOWANCE));
+    }
+
+    #[test]
+    fn test_drops_by_funder_follow_claims() {
+        let mut contract = LinkDrop::new(owner(), None);
+        send_as(&mut contract, bob(), funding_key());
+        send_as(&mut contract, bob(), key(1));
+
+        // A claim takes the drop out of the index, a failed one puts it back
+        callback_env(PromiseResult::Successful(vec![]));
+        contract.claim(new_account(), None).unwrap();
+        assert_eq!(funder_keys(&contract, bob()), vec![key(1)]);
+        let drop = drop_info(MIN_SEND_DEPOSIT - ACCESS_KEY_ALLOWANCE);
+        callback_env(PromiseResult::Failed);
+        contract.on_account_created_and_claimed(new_account(), funding_key(), drop, true, None, true, false);
+        assert_eq!(funder_keys(&contract, bob()), vec![key(1), funding_key()]);
+
+        // As does deleting the key
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(owner())
+            .context.clone()
+        );
+        contract.delete_key(key(1), owner()).unwrap();
+        assert_eq!(funder_keys(&contract, bob()), vec![funding_key()]);
+        contract.delete_key(funding_key(), owner()).unwrap();
+        assert!(contract.drops_by_funder.get(&bob()).is_none());
+    }
+
+    #[test]
+    fn test_create_advanced_account_refund_to() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        for (refund_to, refunded_id) in [(Some(owner()), owner()), (None, bob())] {
+            testing_env!(
+                VMContextBuilder::new()
+                .current_account_id(linkdrop())
+                .predecessor_account_id(bob())
+                .attached_deposit(1_000)
+                .context.clone()
+            );
+            contract.create_account_advanced(new_account(), full_key_options(vec![key(1)]), refund_to, None, None, None).unwrap();
+
+            // The failure callback refunds the override, or the predecessor without one
+            let receipts = get_created_receipts();
+            let args = receipts
+                .iter()
+                .flat_map(|receipt| receipt.actions.iter())
+                .find_map(|action| match action {
+                    VmAction::FunctionCall { function_name, args, .. } if function_name == "on_account_created" => Some(args),
+                    _ => None,
+                })
+                .unwrap();
+            let args: serde_json::Value = serde_json::from_slice(args).unwrap();
+            assert_eq!(args["predecessor_account_id"], json!(refunded_id));
+
+            // Settle the creation before trying again
+            callback_env(PromiseResult::Failed);
+            drop(contract.on_account_created(new_account(), refunded_id, bob(), U128(1_000), None, None, None));
+        }
+    }
+
+    #[test]
+    fn test_create_advanced_account_target_balance() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob())
+            .attached_deposit(1_000)
+            .context.clone()
+        );
+        contract
+            .create_account_advanced(new_account(), full_key_options(vec![key(1)]), None, None, None, Some(U128(600)))
+            .unwrap();
+
+        // The new account gets exactly the target, the callback is told about the excess
+        let receipts = get_created_receipts();
+        assert!(receipts[0].actions.contains(&VmAction::Transfer { deposit: 600 }));
+        let args = receipts
+            .iter()
+            .flat_map(|receipt| receipt.actions.iter())
+            .find_map(|action| match action {
+                VmAction::FunctionCall { function_name, args, .. } if function_name == "on_account_created" => Some(args),
+                _ => None,
+            })
+            .unwrap();
+        let args: serde_json::Value = serde_json::from_slice(args).unwrap();
+        assert_eq!(args["amount"], json!("600"));
+        assert_eq!(args["excess"], json!("400"));
+        // The excess can't be withdrawn while it waits for the callback
+        assert_eq!(contract.pending_refunds, 400);
+    }
+
+    #[test]
+    fn test_create_advanced_account_target_balance_above_deposit() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .attached_deposit(1_000)
+            .context.clone()
+        );
+        let result =
+            contract.create_account_advanced(new_account(), full_key_options(vec![key(1)]), None, None, None, Some(U128(1_001)));
+        assert_eq!(
+            result.err(),
+            Some(ContractError::InsufficientDeposit(
+                "The attached deposit leaves 1000 yoctoNEAR after the creation fees, below the target balance of 1001 \
+                 yoctoNEAR"
+                    .to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_on_account_created_refunds_excess() {
+        let mut contract = LinkDrop::new(owner(), None);
+        contract.pending_refunds = 800;
+
+        callback_env(PromiseResult::Successful(vec![]));
+        let outcome =
+            created_outcome(contract.on_account_created(new_account(), bob(), bob(), U128(600), None, None, Some(U128(400))));
+        assert_eq!(contract.pending_refunds, 400);
+
+        // The excess goes back to the predecessor once the account is created
+        assert_eq!(outcome.refunded, U128(400));
+        let receipts = get_created_receipts();
+        assert_eq!(receipts.len(), 1);
+        assert_eq!(receipts[0].receiver_id, bob());
+        assert_eq!(receipts[0].actions, vec![VmAction::Transfer { deposit: 400 }]);
+
+        // A failed creation refunds the excess along with the amount
+        callback_env(PromiseResult::Failed);
+        drop(contract.on_account_created(new_account(), bob(), bob(), U128(600), None, None, Some(U128(400))));
+        let receipts = get_created_receipts();
+        assert_eq!(receipts[0].receiver_id, bob());
+        assert_eq!(receipts[0].actions, vec![VmAction::Transfer { deposit: 1_000 }]);
+        assert_eq!(contract.pending_refunds, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Not enough gas to create the account, attach at least")]
+    fn test_create_advanced_account_not_enough_gas() {
+        let mut contract = LinkDrop::new(owner(), None);
+        let required_gas = estimate_creation_gas(&contract_options(&[0; 100_000]));
+
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .prepaid_gas(required_gas - Gas(1))
+            .context.clone()
+        );
+        create_with_contract(&mut contract, &[0; 100_000]).unwrap();
+    }
+
+    #[test]
+    fn test_estimate_creation_gas() {
+        let small = estimate_creation_gas(&contract_options(&[0; 10]));
+        let large = estimate_creation_gas(&contract_options(&[0; 100_000]));
+        assert_eq!(large.0 - small.0, CREATION_GAS_PER_CONTRACT_BYTE.0 * (100_000 - 10));
+
+        // The estimate is what get_account_creation_cost reports
+        let contract = LinkDrop::new(owner(), None);
+        assert_eq!(contract.get_account_creation_cost(contract_options(&[0; 10])).gas, U64(small.0));
+
+        // And enough gas passes the check
+        let mut contract = LinkDrop::new(owner(), None);
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .prepaid_gas(large)
+            .context.clone()
+        );
+        create_with_contract(&mut contract, &[0; 100_000]).unwrap();
+    }
+
+    /// Fund the funding key as bob with the given metadata.
+    fn send_with_metadata(contract: &mut LinkDrop, metadata: String) {
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob())
+            .attached_deposit(MIN_SEND_DEPOSIT)
+            .context.clone()
+        );
+        contract.send(funding_key(), None, Some(metadata), None, None, None, None).unwrap();
+    }
+
+    #[test]
+    fn test_send_metadata() {
+        let mut contract = LinkDrop::new(owner(), None);
+        register_storage(&mut contract);
+        let metadata = r#"{"title":"Launch party","media":"https://example.com/drop.png"}"#.to_string();
+
+        send_with_metadata(&mut contract, metadata.clone());
+
+        // The metadata is kept with the drop and returned by the views
+        assert_eq!(contract.get_key_information(funding_key()).unwrap().metadata, Some(metadata.clone()));
+        assert_eq!(contract.get_keys(None, None)[0].metadata, Some(metadata));
+    }
+
+    #[test]
+    fn test_send_max_metadata() {
+        let mut contract = LinkDrop::new(owner(), None);
+        register_storage(&mut contract);
+
+        send_with_metadata(&mut contract, "a".repeat(MAX_METADATA_BYTES));
+    }
+
+    #[test]
+    #[should_panic(expected = "Metadata is 257 bytes long, at most 256 bytes are allowed")]
+    fn test_send_oversized_metadata() {
+        let mut contract = LinkDrop::new(owner(), None);
+        register_storage(&mut contract);
+
+        send_with_metadata(&mut contract, "a".repeat(MAX_METADATA_BYTES + 1));
+    }
+
+    /// A linkdrop of `balance` split between `uses` claims.
+    fn multi_use_drop(balance: Balance, uses: u32) -> DropInfo {
+        DropInfo {
+            uses_remaining: uses,
+            amount_per_use: U128(balance / uses as Balance),
+            ..drop_info(balance)
+        }
+    }
+
+    #[test]
+    fn test_send_multi_use() {
+        let mut contract = LinkDrop::new(owner(), None);
+        register_storage(&mut contract);
+
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob())
+            .attached_deposit(MIN_SEND_DEPOSIT * 3)
+            .context.clone()
+        );
+        contract.send(funding_key(), None, None, Some(3), None, None, None).unwrap();
+
+        // The key gets the allowance of every claim
+        let balance = (MIN_SEND_DEPOSIT - ACCESS_KEY_ALLOWANCE) * 3;
+        assert_eq!(contract.accounts.get(&funding_key()).unwrap(), multi_use_drop(balance, 3));
+        match &get_created_receipts()[0].actions[0] {
+            VmAction::AddKeyWithFunctionCall { allowance, .. } => {
+                assert_eq!(*allowance, Some(ACCESS_KEY_ALLOWANCE * 3))
+            }
+            action => panic!("Unexpected action {:?}", action),
+        }
+    }
+
+    /// Top up the funding key as `predecessor_account_id` with `deposit`.
+    fn top_up(contract: &mut LinkDrop, predecessor_account_id: AccountId, deposit: Balance) -> Result<U128, ContractError> {
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(predecessor_account_id)
+            .attached_deposit(deposit)
+            .block_timestamp(10)
+            .context.clone()
+        );
+        contract.top_up(funding_key())
+    }
+
+    #[test]
+    fn test_top_up_keeps_config() {
+        let mut contract = LinkDrop::new(owner(), None);
+        let drop = DropInfo {
+            metadata: Some("Launch party".to_string()),
+            expires_at: Some(U64(1_000)),
+            min_claim_interval: 5,
+            ..multi_use_drop(900, 3)
+        };
+        contract.accounts.insert(&funding_key(), &drop);
+        contract.total_locked = 900;
+
+        // The new balance is spread over the remaining uses, nothing else changes
+        assert_eq!(top_up(&mut contract, bob(), 300), Ok(U128(1_200)));
+        assert_eq!(
+            contract.accounts.get(&funding_key()).unwrap(),
+            DropInfo { balance: U128(1_200), amount_per_use: U128(400), ..drop }
+        );
+        assert_eq!(contract.get_total_locked_balance(), U128(1_200));
+        // Without adding the key again
+        assert!(get_created_receipts().is_empty());
+
+        // The owner can top it up too
+        assert_eq!(top_up(&mut contract, owner(), 300), Ok(U128(1_500)));
+        assert_eq!(contract.accounts.get(&funding_key()).unwrap().funder_id, bob());
+    }
+
+    #[test]
+    fn test_top_up_rejected() {
+        let mut contract = LinkDrop::new(owner(), None);
+        assert_eq!(top_up(&mut contract, bob(), 300), Err(ContractError::KeyMissing));
+
+        contract.accounts.insert(&funding_key(), &drop_info(900));
+        assert_eq!(
+            top_up(&mut contract, new_account(), 300),
+            Err(ContractError::Unauthorized("Only the funder or the owner can top up the linkdrop"))
+        );
+        contract.accounts.insert(&funding_key(), &DropInfo { expires_at: Some(U64(5)), ..drop_info(900) });
+        assert_eq!(
+            top_up(&mut contract, bob(), 300),
+            Err(ContractError::InvalidArgument("Linkdrop has expired".to_string()))
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Attached deposit must be at least")]
+    fn test_send_multi_use_below_minimum() {
+        let mut contract = LinkDrop::new(owner(), None);
+        register_storage(&mut contract);
+
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob())
+            .attached_deposit(MIN_SEND_DEPOSIT * 2)
+            .context.clone()
+        );
+        contract.send(funding_key(), None, None, Some(3), None, None, None).unwrap();
+    }
+
+    #[test]
+    fn test_claim_multi_use() {
+        let mut contract = LinkDrop::new(owner(), None);
+        register_storage(&mut contract);
+        contract.accounts.insert(&funding_key(), &multi_use_drop(1_000, 3));
+        contract.total_locked = 1_000;
+
+        // Every claim sends its share, the last one what is left, and only the last one deletes the key
+        for (uses_left, amount) in [(2, 333), (1, 333), (0, 334)] {
+            callback_env(PromiseResult::Successful(vec![]));
+            contract.claim(bob(), None).unwrap();
+            assert_eq!(get_created_receipts()[0].actions, vec![VmAction::Transfer { deposit: amount }]);
+
+            callback_env(PromiseResult::Successful(vec![]));
+            assert!(contract.on_account_created_and_claimed(bob(), funding_key(), drop_info(amount), false, None, uses_left == 0, false));
+            let receipts = get_created_receipts();
+            if uses_left == 0 {
+                assert_eq!(receipts[0].actions, vec![VmAction::DeleteKey { public_key: funding_key() }]);
+                assert!(contract.accounts.get(&funding_key()).is_none());
+            } else {
+                assert!(receipts.is_empty());
+                assert_eq!(contract.get_key_information(funding_key()).unwrap().uses_remaining, uses_left);
+            }
+        }
+        assert_eq!(contract.total_locked, 0);
+    }
+
+    /// A linkdrop of 1_000 split between 3 claims at least 10_000 nanoseconds apart, claimed once at 1_000 with
+    /// the `result` of the claim.
+    fn rate_limited_contract(result: PromiseResult) -> LinkDrop {
+        let mut contract = LinkDrop::new(owner(), None);
+        register_storage(&mut contract);
+        contract.accounts.insert(&funding_key(), &DropInfo { min_claim_interval: 10_000, ..multi_use_drop(1_000, 3) });
+        contract.total_locked = 1_000;
+
+        // The first claim is always allowed
+        claim_at(&mut contract, 1_000);
+        testing_env!(
+            VMContextBuilder::new()
+                .current_account_id(linkdrop())
+                .predecessor_account_id(linkdrop())
+                .block_timestamp(1_000)
+                .context.clone(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![result]
+        );
+        contract.on_account_created_and_claimed(new_account(), funding_key(), drop_info(333), true, None, false, false);
+        contract
+    }
+
+    #[test]
+    fn test_claim_after_interval() {
+        let mut contract = rate_limited_contract(PromiseResult::Successful(vec![]));
+        assert_eq!(contract.get_key_information(funding_key()).unwrap().last_claim_timestamp, U64(1_000));
+
+        claim_at(&mut contract, 11_000);
+        assert_eq!(contract.get_key_information(funding_key()).unwrap().uses_remaining, 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Linkdrop was claimed less than 10000 nanoseconds ago")]
+    fn test_claim_within_interval() {
+        let mut contract = rate_limited_contract(PromiseResult::Successful(vec![]));
+
+        claim_at(&mut contract, 10_999);
+    }
+
+    #[test]
+    fn test_failed_claim_keeps_interval() {
+        let mut contract = rate_limited_contract(PromiseResult::Failed);
+
+        // Only successful claims start the interval
+        assert_eq!(contract.get_key_information(funding_key()).unwrap().last_claim_timestamp, U64(0));
+        claim_at(&mut contract, 1_001);
+        assert_eq!(contract.get_key_information(funding_key()).unwrap().uses_remaining, 2);
+    }
+
+    /// Take the next claim of the funding key to a new account, as if its transaction ran now.
+    fn take_claim(contract: &mut LinkDrop) {
+        callback_env(PromiseResult::Successful(vec![]));
+        contract.create_account_and_claim(new_account(), key(1), None, None).unwrap();
+    }
+
+    #[test]
+    fn test_interleaved_claims() {
+        let mut contract = LinkDrop::new(owner(), None);
+        register_storage(&mut contract);
+        contract.storage_accounts.insert(&bob(), &StorageAccount { total: 10 * drop_storage_cost(), used: drop_storage_cost() });
+        contract.accounts.insert(&funding_key(), &multi_use_drop(1_000, 2));
+        contract.total_locked = 1_000;
+
+        // Both claims are taken before either callback runs, and each gets its own use
+        take_claim(&mut contract);
+        take_claim(&mut contract);
+        assert!(contract.accounts.get(&funding_key()).is_none());
+        assert_eq!(contract.total_locked, 0);
+
+        // The claim that took the last use deletes the key, whichever callback runs first
+        callback_env(PromiseResult::Successful(vec![]));
+        contract.on_account_created_and_claimed(new_account(), funding_key(), drop_info(500), true, None, true, false);
+        assert_eq!(get_created_receipts()[0].actions, vec![VmAction::DeleteKey { public_key: funding_key() }]);
+        callback_env(PromiseResult::Successful(vec![]));
+        contract.on_account_created_and_claimed(new_account(), funding_key(), drop_info(500), true, None, false, false);
+        assert!(get_created_receipts().is_empty());
+
+        // The storage of the linkdrop is released once
+        assert_eq!(contract.storage_accounts.get(&bob()).unwrap().used, 0);
+    }
+
+    #[test]
+    fn test_claim_of_key_funded_again() {
+        let mut contract = LinkDrop::new(owner(), None);
+        contract.storage_accounts.insert(&bob(), &StorageAccount { total: 10 * drop_storage_cost(), used: drop_storage_cost() });
+        contract.accounts.insert(&funding_key(), &drop_info(1_000));
+        contract.total_locked = 1_000;
+        take_claim(&mut contract);
+
+        // The key is funded again while the claim of its last use is in flight
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob())
+            .attached_deposit(MIN_SEND_DEPOSIT)
+            .context.clone()
+        );
+        contract.send(funding_key(), None, None, None, None, None, None).unwrap();
+
+        // The new linkdrop keeps the key and its balance, only the storage of the claimed one is released
+        callback_env(PromiseResult::Successful(vec![]));
+        contract.on_account_created_and_claimed(new_account(), funding_key(), drop_info(1_000), true, None, true, false);
+        assert!(get_created_receipts().is_empty());
+        assert_eq!(contract.get_key_balance_or_panic(funding_key()), U128(MIN_SEND_DEPOSIT - ACCESS_KEY_ALLOWANCE));
+        assert_eq!(contract.total_locked, MIN_SEND_DEPOSIT - ACCESS_KEY_ALLOWANCE);
+        assert_eq!(contract.storage_accounts.get(&bob()).unwrap().used, drop_storage_cost());
+    }
+
+    #[test]
+    fn test_claim_multi_use_failure() {
+        let mut contract = LinkDrop::new(owner(), None);
+        contract.accounts.insert(&funding_key(), &multi_use_drop(3_000, 3));
+        contract.total_locked = 3_000;
+
+        callback_env(PromiseResult::Successful(vec![]));
+        contract.claim(bob(), None).unwrap();
+
+        // Only the failed use is restored
+        callback_env(PromiseResult::Failed);
+        assert!(!contract.on_account_created_and_claimed(bob(), funding_key(), drop_info(1_000), false, None, true, false));
+        assert_eq!(contract.accounts.get(&funding_key()).unwrap(), multi_use_drop(3_000, 3));
+        assert_eq!(contract.total_locked, 3_000);
+    }
+
+    #[test]
+    #[should_panic(expected = "Linkdrop has no uses left")]
+    fn test_claim_without_uses() {
+        let mut contract = LinkDrop::new(owner(), None);
+        contract.accounts.insert(&funding_key(), &DropInfo { uses_remaining: 0, ..drop_info(1_000) });
+
+        claim_at(&mut contract, 0);
+    }
+
+    /// A funding key along with its signature of the claim of `new_account()` with `key(1)` at `nonce`, on
+    /// `linkdrop()`.
+    fn signed_claim(nonce: u64) -> (PublicKey, Base64VecU8) {
+        let secret = ed25519_dalek::SecretKey::from_bytes(&[7; 32]).unwrap();
+        let public = ed25519_dalek::PublicKey::from(&secret);
+        let message = format!("{}:{}:{}:{}", linkdrop(), new_account(), String::from(&key(1)), nonce);
+        let signature = ed25519_dalek::ExpandedSecretKey::from(&secret).sign(message.as_bytes(), &public);
+        let mut bytes = vec![0];
+        bytes.extend(public.as_bytes());
+        (PublicKey::try_from(bytes).unwrap(), Base64VecU8(signature.to_bytes().to_vec()))
+    }
+
+    #[test]
+    fn test_claim_with_signature() {
+        let mut contract = LinkDrop::new(owner(), None);
+        register_storage(&mut contract);
+        let (public_key, signature) = signed_claim(0);
+        contract.accounts.insert(&public_key, &drop_info(1_000));
+        contract.total_locked = 1_000;
+
+        // Anyone can submit the signed claim
+        testing_env!(VMContextBuilder::new().current_account_id(linkdrop()).context.clone());
+        contract.claim_with_signature(public_key.clone(), new_account(), key(1), signature, None, U64(0)).unwrap();
+        assert!(contract.accounts.get(&public_key).is_none());
+        assert_eq!(get_created_receipts()[0].receiver_id, new_account());
+
+        // The callback deletes the funding key rather than the one of the transaction
+        callback_env(PromiseResult::Successful(vec![]));
+        assert!(contract.on_account_created_and_claimed(new_account(), public_key.clone(), drop_info(1_000), true, None, true, true));
+        assert_eq!(get_created_receipts()[0].actions, vec![VmAction::DeleteKey { public_key }]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid signature of the funding key")]
+    fn test_claim_with_signature_other_contract() {
+        let mut contract = LinkDrop::new(owner(), None);
+        let (public_key, signature) = signed_claim(0);
+        contract.accounts.insert(&public_key, &drop_info(1_000));
+
+        // The signature does not cover another deployment funding the same key
+        testing_env!(VMContextBuilder::new().current_account_id("other".parse().unwrap()).context.clone());
+        contract.claim_with_signature(public_key, new_account(), key(1), signature, None, U64(0)).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid signature of the funding key")]
+    fn test_claim_with_invalid_signature() {
+        let mut contract = LinkDrop::new(owner(), None);
+        let (public_key, signature) = signed_claim(0);
+        contract.accounts.insert(&public_key, &drop_info(1_000));
+
+        // The signature does not cover another account
+        testing_env!(VMContextBuilder::new().current_account_id(linkdrop()).context.clone());
+        contract
+            .claim_with_signature(public_key, "other.linkdrop".parse().unwrap(), key(1), signature, None, U64(0))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_claim_with_signature_nonce() {
+        let mut contract = LinkDrop::new(owner(), None);
+        register_storage(&mut contract);
+        let (public_key, signature) = signed_claim(0);
+        contract.accounts.insert(&public_key, &multi_use_drop(2_000, 2));
+        contract.total_locked = 2_000;
+        assert_eq!(contract.get_claim_nonce(public_key.clone()), U64(0));
+
+        // The nonce only moves on once the claim succeeds, a failed one leaves the signature usable
+        let claimed = DropInfo { uses_remaining: 1, ..multi_use_drop(1_000, 1) };
+        testing_env!(VMContextBuilder::new().current_account_id(linkdrop()).context.clone());
+        contract.claim_with_signature(public_key.clone(), new_account(), key(1), signature.clone(), None, U64(0)).unwrap();
+        assert_eq!(contract.get_claim_nonce(public_key.clone()), U64(0));
+        callback_env(PromiseResult::Failed);
+        assert!(!contract.on_account_created_and_claimed(new_account(), public_key.clone(), claimed.clone(), true, None, false, true));
+        assert_eq!(contract.get_claim_nonce(public_key.clone()), U64(0));
+
+        testing_env!(VMContextBuilder::new().current_account_id(linkdrop()).context.clone());
+        contract.claim_with_signature(public_key.clone(), new_account(), key(1), signature.clone(), None, U64(0)).unwrap();
+        callback_env(PromiseResult::Successful(vec![]));
+        assert!(contract.on_account_created_and_claimed(new_account(), public_key.clone(), claimed, true, None, false, true));
+        assert_eq!(contract.get_claim_nonce(public_key.clone()), U64(1));
+
+        // Replaying the signature for the remaining use fails
+        testing_env!(VMContextBuilder::new().current_account_id(linkdrop()).context.clone());
+        assert_eq!(
+            contract.claim_with_signature(public_key.clone(), new_account(), key(1), signature, None, U64(0)).err(),
+            Some(ContractError::InvalidArgument(
+                "Stale nonce 0 for the signed claim, the current nonce of the key is 1".to_string()
+            ))
+        );
+
+        // A signature of the current nonce claims the remaining use
+        let (_, signature) = signed_claim(1);
+        contract.claim_with_signature(public_key.clone(), new_account(), key(1), signature, None, U64(1)).unwrap();
+        assert!(contract.accounts.get(&public_key).is_none());
+    }
+
+    pub(crate) fn full_key_options(full_access_keys: Vec<PublicKey>) -> CreateAccountOptions {
+        CreateAccountOptions {
+            full_access_keys: Some(full_access_keys),
+            full_access_key_nonce: None,
+            limited_access_keys: None,
+            templated_access_keys: None,
+            contract_bytes: None,
+            deploy_init: None,
+            stake: None,
+        }
+    }
+
+    #[test]
+    fn test_validate_options() {
+        let contract = LinkDrop::new(owner(), Some(vec![code_hash(b"allowed")]));
+
+        assert_eq!(contract.validate_options(full_key_options(vec![key(1)])), Ok(()));
+        assert_eq!(contract.validate_options(contract_options(b"allowed")), Ok(()));
+        assert_eq!(
+            contract.validate_options(CreateAccountOptions {
+                full_access_keys: None,
+                full_access_key_nonce: None,
+                limited_access_keys: None,
+                templated_access_keys: None,
+                contract_bytes: None,
+                deploy_init: None,
+                stake: None,
+            }),
+            Err("Cannot create account with no options. Please specify either contract bytes, full access keys, or limited access keys.".to_string())
+        );
+        assert_eq!(
+            contract.validate_options(full_key_options((0..=MAX_KEYS_PER_ACCOUNT as u8).map(key).collect())),
+            Err(format!(
+                "Cannot add {} keys, at most {} keys can be added to an account",
+                MAX_KEYS_PER_ACCOUNT + 1,
+                MAX_KEYS_PER_ACCOUNT
+            ))
+        );
+        assert_eq!(
+            contract.validate_options(full_key_options(vec![key(1), key(1)])),
+            Err(format!("Public key {} is added more than once", String::from(&key(1))))
+        );
+        assert_eq!(
+            contract.validate_options(limited_key_options(LimitedAccessKey { allowance: U128(0), ..limited_key(key(2)) })),
+            Err("Limited access key at index 1 has a zero allowance".to_string())
+        );
+        assert_eq!(
+            contract.validate_options(limited_key_options(LimitedAccessKey {
+                method_names: vec!["claim"; MAX_METHOD_NAMES + 1].join(","),
+                ..limited_key(key(2))
+            })),
+            Err("Cannot restrict an access key to 101 method names, at most 100 are allowed".to_string())
+        );
+        assert_eq!(
+            contract.validate_options(limited_key_options(LimitedAccessKey {
+                method_names: "a".repeat(MAX_METHOD_NAME_BYTES + 1),
+                ..limited_key(key(2))
+            })),
+            Err(format!("Method name {} is longer than 256 bytes", "a".repeat(MAX_METHOD_NAME_BYTES + 1)))
+        );
+        assert_eq!(
+            contract.validate_options(contract_options(b"other")),
+            Err("Contract is not in the allowed contract hashes".to_string())
+        );
+    }
+
+    fn init_call() -> InitCall {
+        InitCall {
+            method: "new".to_string(),
+            args: Base64VecU8(br#"{"owner_id":"bob"}"#.to_vec()),
+            deposit: U128(100),
+            gas: Gas(10_000_000_000_000),
+        }
+    }
+
+    #[test]
+    fn test_create_account_with_init_call() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .attached_deposit(1_000)
+            .context.clone()
+        );
+        let keys = no_keys();
+        let contract_bytes = Base64VecU8(b"contract".to_vec());
+        drop(contract.create_account_with_contract(new_account(), contract_bytes, Some(init_call()), keys));
+
+        // The contract is initialized right after its deployment, with the init deposit out of the transfer
+        assert_eq!(
+            get_created_receipts()[0].actions,
+            vec![
+                VmAction::CreateAccount,
+                VmAction::Transfer { deposit: 900 },
+                VmAction::DeployContract { code: b"contract".to_vec() },
+                VmAction::FunctionCall {
+                    function_name: "new".to_string(),
+                    args: br#"{"owner_id":"bob"}"#.to_vec(),
+                    gas: Gas(10_000_000_000_000),
+                    deposit: 100,
+                },
+            ]
+        );
+    }
+
+    /// Create an account deploying a contract initialized with `init_call`, attaching `deposit` and `prepaid_gas`.
+    fn create_with_init_call(deposit: Balance, prepaid_gas: Gas) -> Result<Promise, ContractError> {
+        let mut contract = LinkDrop::new(owner(), None);
+        contract.creation_fee = 100;
+
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .attached_deposit(deposit)
+            .prepaid_gas(prepaid_gas)
+            .context.clone()
+        );
+        let contract_bytes = Base64VecU8(b"contract".to_vec());
+        contract.create_account_with_contract(new_account(), contract_bytes, Some(init_call()), no_keys())
+    }
+
+    /// Gas `create_with_init_call` needs at least.
+    fn init_call_gas() -> Gas {
+        let options = CreateAccountOptions { deploy_init: Some(init_call()), ..contract_options(b"contract") };
+        estimate_creation_gas(&options)
     }
 
-      #[test]
-    fn test_create_advanced_account() {
-        // Create a new instance of the linkdrop contract
-        let mut contract = LinkDrop::new();
-        // Create the public key to be used in the test
-        let pk: PublicKey = "qSq3LoufLvTCTNGC3LJePMDGrok8dHMQ5A1YD9psbiz"
-            .parse()
-            .unwrap();
-        // Default the deposit to an extremely small amount
-        let deposit = 1_000_000;
+    #[test]
+    fn test_init_call_provisioned() {
+        // The deposit covers the fee and the deposit of the init call, the gas covers the init call and callback
+        create_with_init_call(200, init_call_gas()).unwrap();
+        assert!(get_created_receipts()[1].actions.contains(&VmAction::Transfer { deposit: 0 }));
+    }
 
-        // Create options for the advanced account creation
-        let options: CreateAccountOptions = CreateAccountOptions {
-            full_access_keys: Some(vec![pk.clone()]),
+    #[test]
+    fn test_init_call_deposit_not_provisioned() {
+        assert_eq!(
+            create_with_init_call(199, init_call_gas()).err(),
+            Some(ContractError::InsufficientDeposit(
+                "Attached deposit of 199 yoctoNEAR must cover the creation fees of 100 yoctoNEAR and the deposit of the \
+                 init call of 100 yoctoNEAR"
+                    .to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_init_call_gas_not_provisioned() {
+        let required_gas = init_call_gas();
+        let callback_gas = compute_callback_gas(&contract_options(b"contract"));
+        assert_eq!(
+            create_with_init_call(200, required_gas - Gas(1)).err(),
+            Some(ContractError::InvalidArgument(format!(
+                "Not enough gas to create the account, attach at least {} gas: 15000000000000 for the init call, 0 \
+                 for the stake call, {} for the callback and {} for the creation",
+                required_gas.0,
+                callback_gas.0,
+                required_gas.0 - 15_000_000_000_000 - callback_gas.0
+            )))
+        );
+    }
+
+    #[test]
+    fn test_init_call_without_gas() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        testing_env!(VMContextBuilder::new().current_account_id(linkdrop()).attached_deposit(1_000).context.clone());
+        let contract_bytes = Base64VecU8(b"contract".to_vec());
+        let init = InitCall { gas: Gas(0), ..init_call() };
+        assert_eq!(
+            contract.create_account_with_contract(new_account(), contract_bytes, Some(init), no_keys()).err(),
+            Some(ContractError::InvalidArgument("The init call of new needs gas".to_string()))
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot call an init method without contract bytes to deploy")]
+    fn test_create_account_init_call_without_contract() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        testing_env!(VMContextBuilder::new().current_account_id(linkdrop()).context.clone());
+        contract.create_account_advanced(
+            new_account(),
+            CreateAccountOptions { deploy_init: Some(init_call()), ..full_key_options(vec![key(1)]) },
+            None,
+            None,
+            None,
+            None,
+        ).unwrap();
+    }
+
+    /// yoctoNEAR staked by `stake_call`, 1 N.
+    const STAKE_AMOUNT: Balance = 1_000_000_000_000_000_000_000_000;
+
+    /// Stakes `STAKE_AMOUNT` with "pool".
+    fn stake_call() -> StakeCall {
+        StakeCall {
+            staking_pool_id: AccountId::new_unchecked("pool".to_string()),
+            amount: U128(STAKE_AMOUNT),
+            gas: Gas(20_000_000_000_000),
+        }
+    }
+
+    /// Create an account deploying a contract initialized with `init_call` that stakes with `stake_call`,
+    /// attaching `deposit` and `prepaid_gas`.
+    fn create_with_stake(deposit: Balance, prepaid_gas: Gas) -> Result<Promise, ContractError> {
+        let mut contract = LinkDrop::new(owner(), None);
+        contract.creation_fee = 100;
+
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .attached_deposit(deposit)
+            .prepaid_gas(prepaid_gas)
+            .context.clone()
+        );
+        let contract_bytes = Base64VecU8(b"contract".to_vec());
+        let keys = CreateAccountOptions { stake: Some(stake_call()), ..no_keys() };
+        contract.create_account_with_contract(new_account(), contract_bytes, Some(init_call()), keys)
+    }
+
+    /// Gas `create_with_stake` needs at least.
+    fn stake_call_gas() -> Gas {
+        let options =
+            CreateAccountOptions { deploy_init: Some(init_call()), stake: Some(stake_call()), ..contract_options(b"contract") };
+        estimate_creation_gas(&options)
+    }
+
+    /// yoctoNEAR the storage of the account created by `create_with_stake` costs.
+    fn stake_storage_cost() -> Balance {
+        account_storage_bytes(&contract_options(b"contract")) as Balance * env::storage_byte_cost()
+    }
+
+    #[test]
+    fn test_create_account_with_stake() {
+        let storage_cost = stake_storage_cost();
+        drop(create_with_stake(200 + STAKE_AMOUNT + storage_cost, stake_call_gas()).unwrap());
+
+        // The stake is attached to the deployed contract after its init call, out of the transfer
+        assert_eq!(
+            get_created_receipts()[1].actions,
+            vec![
+                VmAction::CreateAccount,
+                VmAction::Transfer { deposit: storage_cost },
+                VmAction::DeployContract { code: b"contract".to_vec() },
+                VmAction::FunctionCall {
+                    function_name: "new".to_string(),
+                    args: br#"{"owner_id":"bob"}"#.to_vec(),
+                    gas: Gas(10_000_000_000_000),
+                    deposit: 100,
+                },
+                VmAction::FunctionCall {
+                    function_name: STAKE_METHOD_NAME.to_string(),
+                    args: json!({ "staking_pool_id": "pool", "amount": STAKE_AMOUNT.to_string() }).to_string().into_bytes(),
+                    gas: Gas(20_000_000_000_000),
+                    deposit: STAKE_AMOUNT,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_create_account_stake_leaves_no_storage() {
+        let storage_cost = stake_storage_cost();
+        assert_eq!(
+            create_with_stake(200 + STAKE_AMOUNT + storage_cost - 1, stake_call_gas()).err(),
+            Some(ContractError::InsufficientDeposit(format!(
+                "Staking {} yoctoNEAR would leave the new account {} yoctoNEAR, below the {} yoctoNEAR its storage costs",
+                STAKE_AMOUNT,
+                storage_cost - 1,
+                storage_cost
+            )))
+        );
+    }
+
+    #[test]
+    fn test_stake_gas_not_provisioned() {
+        let required_gas = stake_call_gas();
+        let callback_gas = compute_callback_gas(&contract_options(b"contract"));
+        assert_eq!(
+            create_with_stake(200 + STAKE_AMOUNT + stake_storage_cost(), required_gas - Gas(1)).err(),
+            Some(ContractError::InvalidArgument(format!(
+                "Not enough gas to create the account, attach at least {} gas: 15000000000000 for the init call, \
+                 25000000000000 for the stake call, {} for the callback and {} for the creation",
+                required_gas.0,
+                callback_gas.0,
+                required_gas.0 - 15_000_000_000_000 - 25_000_000_000_000 - callback_gas.0
+            )))
+        );
+    }
+
+    #[test]
+    fn test_create_account_stake_without_contract() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        testing_env!(VMContextBuilder::new().current_account_id(linkdrop()).attached_deposit(2 * STAKE_AMOUNT).context.clone());
+        assert_eq!(
+            contract.create_account_advanced(
+                new_account(),
+                CreateAccountOptions { stake: Some(stake_call()), ..full_key_options(vec![key(1)]) },
+                None,
+                None,
+                None,
+                None,
+            ).err(),
+            Some(ContractError::InvalidArgument("Cannot stake without contract bytes to deploy".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_total_accounts_created() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        // Only successful creations count, with or without a linkdrop
+        callback_env(PromiseResult::Successful(vec![]));
+        contract.on_account_created(new_account(), bob(), bob(), U128(1_000), None, None, None);
+        callback_env(PromiseResult::Failed);
+        drop(contract.on_account_created(new_account(), bob(), bob(), U128(1_000), None, None, None));
+        assert_eq!(contract.get_total_accounts_created(), 1);
+
+        callback_env(PromiseResult::Successful(vec![]));
+        contract.on_account_created_and_claimed(new_account(), funding_key(), drop_info(1_000), true, None, true, false);
+        callback_env(PromiseResult::Failed);
+        contract.on_account_created_and_claimed(new_account(), funding_key(), drop_info(1_000), true, None, true, false);
+        assert_eq!(contract.get_total_accounts_created(), 2);
+
+        // Claims to existing accounts don't create any
+        callback_env(PromiseResult::Successful(vec![]));
+        contract.on_account_created_and_claimed(bob(), funding_key(), drop_info(1_000), false, None, true, false);
+        assert_eq!(contract.get_total_accounts_created(), 2);
+    }
+
+    /// Set up a callback of this contract receiving `results`.
+    fn results_env(results: Vec<PromiseResult>) {
+        testing_env!(
+            VMContextBuilder::new()
+                .current_account_id(linkdrop())
+                .predecessor_account_id(linkdrop())
+                .context.clone(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            results
+        );
+    }
+
+    #[test]
+    fn test_all_promises_succeeded() {
+        results_env(vec![PromiseResult::Successful(vec![]), PromiseResult::Successful(vec![])]);
+        assert!(all_promises_succeeded(2));
+
+        // Any failure, wherever it is, fails them all
+        results_env(vec![PromiseResult::Successful(vec![]), PromiseResult::Failed]);
+        assert!(!all_promises_succeeded(2));
+        results_env(vec![PromiseResult::Failed, PromiseResult::Successful(vec![])]);
+        assert!(!all_promises_succeeded(2));
+
+        results_env(vec![PromiseResult::Successful(vec![])]);
+        assert!(is_promise_success());
+        results_env(vec![PromiseResult::Failed]);
+        assert!(!is_promise_success());
+    }
+
+    #[test]
+    #[should_panic(expected = "Contract expected 1 results on the callback")]
+    fn test_is_promise_success_multiple_results() {
+        results_env(vec![PromiseResult::Successful(vec![]), PromiseResult::Successful(vec![])]);
+        is_promise_success();
+    }
+
+    /// Fund a linkdrop of 1_000 behind the secret "open sesame" and claim through the contract's own access key.
+    fn secret_drop(contract: &mut LinkDrop) {
+        contract.accounts.insert(
+            &funding_key(),
+            &DropInfo { secret_hash: Some(env::sha256(b"open sesame")), ..drop_info(1_000) },
+        );
+        contract.total_locked = 1_000;
+        callback_env(PromiseResult::Successful(vec![]));
+    }
+
+    #[test]
+    fn test_send_with_secret() {
+        let mut contract = LinkDrop::new(owner(), None);
+        register_storage(&mut contract);
+
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob())
+            .attached_deposit(MIN_SEND_DEPOSIT)
+            .context.clone()
+        );
+        contract.send(funding_key(), None, None, None, Some(Base64VecU8(env::sha256(b"open sesame"))), None, None).unwrap();
+        assert_eq!(contract.accounts.get(&funding_key()).unwrap().secret_hash, Some(env::sha256(b"open sesame")));
+        assert!(contract.get_key_information(funding_key()).unwrap().requires_secret);
+    }
+
+    #[test]
+    #[should_panic(expected = "The secret hash must be a sha256 hash")]
+    fn test_send_with_invalid_secret_hash() {
+        let mut contract = LinkDrop::new(owner(), None);
+        register_storage(&mut contract);
+
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob())
+            .attached_deposit(MIN_SEND_DEPOSIT)
+            .context.clone()
+        );
+        contract.send(funding_key(), None, None, None, Some(Base64VecU8(b"open sesame".to_vec())), None, None).unwrap();
+    }
+
+    #[test]
+    fn test_claim_with_secret() {
+        let mut contract = LinkDrop::new(owner(), None);
+        secret_drop(&mut contract);
+
+        contract.create_account_and_claim(new_account(), key(1), Some("open sesame".to_string()), None).unwrap();
+        assert!(contract.accounts.get(&funding_key()).is_none());
+        assert_eq!(contract.total_locked, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Wrong secret for the linkdrop")]
+    fn test_claim_with_wrong_secret() {
+        let mut contract = LinkDrop::new(owner(), None);
+        secret_drop(&mut contract);
+
+        contract.create_account_and_claim(new_account(), key(1), Some("open sesame!".to_string()), None).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "The linkdrop can only be claimed with its secret")]
+    fn test_claim_without_secret() {
+        let mut contract = LinkDrop::new(owner(), None);
+        secret_drop(&mut contract);
+
+        contract.claim(bob(), None).unwrap();
+    }
+
+    #[test]
+    fn test_claim_ignores_secret_when_not_required() {
+        let mut contract = LinkDrop::new(owner(), None);
+        contract.accounts.insert(&funding_key(), &drop_info(1_000));
+        contract.total_locked = 1_000;
+
+        // Drops funded without a secret hash accept any secret, or none
+        callback_env(PromiseResult::Successful(vec![]));
+        contract.create_account_and_claim(new_account(), key(1), Some("anything".to_string()), None).unwrap();
+        assert!(contract.accounts.get(&funding_key()).is_none());
+    }
+
+    /// Create `new_account()` with a full access key and fund `funding_key()` with `funded_amount`, as bob
+    /// attaching `deposit`.
+    fn create_and_fund(contract: &mut LinkDrop, deposit: Balance, funded_amount: Balance) {
+        register_storage(contract);
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob())
+            .attached_deposit(deposit)
+            .context.clone()
+        );
+        contract.create_account_and_fund_key(new_account(), full_key_options(vec![key(1)]), funding_key(), U128(funded_amount)).unwrap();
+    }
+
+    #[test]
+    fn test_create_account_and_fund_key_refuses_contract() {
+        let mut contract = LinkDrop::new(owner(), None);
+        register_storage(&mut contract);
+
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob())
+            .attached_deposit(MIN_SEND_DEPOSIT + 1_000)
+            .context.clone()
+        );
+        let result = contract.create_account_and_fund_key(
+            new_account(),
+            contract_options(b"contract"),
+            funding_key(),
+            U128(MIN_SEND_DEPOSIT),
+        );
+        assert_eq!(
+            result.err(),
+            Some(ContractError::InvalidArgument(
+                "Cannot deploy a contract with create_account_and_fund_key, use create_account_with_contract"
+                    .to_string()
+            ))
+        );
+        assert!(contract.accounts.get(&funding_key()).is_none());
+    }
+
+    #[test]
+    fn test_create_account_and_fund_key() {
+        let mut contract = LinkDrop::new(owner(), None);
+        create_and_fund(&mut contract, MIN_SEND_DEPOSIT + 1_000, MIN_SEND_DEPOSIT);
+
+        // The linkdrop is recorded for bob, less the allowance of the key
+        let drop = contract.accounts.get(&funding_key()).unwrap();
+        assert_eq!(drop.funder_id, bob());
+        assert_eq!(drop.balance, U128(MIN_SEND_DEPOSIT - ACCESS_KEY_ALLOWANCE));
+        assert_eq!(contract.total_locked, MIN_SEND_DEPOSIT - ACCESS_KEY_ALLOWANCE);
+
+        // The key is added to the contract and the rest of the deposit goes to the new account
+        let receipts = get_created_receipts();
+        let key_receipt = receipts.iter().find(|receipt| receipt.receiver_id == linkdrop()).unwrap();
+        assert_eq!(
+            key_receipt.actions,
+            vec![VmAction::AddKeyWithFunctionCall {
+                public_key: funding_key(),
+                nonce: 0,
+                allowance: Some(ACCESS_KEY_ALLOWANCE),
+                receiver_id: linkdrop(),
+                function_names: vec!["claim".to_string(), "claim_with_keys".to_string(), "create_account_and_claim".to_string()],
+            }]
+        );
+        let creation_receipt = receipts.iter().find(|receipt| receipt.receiver_id == new_account()).unwrap();
+        assert!(creation_receipt.actions.contains(&VmAction::Transfer { deposit: 1_000 }));
+        assert!(creation_receipt.actions.contains(&VmAction::AddKeyWithFullAccess { public_key: key(1), nonce: 0 }));
+    }
+
+    #[test]
+    #[should_panic(expected = "Attached deposit must cover the funded amount of")]
+    fn test_create_account_and_fund_key_above_deposit() {
+        let mut contract = LinkDrop::new(owner(), None);
+        create_and_fund(&mut contract, MIN_SEND_DEPOSIT, MIN_SEND_DEPOSIT + 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Attached deposit must cover the creation fee of 100 yoctoNEAR")]
+    fn test_create_account_and_fund_key_without_creation_cost() {
+        let mut contract = LinkDrop::new(owner(), None);
+        contract.creation_fee = 100;
+        create_and_fund(&mut contract, MIN_SEND_DEPOSIT + 99, MIN_SEND_DEPOSIT);
+    }
+
+    #[test]
+    fn test_describe_creation_plan_keys() {
+        let contract = LinkDrop::new(owner(), None);
+        let options = CreateAccountOptions {
+            full_access_keys: Some(vec![key(1), key(2)]),
+            full_access_key_nonce: None,
             limited_access_keys: Some(vec![LimitedAccessKey {
-                public_key: pk.clone(),
+                public_key: key(3),
                 allowance: U128(100),
                 receiver_id: linkdrop(),
-                method_names: "send".to_string(),
+                method_names: "claim,send".to_string(),
+                nonce: None,
             }]),
-            contract_bytes: Some(include_bytes!("../target/wasm32-unknown-unknown/release/linkdrop.wasm").to_vec()),
+            templated_access_keys: None,
+            contract_bytes: None,
+            deploy_init: None,
+            stake: None,
         };
 
-        // Initialize the mocked blockchain
+        assert_eq!(
+            contract.describe_creation_plan(new_account(), options),
+            CreationPlan {
+                creates_account: true,
+                full_access_keys: 2,
+                limited_access_keys: vec![PlannedAccessKey {
+                    public_key: key(3),
+                    allowance: U128(100),
+                    receiver_id: linkdrop(),
+                    method_names: vec!["claim".to_string(), "send".to_string()],
+                }],
+                contract_hash: None,
+                init_method: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_describe_creation_plan_all_options() {
+        let contract = LinkDrop::new(owner(), None);
+        let options = CreateAccountOptions {
+            full_access_keys: Some(vec![key(1)]),
+            full_access_key_nonce: None,
+            limited_access_keys: Some(vec![LimitedAccessKey {
+                public_key: key(2),
+                allowance: U128(100),
+                receiver_id: linkdrop(),
+                method_names: "".to_string(),
+                nonce: None,
+            }]),
+            templated_access_keys: None,
+            contract_bytes: Some(b"contract".to_vec()),
+            deploy_init: Some(init_call()),
+            stake: None,
+        };
+
+        let plan = contract.describe_creation_plan(new_account(), options);
+        assert_eq!(plan.full_access_keys, 1);
+        // Keys without method names can call any method
+        assert!(plan.limited_access_keys[0].method_names.is_empty());
+        assert_eq!(plan.contract_hash, Some(Base58CryptoHash::from(env::sha256_array(b"contract"))));
+        assert_eq!(plan.init_method, Some(init_call().method));
+    }
+
+    #[test]
+    fn test_describe_creation_plan_own_account() {
+        let contract = LinkDrop::new(owner(), None);
+        testing_env!(VMContextBuilder::new().current_account_id(linkdrop()).context.clone());
+
+        // The contract itself is never created, nor is anything added to it
+        let plan = contract.describe_creation_plan(linkdrop(), full_key_options(vec![key(1)]));
+        assert!(!plan.creates_account);
+        assert_eq!(plan.full_access_keys, 1);
+        assert!(plan.limited_access_keys.is_empty());
+        assert_eq!(plan.contract_hash, None);
+    }
+
+    fn referrer() -> AccountId {
+        "referrer".parse().unwrap()
+    }
+
+    #[test]
+    fn test_claim_with_beneficiary() {
+        let mut contract = LinkDrop::new(owner(), None);
+        register_storage(&mut contract);
+        contract.accounts.insert(&funding_key(), &drop_info(1_000));
+        contract.total_locked = 1_000;
+
+        // The new account only gets what the beneficiary doesn't
+        callback_env(PromiseResult::Successful(vec![]));
+        contract.create_account_and_claim(new_account(), key(1), None, Some((referrer(), U128(300)))).unwrap();
+        assert!(get_created_receipts()[0].actions.contains(&VmAction::Transfer { deposit: 700 }));
+
+        // The beneficiary is paid once the account exists
+        callback_env(PromiseResult::Successful(vec![]));
+        contract.on_account_created_and_claimed(new_account(), funding_key(), drop_info(1_000), true, Some((referrer(), U128(300))), true, false);
+        let receipts = get_created_receipts();
+        assert_eq!(receipts[0].receiver_id, referrer());
+        assert_eq!(receipts[0].actions, vec![VmAction::Transfer { deposit: 300 }]);
+    }
+
+    #[test]
+    fn test_failed_claim_with_beneficiary() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        // Nothing goes to the beneficiary and the whole balance is restored
+        callback_env(PromiseResult::Failed);
+        contract.on_account_created_and_claimed(new_account(), funding_key(), drop_info(1_000), true, Some((referrer(), U128(300))), true, false);
+        assert!(get_created_receipts().is_empty());
+        assert_eq!(contract.get_key_balance_or_panic(funding_key()), U128(1_000));
+    }
+
+    #[test]
+    #[should_panic(expected = "The beneficiary amount of 1001 yoctoNEAR exceeds the claimed balance of 1000 yoctoNEAR")]
+    fn test_claim_with_beneficiary_above_balance() {
+        let mut contract = LinkDrop::new(owner(), None);
+        contract.accounts.insert(&funding_key(), &drop_info(1_000));
+        contract.total_locked = 1_000;
+
+        callback_env(PromiseResult::Successful(vec![]));
+        contract.create_account_and_claim(new_account(), key(1), None, Some((referrer(), U128(1_001)))).unwrap();
+    }
+
+    #[test]
+    fn test_can_create() {
+        testing_env!(VMContextBuilder::new().current_account_id(linkdrop()).predecessor_account_id(owner()).context.clone());
+        let mut contract = LinkDrop::new(owner(), None);
+        contract.add_reserved_label("admin".to_string()).unwrap();
+
+        assert!(contract.can_create("bob.linkdrop".parse().unwrap()));
+        // Reserved names, nested subaccounts and accounts outside of this contract can't be created
+        assert!(!contract.can_create("admin.linkdrop".parse().unwrap()));
+        assert!(!contract.can_create("a.bob.linkdrop".parse().unwrap()));
+        assert!(!contract.can_create("bob".parse().unwrap()));
+        assert!(!contract.can_create("bob.near".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_get_version() {
+        let contract = LinkDrop::new(owner(), None);
+        assert_eq!(contract.get_version(), "0.1.0");
+        assert_eq!(contract.last_migration, CONTRACT_VERSION);
+    }
+
+    #[test]
+    fn test_health_check() {
+        let mut contract = LinkDrop::new(owner(), None);
+        register_storage(&mut contract);
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob())
+            .attached_deposit(MIN_SEND_DEPOSIT)
+            .context.clone()
+        );
+        contract.send(funding_key(), None, None, None, None, None, None).unwrap();
+
+        callback_env(PromiseResult::Successful(vec![]));
+        contract.on_account_created(new_account(), bob(), bob(), U128(1_000), None, None, None);
+        callback_env(PromiseResult::Failed);
+        drop(contract.on_account_created(new_account(), bob(), bob(), U128(1_000), None, None, None));
+        testing_env!(VMContextBuilder::new().predecessor_account_id(owner()).context.clone());
+        contract.pause().unwrap();
+
+        assert_eq!(
+            contract.health_check(),
+            ContractHealth {
+                paused: true,
+                total_locked: U128(MIN_SEND_DEPOSIT - ACCESS_KEY_ALLOWANCE),
+                accounts_created: 1,
+                storage_usage: U64(env::storage_usage()),
+                available_balance: contract.get_available_balance(),
+                consecutive_failures: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn test_get_contract_features() {
+        let mut contract = LinkDrop::new(owner(), None);
+        contract.creation_fee = 100;
+
+        assert_eq!(
+            contract.get_contract_features(),
+            ContractFeatures {
+                ft_drops: true,
+                nft_drops: true,
+                secrets: true,
+                expiration: true,
+                multi_use: true,
+                signed_claims: true,
+                vesting: true,
+                max_keys_per_account: MAX_KEYS_PER_ACCOUNT,
+                creation_fee: U128(100),
+                fee_bps: 0,
+                max_contract_size: U64(DEFAULT_MAX_CONTRACT_SIZE),
+            }
+        );
+    }
+
+    /// A linkdrop of 1000 yoctoNEAR vesting from 1000 to 11000, with its cliff at 2000.
+    fn vesting_drop(contract: &mut LinkDrop) {
+        let vesting = Vesting { start: U64(1_000), cliff: U64(2_000), end: U64(11_000) };
+        contract.accounts.insert(&funding_key(), &DropInfo { vesting: Some(vesting), ..drop_info(1_000) });
+        contract.total_locked = 1_000;
+    }
+
+    fn vesting_env(block_timestamp: u64, result: PromiseResult) {
+        testing_env!(
+            VMContextBuilder::new()
+                .current_account_id(linkdrop())
+                .predecessor_account_id(linkdrop())
+                .signer_account_pk(funding_key())
+                .block_timestamp(block_timestamp)
+                .context.clone(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![result]
+        );
+    }
+
+    fn send_many_env(deposit: Balance) {
         testing_env!(
             VMContextBuilder::new()
             .current_account_id(linkdrop())
+            .predecessor_account_id(bob())
             .attached_deposit(deposit)
             .context.clone()
         );
+    }
 
-        // Create bob's account with the advanced options
-        contract.create_account_advanced(bob(), options);
+    #[test]
+    fn test_send_many() {
+        let mut contract = LinkDrop::new(owner(), None);
+        register_storage(&mut contract);
+
+        // The duplicate key is funded once, and the excess deposit goes back to bob
+        send_many_env(2 * MIN_SEND_DEPOSIT + 5);
+        let amount_each = MIN_SEND_DEPOSIT - ACCESS_KEY_ALLOWANCE;
+        assert_eq!(contract.send_many(vec![key(1), key(2), key(1)], U128(amount_each)), Ok(2));
+        for public_key in [key(1), key(2)] {
+            assert_eq!(contract.get_key_balance_or_panic(public_key), U128(amount_each));
+        }
+        assert_eq!(contract.total_locked, 2 * amount_each);
+        assert_eq!(contract.get_drops_by_funder(bob(), None, None).len(), 2);
+        let receipts = get_created_receipts();
+        let refund = receipts.iter().find(|receipt| receipt.receiver_id == bob()).unwrap();
+        assert_eq!(refund.actions, vec![VmAction::Transfer { deposit: 5 }]);
+    }
+
+    #[test]
+    #[should_panic(expected = "to fund 2 keys")]
+    fn test_send_many_over_budget() {
+        let mut contract = LinkDrop::new(owner(), None);
+        register_storage(&mut contract);
+
+        send_many_env(2 * MIN_SEND_DEPOSIT - 1);
+        contract.send_many(vec![key(1), key(2)], U128(MIN_SEND_DEPOSIT - ACCESS_KEY_ALLOWANCE)).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "Attached deposit must be at least")]
+    fn test_send_many_below_min_deposit() {
+        let mut contract = LinkDrop::new(owner(), None);
+        register_storage(&mut contract);
+
+        // Every key still needs the minimum deposit of `send`
+        send_many_env(2 * (ACCESS_KEY_ALLOWANCE + 1));
+        contract.send_many(vec![key(1), key(2)], U128(1)).unwrap();
+    }
+
+    #[test]
+    fn test_send_many_overflow() {
+        let mut contract = LinkDrop::new(owner(), None);
+        register_storage(&mut contract);
+
+        send_many_env(MIN_SEND_DEPOSIT);
+        let amount_each = Balance::MAX / 2;
+        assert_eq!(
+            contract.send_many(vec![key(1), key(2)], U128(amount_each)).err(),
+            Some(ContractError::InvalidArgument(format!("Cannot fund 2 keys with {} yoctoNEAR each", amount_each)))
+        );
+        assert_eq!(
+            contract.send_many(vec![key(1)], U128(Balance::MAX)).err(),
+            Some(ContractError::InvalidArgument(format!("Cannot fund 1 keys with {} yoctoNEAR each", Balance::MAX)))
+        );
+        assert!(contract.accounts.get(&key(1)).is_none());
+    }
+
+    #[test]
+    fn test_send_vesting() {
+        let mut contract = LinkDrop::new(owner(), None);
+        register_storage(&mut contract);
+
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob())
+            .attached_deposit(MIN_SEND_DEPOSIT)
+            .context.clone()
+        );
+        let vesting = Vesting { start: U64(1_000), cliff: U64(2_000), end: U64(11_000) };
+        contract.send_vesting(funding_key(), vesting.clone(), None).unwrap();
+        let key_info = contract.get_key_information(funding_key()).unwrap();
+        assert_eq!(key_info.vesting, Some(vesting));
+        assert_eq!(key_info.claimed, U128(0));
+    }
+
+    #[test]
+    fn test_send_vesting_cliff_after_end() {
+        let mut contract = LinkDrop::new(owner(), None);
+        register_storage(&mut contract);
+
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob())
+            .attached_deposit(MIN_SEND_DEPOSIT)
+            .context.clone()
+        );
+        let vesting = Vesting { start: U64(1_000), cliff: U64(12_000), end: U64(11_000) };
+        assert_eq!(
+            contract.send_vesting(funding_key(), vesting, None).err(),
+            Some(ContractError::InvalidArgument("The vesting cliff must be between its start and end".to_string()))
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Linkdrop is vesting until its cliff at 2000")]
+    fn test_claim_vesting_before_cliff() {
+        let mut contract = LinkDrop::new(owner(), None);
+        vesting_drop(&mut contract);
+
+        vesting_env(1_999, PromiseResult::Successful(vec![]));
+        contract.create_account_and_claim(new_account(), key(1), None, None).unwrap();
+    }
+
+    #[test]
+    fn test_claim_vesting_in_part() {
+        let mut contract = LinkDrop::new(owner(), None);
+        register_storage(&mut contract);
+        vesting_drop(&mut contract);
+
+        // Halfway through, half of the balance is claimed and the key stays for the rest
+        vesting_env(6_000, PromiseResult::Successful(vec![]));
+        contract.create_account_and_claim(new_account(), key(1), None, None).unwrap();
+        assert_eq!(contract.get_key_balance_or_panic(funding_key()), U128(500));
+        assert_eq!(contract.get_key_information(funding_key()).unwrap().claimed, U128(500));
+        assert_eq!(contract.total_locked, 500);
+
+        vesting_env(6_000, PromiseResult::Successful(vec![]));
+        let drop = DropInfo { uses_remaining: 0, ..drop_info(500) };
+        contract.on_account_created_and_claimed(new_account(), funding_key(), drop.clone(), true, None, false, false);
+        assert!(get_created_receipts().is_empty());
+
+        // A failed claim gives the amount back as unclaimed
+        vesting_env(8_000, PromiseResult::Successful(vec![]));
+        contract.claim(bob(), None).unwrap();
+        assert_eq!(get_created_receipts()[0].actions, vec![VmAction::Transfer { deposit: 200 }]);
+        vesting_env(8_000, PromiseResult::Failed);
+        contract.on_account_created_and_claimed(bob(), funding_key(), DropInfo { balance: U128(200), ..drop }, false, None, false, false);
+        let key_info = contract.get_key_information(funding_key()).unwrap();
+        assert_eq!((key_info.balance, key_info.claimed, key_info.uses_remaining), (U128(500), U128(500), 1));
+    }
+
+    #[test]
+    fn test_failed_vesting_claim_after_refunding() {
+        let mut contract = LinkDrop::new(owner(), None);
+        register_storage(&mut contract);
+        vesting_drop(&mut contract);
+
+        vesting_env(6_000, PromiseResult::Successful(vec![]));
+        contract.create_account_and_claim(new_account(), key(1), None, None).unwrap();
+        let claimed = DropInfo { uses_remaining: 0, ..drop_info(500) };
+        let vesting = contract.accounts.get(&funding_key()).unwrap().vesting;
+
+        // The key is funded anew while the claim is in flight, from scratch, with or without vesting
+        for refunded in [drop_info(1_000), DropInfo { vesting, ..drop_info(1_000) }] {
+            contract.accounts.insert(&funding_key(), &refunded);
+            vesting_env(6_000, PromiseResult::Failed);
+            contract.on_account_created_and_claimed(new_account(), funding_key(), claimed.clone(), true, None, false, false);
+            let key_info = contract.get_key_information(funding_key()).unwrap();
+            assert_eq!((key_info.balance, key_info.claimed), (U128(1_500), U128(0)));
+        }
+    }
+
+    #[test]
+    fn test_claim_vesting_after_end() {
+        let mut contract = LinkDrop::new(owner(), None);
+        register_storage(&mut contract);
+        contract.storage_accounts.insert(&bob(), &StorageAccount { total: 10 * drop_storage_cost(), used: drop_storage_cost() });
+        vesting_drop(&mut contract);
+        contract.accounts.insert(
+            &funding_key(),
+            &DropInfo { balance: U128(700), claimed: U128(300), ..contract.accounts.get(&funding_key()).unwrap() },
+        );
+        contract.total_locked = 700;
+
+        // Once vested, the rest of the balance is claimed with the last use
+        vesting_env(11_000, PromiseResult::Successful(vec![]));
+        contract.claim(bob(), None).unwrap();
+        assert_eq!(get_created_receipts()[0].actions, vec![VmAction::Transfer { deposit: 700 }]);
+        assert!(contract.get_key_balance(funding_key()).is_none());
+        assert_eq!(contract.total_locked, 0);
+
+        vesting_env(11_000, PromiseResult::Successful(vec![]));
+        contract.on_account_created_and_claimed(bob(), funding_key(), drop_info(700), false, None, true, false);
+        assert_eq!(get_created_receipts()[0].actions, vec![VmAction::DeleteKey { public_key: funding_key() }]);
     }
 }
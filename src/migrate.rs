@@ -0,0 +1,248 @@
+use crate::*;
+
+/// Storage key near_bindgen writes the contract state under.
+const STATE_KEY: &[u8] = b"STATE";
+
+/// State of the contract before linkdrops recorded their funder, when `accounts` held bare balances.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct OldLinkDrop {
+    /// Deprecated, becomes `legacy_accounts` as is, under the same prefix.
+    pub accounts: UnorderedMap<PublicKey, Balance>,
+}
+
+#[near_bindgen]
+impl LinkDrop {
+    /// Upgrade the state written by the `OldLinkDrop` layout. The contract account becomes the owner and the
+    /// treasury, nothing is paused or charged, and `total_locked` grows as `migrate_drops` moves the old balances.
+    /// A state already in the current layout only records the new `CONTRACT_VERSION` in `last_migration`, and is
+    /// refused if it's on that version already.
+    #[private]
+    #[init(ignore_state)]
+    pub fn migrate() -> Self {
+        let current = env::storage_read(STATE_KEY).and_then(|state| LinkDrop::try_from_slice(&state).ok());
+        if let Some(mut contract) = current {
+            assert_ne!(
+                contract.last_migration, CONTRACT_VERSION,
+                "The contract is already on version {}",
+                CONTRACT_VERSION
+            );
+            contract.last_migration = CONTRACT_VERSION.to_string();
+            return contract;
+        }
+        let old: OldLinkDrop = env::state_read().expect("Failed to read the old state");
+        let owner_id = env::current_account_id();
+        Self {
+            accounts: UnorderedMap::new(b"d"),
+            total_locked: 0,
+            allowed_contract_hashes: UnorderedSet::new(b"c"),
+            treasury_id: owner_id.clone(),
+            owner_id,
+            proposed_owner_id: None,
+            paused: false,
+            legacy_accounts: old.accounts,
+            storage_accounts: LookupMap::new(b"s"),
+            creation_fee: 0,
+            fee_bps: 0,
+            drops_by_funder: LookupMap::new(b"f"),
+            authorized_callers: UnorderedSet::new(b"w"),
+            council: UnorderedSet::new(b"m"),
+            required_confirmations: 0,
+            requests: UnorderedMap::new(b"r"),
+            next_request_id: 0,
+            min_new_account_balance: 0,
+            accounts_created: 0,
+            max_deposit_per_call: Balance::MAX,
+            max_total_locked: Balance::MAX,
+            idempotency_keys: LookupMap::new(b"i"),
+            creation_log: Vector::new(b"l"),
+            consecutive_failures: 0,
+            max_consecutive_failures: 0,
+            failing_creators: UnorderedSet::new(b"u"),
+            reserved_labels: UnorderedSet::new(b"n"),
+            creations_in_flight: LookupMap::new(b"p"),
+            refund_policy: RefundPolicy::RefundToPredecessor,
+            retained_refunds: 0,
+            pending_refunds: 0,
+            storage_deposits: 0,
+            per_account_cooldown: 0,
+            last_creation_at: LookupMap::new(b"q"),
+            debts: LookupMap::new(b"o"),
+            max_contract_size: DEFAULT_MAX_CONTRACT_SIZE,
+            claimed_keys: UnorderedSet::new(b"t"),
+            default_full_access_keys: vec![],
+            storage_reserve: 0,
+            claim_nonces: LookupMap::new(b"g"),
+            category_budgets: LookupMap::new(b"e"),
+            fee_overrides: UnorderedMap::new(b"v"),
+            last_migration: CONTRACT_VERSION.to_string(),
+        }
+    }
+
+    /// Move up to `limit` balances funded before drops recorded their funder into `accounts`, crediting them
+    /// to the owner. Returns how many were moved, call it again until it returns 0.
+    pub fn migrate_drops(&mut self, limit: u64) -> u64 {
+        self.assert_owner();
+        let keys: Vec<PublicKey> = self.legacy_accounts.keys().take(limit as usize).collect();
+        for public_key in keys.iter() {
+            let balance = self.legacy_accounts.remove(public_key).unwrap();
+            let mut drop = match self.accounts.get(public_key) {
+                Some(drop) => drop,
+                None => {
+                    // The contract already pays for the legacy entry, account for it without requiring a deposit
+                    let mut storage = self
+                        .storage_accounts
+                        .get(&self.owner_id)
+                        .unwrap_or(StorageAccount { total: 0, used: 0 });
+                    storage.used += drop_storage_cost();
+                    self.storage_accounts.insert(&self.owner_id, &storage);
+                    let owner_id = self.owner_id.clone();
+                    self.internal_index_drop(&owner_id, public_key);
+                    DropInfo {
+                        balance: U128(0),
+                        funder_id: self.owner_id.clone(),
+                        expires_at: None,
+                        ft: None,
+                        nft: None,
+                        metadata: None,
+                        secret_hash: None,
+                        uses_remaining: 1,
+                        amount_per_use: U128(0),
+                        last_claim_timestamp: 0,
+                        min_claim_interval: 0,
+                        vesting: None,
+                        claimed: U128(0),
+                        category: None,
+                        claim_callback_receiver: None,
+                        claim_callback_method: None,
+                    }
+                }
+            };
+            drop.add_balance(balance);
+            self.accounts.insert(public_key, &drop);
+            self.total_locked += balance;
+        }
+        keys.len() as u64
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[cfg(test)]
+mod tests {
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::testing_env;
+
+    use crate::tests::{bob, drop_info, key, linkdrop, owner};
+
+    use super::*;
+
+    #[test]
+    fn test_migrate_drops() {
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(owner())
+            .context.clone()
+        );
+        let mut contract = LinkDrop::new(owner(), None);
+        for i in 0..3 {
+            contract.legacy_accounts.insert(&key(i), &100);
+        }
+        contract.accounts.insert(&key(2), &DropInfo { funder_id: owner(), ..drop_info(50) });
+        contract.total_locked = 50;
+
+        // Entries are moved in batches
+        assert_eq!(contract.migrate_drops(2), 2);
+        assert_eq!(contract.migrate_drops(2), 1);
+        assert_eq!(contract.migrate_drops(2), 0);
+
+        // The owner is recorded as the funder and balances funded since are kept
+        assert!(contract.legacy_accounts.is_empty());
+        let info = contract.get_key_information(key(0)).unwrap();
+        assert_eq!(info.balance, U128(100));
+        assert_eq!(info.funder_id, owner());
+        assert_eq!(contract.get_drops_by_funder(owner(), None, None).len(), 2);
+        assert_eq!(contract.get_key_balance_or_panic(key(2)), U128(150));
+        assert_eq!(contract.get_total_locked_balance(), U128(350));
+    }
+
+    #[test]
+    fn test_migrate() {
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .context.clone()
+        );
+        let mut old = OldLinkDrop { accounts: UnorderedMap::new(b"a") };
+        old.accounts.insert(&key(0), &100);
+        old.accounts.insert(&key(1), &200);
+        env::state_write(&old);
+
+        let mut contract = LinkDrop::migrate();
+
+        // The new fields are defaulted and the old balances wait for migrate_drops
+        assert_eq!(contract.get_owner(), linkdrop());
+        assert_eq!(contract.get_treasury(), linkdrop());
+        assert_eq!(contract.get_proposed_owner(), None);
+        assert!(!contract.is_paused());
+        assert_eq!(contract.get_creation_fee(), U128(0));
+        assert_eq!(contract.get_key_total_supply(), 0);
+        assert_eq!(contract.get_total_locked_balance(), U128(0));
+        assert_eq!(contract.legacy_accounts.get(&key(1)), Some(200));
+
+        assert_eq!(contract.migrate_drops(10), 2);
+        assert_eq!(contract.get_key_balance_or_panic(key(0)), U128(100));
+        assert_eq!(contract.get_total_locked_balance(), U128(300));
+    }
+
+    #[test]
+    #[should_panic(expected = "The contract is already on version")]
+    fn test_repeat_migrate() {
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .context.clone()
+        );
+        env::state_write(&OldLinkDrop { accounts: UnorderedMap::new(b"a") });
+        let contract = LinkDrop::migrate();
+        assert_eq!(contract.last_migration, CONTRACT_VERSION);
+
+        env::state_write(&contract);
+        LinkDrop::migrate();
+    }
+
+    #[test]
+    fn test_migrate_records_version() {
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(linkdrop())
+            .context.clone()
+        );
+        let mut contract = LinkDrop::new(owner(), None);
+        contract.last_migration = "0.0.9".to_string();
+        contract.accounts.insert(&key(0), &drop_info(100));
+        env::state_write(&contract);
+
+        // A state in the current layout is kept as is, on the new version
+        let migrated = LinkDrop::migrate();
+        assert_eq!(migrated.last_migration, CONTRACT_VERSION);
+        assert_eq!(migrated.get_owner(), owner());
+        assert_eq!(migrated.get_key_balance_or_panic(key(0)), U128(100));
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the owner can call this method")]
+    fn test_migrate_drops_not_owner() {
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob())
+            .context.clone()
+        );
+        let mut contract = LinkDrop::new(owner(), None);
+
+        contract.migrate_drops(10);
+    }
+}
@@ -1,19 +1,505 @@
+use std::collections::HashSet;
+
 use near_sdk::serde::{Serialize, Deserialize};
 
 use crate::*;
 
+/// Account allowed to create top-level accounts, e.g. `alice` rather than `alice.near`.
+pub const REGISTRAR_ACCOUNT_ID: &str = "registrar";
+
+/// Whether a contract deployed at `parent_id` is able to create `new_account_id`. It has to be a direct
+/// subaccount of `parent_id`, or a top-level account when deployed at the registrar.
+pub fn can_create_account(new_account_id: &AccountId, parent_id: &AccountId) -> bool {
+    match new_account_id.as_str().split_once('.') {
+        Some((_, parent)) => parent == parent_id.as_str(),
+        None => parent_id.as_str() == REGISTRAR_ACCOUNT_ID && new_account_id != parent_id,
+    }
+}
+
+/// Length bounds of a NEAR account id.
+pub const MIN_ACCOUNT_ID_LEN: usize = 2;
+pub const MAX_ACCOUNT_ID_LEN: usize = 64;
+
+/// Returns an error unless `account_id` follows the runtime's rules: 2 to 64 lowercase alphanumerics, labels
+/// separated by `.` and joined by `-` or `_`, without leading, trailing or consecutive separators. Lets the
+/// creation fail right away rather than in the receipt creating the account, e.g. for an id built with
+/// `AccountId::new_unchecked` as deserializing one already checks it.
+pub fn validate_account_id_format(account_id: &AccountId) -> Result<(), String> {
+    validate_account_id_str(account_id.as_str())
+}
+
+/// `validate_account_id_format` on a plain string.
+pub fn validate_account_id_str(id: &str) -> Result<(), String> {
+    if id.len() < MIN_ACCOUNT_ID_LEN || id.len() > MAX_ACCOUNT_ID_LEN {
+        return Err(format!(
+            "Account id {} must be between {} and {} characters long",
+            id, MIN_ACCOUNT_ID_LEN, MAX_ACCOUNT_ID_LEN
+        ));
+    }
+    let is_separator = |c: char| matches!(c, '.' | '-' | '_');
+    if let Some(c) = id.chars().find(|c| !c.is_ascii_lowercase() && !c.is_ascii_digit() && !is_separator(*c)) {
+        return Err(format!("Account id {} contains {}, only lowercase alphanumerics, -, _ and . are allowed", id, c));
+    }
+    let mut previous_is_separator = true;
+    for c in id.chars() {
+        if is_separator(c) && previous_is_separator {
+            return Err(format!("Account id {} has a misplaced separator {}", id, c));
+        }
+        previous_is_separator = is_separator(c);
+    }
+    if previous_is_separator {
+        return Err(format!("Account id {} ends with a separator", id));
+    }
+    Ok(())
+}
+
+/// A linkdrop funded under a public key, stored in `accounts`. Also serialized to JSON to travel through the claim
+/// callback, which restores it if the claim fails.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DropInfo {
+    /// yoctoNEAR amount claimable with the key.
+    pub balance: U128,
+    /// The account that funded the linkdrop.
+    pub funder_id: AccountId,
+    /// Block timestamp, in nanoseconds, after which the linkdrop can't be claimed anymore and the funder can
+    /// reclaim it. Never expires when `None`.
+    pub expires_at: Option<U64>,
+    /// Fungible tokens transferred to this contract for the linkdrop with `ft_transfer_call`, if any.
+    pub ft: Option<FtDropData>,
+    /// NFT transferred to this contract for the linkdrop with `nft_transfer_call`, if any. A linkdrop holds
+    /// either fungible tokens or an NFT.
+    pub nft: Option<NftDropData>,
+    /// Off-chain campaign information given to `send`, e.g. a JSON title and image URL.
+    pub metadata: Option<String>,
+    /// sha256 of the secret the claims must give along with the key, if any.
+    pub secret_hash: Option<Vec<u8>>,
+    /// Number of claims left, the key is deleted after the last one.
+    pub uses_remaining: u32,
+    /// yoctoNEAR amount sent by every claim but the last, which takes whatever is left of the balance.
+    pub amount_per_use: U128,
+    /// Block timestamp in nanoseconds of the last successful claim, 0 before the first one.
+    pub last_claim_timestamp: u64,
+    /// Nanoseconds to wait after a successful claim before the next one, 0 for no limit.
+    pub min_claim_interval: u64,
+    /// Schedule unlocking the balance over time, claimable all at once when `None`.
+    pub vesting: Option<Vesting>,
+    /// yoctoNEAR amount claimed so far from a vesting linkdrop.
+    pub claimed: U128,
+    /// Campaign whose budget the funding of the linkdrop is charged to, see `CategoryBudget`.
+    pub category: Option<String>,
+    /// Contract notified of every successful claim by calling its `claim_callback_method`, e.g. a relay for the
+    /// webhooks of an off-chain campaign system. See `set_claim_callback`.
+    pub claim_callback_receiver: Option<AccountId>,
+    /// Method of `claim_callback_receiver` called with the details of the claim, at most `MAX_METHOD_NAME_BYTES`
+    /// long.
+    pub claim_callback_method: Option<String>,
+}
+
+impl DropInfo {
+    /// Whether the last successful claim is too recent to claim again at the current block timestamp. The first
+    /// claim is always allowed.
+    pub fn is_rate_limited(&self) -> bool {
+        self.last_claim_timestamp > 0
+            && env::block_timestamp().saturating_sub(self.last_claim_timestamp) < self.min_claim_interval
+    }
+
+    /// Whether the linkdrop can no longer be claimed at the current block timestamp.
+    pub fn is_expired(&self) -> bool {
+        self.expires_at.is_some_and(|expires_at| env::block_timestamp() > expires_at.0)
+    }
+
+    /// The contract and method to notify of a successful claim, if any.
+    pub fn claim_callback(&self) -> Option<(AccountId, String)> {
+        self.claim_callback_receiver.clone().zip(self.claim_callback_method.clone())
+    }
+
+    /// Add `amount` to the balance, spread evenly over the remaining uses.
+    pub fn add_balance(&mut self, amount: Balance) {
+        self.balance.0 += amount;
+        self.amount_per_use = U128(self.balance.0 / self.uses_remaining.max(1) as Balance);
+    }
+
+    /// yoctoNEAR amount sent by the next claim, only the part vested and not claimed yet for a vesting linkdrop.
+    pub fn claim_amount(&self) -> Balance {
+        if let Some(vesting) = &self.vesting {
+            vesting.vested(self.balance.0 + self.claimed.0).saturating_sub(self.claimed.0)
+        } else if self.uses_remaining > 1 {
+            self.amount_per_use.0
+        } else {
+            self.balance.0
+        }
+    }
+
+    /// yoctoNEAR kept out of the claimed balance to register the claimer with the token contract of an FT drop.
+    pub fn ft_storage_reserve(&self) -> Balance {
+        if self.ft.is_some() {
+            FT_STORAGE_DEPOSIT.min(self.balance.0)
+        } else {
+            0
+        }
+    }
+}
+
+/// Linear unlocking of the balance of a linkdrop, as block timestamps in nanoseconds: nothing can be claimed
+/// before the `cliff`, then the balance vests in proportion to the time elapsed since `start` until `end`.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Vesting {
+    pub start: U64,
+    pub cliff: U64,
+    pub end: U64,
+}
+
+impl Vesting {
+    /// Fails unless the cliff is between the start and the end.
+    pub fn check_valid(&self) -> Result<(), ContractError> {
+        ensure(
+            self.start.0 <= self.cliff.0 && self.cliff.0 <= self.end.0 && self.start.0 < self.end.0,
+            || ContractError::InvalidArgument("The vesting cliff must be between its start and end".to_string()),
+        )
+    }
+
+    /// Whether the current block timestamp is before the cliff.
+    pub fn is_before_cliff(&self) -> bool {
+        env::block_timestamp() < self.cliff.0
+    }
+
+    /// Part of `total` vested at the current block timestamp.
+    pub fn vested(&self, total: Balance) -> Balance {
+        let now = env::block_timestamp();
+        if self.is_before_cliff() {
+            return 0;
+        }
+        if now >= self.end.0 {
+            return total;
+        }
+        // Split the product to keep it from overflowing
+        let elapsed = (now - self.start.0) as Balance;
+        let duration = (self.end.0 - self.start.0) as Balance;
+        total / duration * elapsed + total % duration * elapsed / duration
+    }
+}
+
+/// Fungible tokens dropped along with the NEAR balance of a linkdrop, sent to the claimer with `ft_transfer`.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtDropData {
+    /// The NEP-141 contract of the tokens.
+    pub contract_id: AccountId,
+    /// Amount of tokens claimable with the key.
+    pub amount: U128,
+}
+
+/// An NFT dropped along with the NEAR balance of a linkdrop, sent to the claimer with `nft_transfer`.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NftDropData {
+    /// The NEP-171 contract of the token.
+    pub contract_id: AccountId,
+    pub token_id: String,
+}
+
+/// Storage balance of an account funding linkdrops, stored in `storage_accounts`.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct StorageAccount {
+    /// yoctoNEAR$ deposited through `storage_deposit`.
+    pub total: Balance,
+    /// yoctoNEAR$ covering the storage of the account's funded linkdrops.
+    pub used: Balance,
+}
+
+impl StorageAccount {
+    /// yoctoNEAR$ that can still be used or withdrawn.
+    pub fn available(&self) -> Balance {
+        self.total.saturating_sub(self.used)
+    }
+}
+
+/// NEP-145 storage balance of an account.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StorageBalance {
+    pub total: U128,
+    pub available: U128,
+}
+
+impl From<&StorageAccount> for StorageBalance {
+    fn from(storage: &StorageAccount) -> Self {
+        Self {
+            total: U128(storage.total),
+            available: U128(storage.available()),
+        }
+    }
+}
+
+impl From<&mut StorageAccount> for StorageBalance {
+    fn from(storage: &mut StorageAccount) -> Self {
+        (&*storage).into()
+    }
+}
+
+/// NEP-145 storage balance bounds.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StorageBalanceBounds {
+    pub min: U128,
+    pub max: Option<U128>,
+}
+
 /// Information about a specific public key. Should be returned in the `get_key_information` view method.
 /// Part of the linkdrop NEP
-#[derive(Serialize)]
+#[derive(Serialize, Debug, PartialEq)]
 #[serde(crate = "near_sdk::serde")]
 pub struct KeyInfo {
-    /// yoctoNEAR$ amount that will be sent to the claiming account (either new or existing)
+    /// The public key the linkdrop is funded under.
+    pub public_key: PublicKey,
+    /// yoctoNEAR amount that will be sent to the claiming account (either new or existing)
     /// when the key is successfully used.
     pub balance: U128,
+    /// The account that funded the linkdrop.
+    pub funder_id: AccountId,
+    /// Block timestamp, in nanoseconds, after which the linkdrop expires, if any.
+    pub expires_at: Option<U64>,
+    /// Fungible tokens sent to the claiming account along with the balance, if any.
+    pub ft: Option<FtDropData>,
+    /// NFT sent to the claiming account along with the balance, if any.
+    pub nft: Option<NftDropData>,
+    /// Off-chain campaign information of the linkdrop, if any.
+    pub metadata: Option<String>,
+    /// Whether the claims must give a secret along with the key.
+    pub requires_secret: bool,
+    /// Number of claims left with the key.
+    pub uses_remaining: u32,
+    /// yoctoNEAR amount sent by every claim but the last, which takes whatever is left of the balance.
+    pub amount_per_use: U128,
+    /// Block timestamp in nanoseconds of the last successful claim, 0 before the first one.
+    pub last_claim_timestamp: U64,
+    /// Nanoseconds to wait after a successful claim before the next one, 0 for no limit.
+    pub min_claim_interval: U64,
+    /// Schedule unlocking the balance over time, if any.
+    pub vesting: Option<Vesting>,
+    /// yoctoNEAR amount claimed so far from a vesting linkdrop.
+    pub claimed: U128,
 }
 
 
-#[derive(Serialize, Deserialize)]
+impl KeyInfo {
+    pub fn new(public_key: PublicKey, drop: DropInfo) -> Self {
+        Self {
+            public_key,
+            balance: drop.balance,
+            funder_id: drop.funder_id,
+            expires_at: drop.expires_at,
+            ft: drop.ft,
+            nft: drop.nft,
+            metadata: drop.metadata,
+            requires_secret: drop.secret_hash.is_some(),
+            uses_remaining: drop.uses_remaining,
+            amount_per_use: drop.amount_per_use,
+            last_claim_timestamp: U64(drop.last_claim_timestamp),
+            min_claim_interval: U64(drop.min_claim_interval),
+            vesting: drop.vesting,
+            claimed: drop.claimed,
+        }
+    }
+}
+
+/// What `create_account_advanced` costs with given options, returned by `get_account_creation_cost`.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AccountCreationCost {
+    /// Minimum deposit to attach, in yoctoNEAR.
+    pub deposit: U128,
+    /// Minimum gas to attach.
+    pub gas: U64,
+}
+
+/// Whether `create_account_and_claim` can fund a given new account, returned by `can_claim`.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ClaimFeasibility {
+    /// Whether the claim would go through and leave the new account with at least `required`.
+    pub feasible: bool,
+    /// yoctoNEAR the next claim would send to the new account.
+    pub claimable: U128,
+    /// yoctoNEAR the new account needs for its storage and full access key, or `min_new_account_balance` if more.
+    pub required: U128,
+    /// yoctoNEAR missing from `claimable` to cover `required`, 0 when it does.
+    pub shortfall: U128,
+    /// Why the claim would fail whatever the balance, e.g. the linkdrop expired or has no uses left.
+    pub reason: Option<String>,
+}
+
+/// What `create_account_and_claim` would send right now, returned by `simulate_claim`.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ClaimSimulation {
+    /// Whether the claim would go through, the amounts are 0 otherwise.
+    pub valid: bool,
+    /// yoctoNEAR the new account would receive.
+    pub amount: U128,
+    /// yoctoNEAR the beneficiary would receive.
+    pub beneficiary_amount: U128,
+    /// yoctoNEAR kept out of the claim to register the new account with the token contract of an FT drop.
+    pub ft_storage_reserve: U128,
+    /// Why the claim would fail, e.g. the linkdrop expired or has no uses left.
+    pub reason: Option<String>,
+}
+
+/// What this deployment supports, returned by `get_contract_features` so wallets can adapt to it. Fields are
+/// only ever added, clients should ignore the ones they don't know.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ContractFeatures {
+    /// Linkdrops can hold fungible tokens, sent with `ft_transfer_call`.
+    pub ft_drops: bool,
+    /// Linkdrops can hold an NFT, sent with `nft_transfer_call`.
+    pub nft_drops: bool,
+    /// Linkdrops can require a secret along with the key.
+    pub secrets: bool,
+    /// Linkdrops can expire and be reclaimed by their funder.
+    pub expiration: bool,
+    /// Linkdrops can be claimed several times.
+    pub multi_use: bool,
+    /// Linkdrops can be claimed by a relayer with a signature of the funding key.
+    pub signed_claims: bool,
+    /// Linkdrops can unlock their balance over time, sent with `send_vesting`.
+    pub vesting: bool,
+    /// Most keys `create_account_advanced` adds to an account.
+    pub max_keys_per_account: u64,
+    /// yoctoNEAR taken out of the deposit of every `create_account_advanced` call.
+    pub creation_fee: U128,
+    /// Basis points of the rest of the deposit also taken out as a fee.
+    pub fee_bps: u16,
+    /// Bytes a contract deployed by `create_account_with_contract` can take at most.
+    pub max_contract_size: U64,
+}
+
+/// Operational state of this deployment, returned by `health_check` so monitoring takes a single call. Fields are
+/// only ever added, clients should ignore the ones they don't know.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ContractHealth {
+    pub paused: bool,
+    /// yoctoNEAR$ locked in the funded linkdrops.
+    pub total_locked: U128,
+    /// Number of accounts successfully created, with or without a linkdrop.
+    pub accounts_created: u64,
+    /// Bytes of storage used by the contract.
+    pub storage_usage: U64,
+    /// yoctoNEAR$ the owner can withdraw, see `get_available_balance`.
+    pub available_balance: U128,
+    /// Creations that failed in a row since the last successful one.
+    pub consecutive_failures: u32,
+}
+
+/// What `create_account_advanced` would schedule with given options, returned by `describe_creation_plan`.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CreationPlan {
+    /// Whether the account would be created, `false` when `create_account_advanced` would refund right away.
+    pub creates_account: bool,
+    /// Number of full access keys added to the account.
+    pub full_access_keys: u32,
+    /// Function call access keys added to the account.
+    pub limited_access_keys: Vec<PlannedAccessKey>,
+    /// sha256 hash of the contract deployed to the account, if any.
+    pub contract_hash: Option<Base58CryptoHash>,
+    /// Method of the deployed contract called right after the deployment, if any.
+    pub init_method: Option<String>,
+}
+
+/// A function call access key of a `CreationPlan`.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PlannedAccessKey {
+    pub public_key: PublicKey,
+    pub allowance: U128,
+    pub receiver_id: AccountId,
+    /// Methods the key can call, any method of `receiver_id` when empty.
+    pub method_names: Vec<String>,
+}
+
+/// Status of a public key, returned by `get_key_status` for relayers polling for a claim to complete.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde", tag = "status", rename_all = "snake_case")]
+pub enum KeyStatus {
+    /// The key holds a linkdrop that can be claimed.
+    Funded { balance: U128 },
+    /// The key was never funded, or its linkdrop was claimed or reclaimed.
+    NotFound,
+}
+
+/// Where the deposit of a failed creation goes.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde", tag = "type", rename_all = "snake_case")]
+pub enum RefundPolicy {
+    /// Back to the predecessor of the creation, or the `refund_to` it asked for.
+    RefundToPredecessor,
+    /// To `account_id`, whatever the creation asked for.
+    RefundTo { account_id: AccountId },
+    /// Kept for the treasury for manual handling, see `withdraw_retained_refunds`.
+    Retain,
+}
+
+/// A privileged operation, executed through a council request once multi-sig is enabled.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde", tag = "type", rename_all = "snake_case")]
+pub enum RequestAction {
+    SetCreationFee { creation_fee: U128 },
+    SetFeeBps { fee_bps: u16 },
+    SetFeeOverride { account_id: AccountId, fee_config: Option<FeeConfig> },
+    SetTreasury { treasury_id: AccountId },
+    SetMinNewAccountBalance { min_new_account_balance: U128 },
+    SetMaxDepositPerCall { max_deposit_per_call: U128 },
+    SetMaxTotalLocked { max_total_locked: U128 },
+    SetMaxConsecutiveFailures { max_consecutive_failures: u32 },
+    SetRefundPolicy { refund_policy: RefundPolicy },
+    SetPerAccountCooldown { per_account_cooldown: U64 },
+    SetMaxContractSize { max_contract_size: U64 },
+    WithdrawContractBalance { amount: U128, receiver_id: AccountId },
+    AddAllowedContractHash { code_hash: Base58CryptoHash },
+    RemoveAllowedContractHash { code_hash: Base58CryptoHash },
+    AddAuthorizedCaller { account_id: AccountId },
+    RemoveAuthorizedCaller { account_id: AccountId },
+    AddReservedLabel { label: String },
+    RemoveReservedLabel { label: String },
+    ClearTombstone { public_key: PublicKey },
+    SetCategoryBudget { category: String, limit: U128 },
+    SetDefaultFullAccessKeys { public_keys: Vec<PublicKey> },
+    DeleteKey { public_key: PublicKey, recipient_id: AccountId },
+    BatchReclaimExpired { keys: Vec<PublicKey> },
+    SetCouncil { council: Vec<AccountId>, required_confirmations: u32 },
+}
+
+/// A privileged operation proposed by a council member, stored in `requests` until enough members confirm it.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Request {
+    pub action: RequestAction,
+    /// Council members who confirmed the request, starting with the one who proposed it.
+    pub confirmations: Vec<AccountId>,
+    /// Block timestamp in nanoseconds after which the request can't be confirmed anymore.
+    pub expires_at: U64,
+}
+
+/// Result of an account creation, returned by the `on_account_created` callback so relayers can read it directly.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CreationOutcome {
+    /// Whether the account was created.
+    pub success: bool,
+    /// The account that was being created.
+    pub new_account_id: AccountId,
+    /// yoctoNEAR amount sent back to the predecessor, zero when the creation succeeded.
+    pub refunded: U128,
+    /// Gas burnt by the callback settling the creation up to the outcome, to help relayers tune the gas they
+    /// attach. Doesn't include the gas of the creation receipt itself nor of the refund.
+    pub gas_burnt: U64,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 #[serde(crate = "near_sdk::serde")]
 /// Information about any limited access keys that are being added to the account as part of `create_account_advanced`.
 pub struct LimitedAccessKey {
@@ -23,8 +509,93 @@ pub struct LimitedAccessKey {
     pub allowance: U128,
     /// Which contract should this key be allowed to call.
     pub receiver_id: AccountId,
-    /// Which methods should this key be allowed to call.
+    /// Which methods should this key be allowed to call, comma separated. An empty string allows the key to
+    /// call any method on `receiver_id`.
     pub method_names: String,
+    /// Nonce to start the key at, e.g. above the nonces of transactions signed for a deleted account. The
+    /// runtime picks one when `None`.
+    pub nonce: Option<U64>,
+}
+
+impl LimitedAccessKey {
+    /// Fails if the key can't be added as a usable function call access key. `index` is the position of the
+    /// key in `limited_access_keys`, to point the caller at the offending entry.
+    /// An empty `receiver_id` never gets this far as `AccountId` refuses to deserialize it.
+    pub fn check(&self, index: usize) -> Result<(), String> {
+        if self.allowance.0 == 0 {
+            return Err(format!("Limited access key at index {} has a zero allowance", index));
+        }
+        let method_names: Vec<String> = self
+            .method_names
+            .split(',')
+            .filter(|name| !name.is_empty())
+            .map(str::to_string)
+            .collect();
+        check_method_names(&method_names)
+    }
+}
+
+/// Settings of a limited access key shared by several public keys, see `TemplatedAccessKeys`.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct LimitedAccessKeyTemplate {
+    pub allowance: U128,
+    pub receiver_id: AccountId,
+    /// Comma separated, like `LimitedAccessKey::method_names`.
+    pub method_names: String,
+}
+
+/// Limited access keys added with the same settings, each expanded into a `LimitedAccessKey` after those of
+/// `limited_access_keys`.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TemplatedAccessKeys {
+    pub template: LimitedAccessKeyTemplate,
+    pub public_keys: Vec<PublicKey>,
+}
+
+/// Maximum number of method names a function call access key added by this contract can be restricted to.
+pub const MAX_METHOD_NAMES: usize = 100;
+
+/// Maximum length in bytes of a method name accepted by the runtime for a function call access key.
+pub const MAX_METHOD_NAME_BYTES: usize = 256;
+
+/// Returns an error unless `public_key` holds as many bytes as its curve needs: 32 for ed25519 and 64 for
+/// secp256k1, so that a malformed key is refused up front rather than failing the action that adds it.
+pub fn check_public_key(public_key: &PublicKey) -> Result<(), String> {
+    let (curve, expected_bytes) = match public_key.curve_type() {
+        CurveType::ED25519 => ("ed25519", 32),
+        CurveType::SECP256K1 => ("secp256k1", 64),
+    };
+    let bytes = public_key.as_bytes().len() - 1;
+    if bytes != expected_bytes {
+        return Err(format!(
+            "Public key {} has {} bytes, a {} key has {}",
+            String::from(public_key),
+            bytes,
+            curve,
+            expected_bytes
+        ));
+    }
+    Ok(())
+}
+
+/// Returns an error if the runtime would refuse a function call access key restricted to `method_names`,
+/// naming the offending method rather than failing the whole transaction.
+pub fn check_method_names(method_names: &[String]) -> Result<(), String> {
+    if method_names.len() > MAX_METHOD_NAMES {
+        return Err(format!(
+            "Cannot restrict an access key to {} method names, at most {} are allowed",
+            method_names.len(),
+            MAX_METHOD_NAMES
+        ));
+    }
+    for method_name in method_names {
+        if method_name.len() > MAX_METHOD_NAME_BYTES {
+            return Err(format!("Method name {} is longer than {} bytes", method_name, MAX_METHOD_NAME_BYTES));
+        }
+    }
+    Ok(())
 }
     
 #[derive(Serialize, Deserialize)]
@@ -32,7 +603,290 @@ pub struct LimitedAccessKey {
 /// Options for `create_account_advanced`.
 pub struct CreateAccountOptions {
     pub full_access_keys: Option<Vec<PublicKey>>,
+    /// Nonce to start the full access keys at, like `LimitedAccessKey::nonce`.
+    pub full_access_key_nonce: Option<U64>,
     pub limited_access_keys: Option<Vec<LimitedAccessKey>>,
+    /// Limited access keys sharing their settings, so they don't have to be repeated for every key.
+    pub templated_access_keys: Option<TemplatedAccessKeys>,
     pub contract_bytes: Option<Vec<u8>>,
+    /// Method of the deployed contract to call right after the deployment, in the same receipt.
+    pub deploy_init: Option<InitCall>,
+    /// Part of the deposit to stake right after the deployment and the init call, in the same receipt.
+    pub stake: Option<StakeCall>,
+}
+
+/// A function call on the contract deployed to a new account, e.g. its `new` method, so it's initialized along
+/// with the creation.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct InitCall {
+    pub method: String,
+    pub args: Base64VecU8,
+    /// yoctoNEAR$ attached to the call, taken out of the deposit of the creation.
+    pub deposit: U128,
+    pub gas: Gas,
+}
+
+/// Method of the contract deployed to a new account called by `StakeCall`.
+pub const STAKE_METHOD_NAME: &str = "deposit_and_stake";
+
+/// A call to the `deposit_and_stake` method of the contract deployed to a new account, e.g. a lockup-style
+/// contract, attaching `amount` for it to stake with `staking_pool_id`. Only the new account can delegate its own
+/// stake: a pool credits the stake to its caller, so this contract calling the pool would stake for itself.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StakeCall {
+    pub staking_pool_id: AccountId,
+    /// yoctoNEAR$ to stake, taken out of the deposit of the creation.
+    pub amount: U128,
+    pub gas: Gas,
+}
+
+/// Settings of a linkdrop given to `send`, only applied when the key is first funded.
+#[derive(Default)]
+pub struct SendOptions {
+    pub expires_at: Option<U64>,
+    pub metadata: Option<String>,
+    pub uses: Option<u32>,
+    pub secret_hash: Option<Base64VecU8>,
+    pub min_claim_interval: Option<U64>,
+    pub vesting: Option<Vesting>,
+    pub category: Option<String>,
+}
+
+/// Outcome of a creation callback, appended to `creation_log` for auditors.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CreationRecord {
+    pub new_account_id: AccountId,
+    /// The account that called for the creation.
+    pub predecessor_account_id: AccountId,
+    /// yoctoNEAR amount sent to the new account, refunded if the creation failed.
+    pub amount: U128,
+    pub success: bool,
+    /// Block timestamp in nanoseconds of the callback.
+    pub timestamp: U64,
+}
+
+/// An account created with an idempotency key, remembered to short-circuit retries until `expires_at`.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct IdempotentCreation {
+    pub new_account_id: AccountId,
+    /// Block timestamp in nanoseconds after which retries create the account again.
+    pub expires_at: u64,
+}
+
+impl IdempotentCreation {
+    /// Whether the creation is too old to short-circuit retries.
+    pub fn is_expired(&self) -> bool {
+        env::block_timestamp() > self.expires_at
+    }
+}
+
+/// What an account created with a debt still owes to the sponsor of its creation, see `repay`.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DebtRecord {
+    /// The account that paid for the creation and gets the repayments.
+    pub sponsor_id: AccountId,
+    /// yoctoNEAR$ left to repay.
+    pub amount: U128,
+}
+
+/// Fees charged on the creations of a caller, overriding the global `creation_fee` and `fee_bps`, e.g. a
+/// waiver negotiated by a partner relayer. See `set_fee_override`.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FeeConfig {
+    /// Flat yoctoNEAR$ fee, like `creation_fee`.
+    pub creation_fee: U128,
+    /// Basis points of the rest of the deposit, like `fee_bps`.
+    pub fee_bps: u16,
+}
+
+impl FeeConfig {
+    /// yoctoNEAR taken out of `deposit` for the treasury: `creation_fee` plus `fee_bps` of the rest, rounded
+    /// down. `deposit` must cover `creation_fee`.
+    pub fn fees_of(&self, deposit: Balance) -> Balance {
+        let amount = deposit - self.creation_fee.0;
+        let fee_bps = self.fee_bps as Balance;
+        let max_fee_bps = MAX_FEE_BPS as Balance;
+        // Split the product so it can't overflow
+        let percentage_fee = amount / max_fee_bps * fee_bps + amount % max_fee_bps * fee_bps / max_fee_bps;
+        self.creation_fee.0 + percentage_fee
+    }
+}
+
+/// What the linkdrops of a category can lock at most, set by the owner with `set_category_budget`.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CategoryBudget {
+    /// yoctoNEAR$ the category can spend at most.
+    pub limit: U128,
+    /// yoctoNEAR$ funded under the category, less what was reclaimed.
+    pub spent: U128,
+}
+
+/// A single account to create with `create_accounts`.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CreateAccountRequest {
+    pub new_account_id: AccountId,
+    pub options: CreateAccountOptions,
+    /// yoctoNEAR$ amount out of the attached deposit used for this account, including the creation fee.
+    pub deposit: U128,
+}
+
+impl CreateAccountOptions {
+    /// The limited access keys to add, those of `limited_access_keys` followed by the expanded
+    /// `templated_access_keys`.
+    pub fn limited_keys(&self) -> Vec<LimitedAccessKey> {
+        let mut keys: Vec<LimitedAccessKey> = self.limited_access_keys.clone().unwrap_or_default();
+        if let Some(TemplatedAccessKeys { template, public_keys }) = &self.templated_access_keys {
+            keys.extend(public_keys.iter().map(|public_key| LimitedAccessKey {
+                public_key: public_key.clone(),
+                allowance: template.allowance,
+                receiver_id: template.receiver_id.clone(),
+                method_names: template.method_names.clone(),
+                nonce: None,
+            }));
+        }
+        keys
+    }
+
+    /// Fails if the options add more than `MAX_KEYS_PER_ACCOUNT` keys, or the same public key more than once
+    /// across both lists, either of which would fail the whole creation on-chain.
+    pub fn check_unique_keys(&self) -> Result<(), String> {
+        let full_access_keys = self.full_access_keys.iter().flatten();
+        let limited_access_keys = self.limited_keys();
+        let limited_access_keys = limited_access_keys.iter().map(|key_info| &key_info.public_key);
+        let keys: Vec<&PublicKey> = full_access_keys.chain(limited_access_keys).collect();
+
+        if keys.len() as u64 > MAX_KEYS_PER_ACCOUNT {
+            return Err(format!(
+                "Cannot add {} keys, at most {} keys can be added to an account",
+                keys.len(),
+                MAX_KEYS_PER_ACCOUNT
+            ));
+        }
+
+        let mut seen = HashSet::new();
+        for key in keys {
+            if !seen.insert(key) {
+                return Err(format!("Public key {} is added more than once", String::from(key)));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use super::*;
+
+    fn account(id: &str) -> AccountId {
+        id.parse().unwrap()
+    }
+
+    #[test]
+    fn test_can_create_account() {
+        let current = account("current");
+
+        assert!(can_create_account(&account("a.current"), &current));
+        assert!(!can_create_account(&account("a.b.current"), &current));
+        assert!(!can_create_account(&account("current"), &current));
+        assert!(!can_create_account(&account("other.near"), &current));
+        assert!(!can_create_account(&account("acurrent"), &current));
+        assert!(!can_create_account(&account("other"), &current));
+    }
+
+    #[test]
+    fn test_can_create_account_from_registrar() {
+        let registrar = account(REGISTRAR_ACCOUNT_ID);
+
+        assert!(can_create_account(&account("other"), &registrar));
+        assert!(can_create_account(&account("a.registrar"), &registrar));
+        assert!(!can_create_account(&account("registrar"), &registrar));
+        assert!(!can_create_account(&account("other.near"), &registrar));
+    }
+
+    #[test]
+    fn test_validate_account_id_format() {
+        for id in ["ab", "bob.linkdrop", "a-b_c.near", "0x1", &"a".repeat(MAX_ACCOUNT_ID_LEN)] {
+            assert_eq!(validate_account_id_format(&account(id)), Ok(()), "{}", id);
+        }
+    }
+
+    #[test]
+    fn test_validate_account_id_format_length() {
+        assert_eq!(
+            validate_account_id_str("a"),
+            Err("Account id a must be between 2 and 64 characters long".to_string())
+        );
+        let too_long = "a".repeat(MAX_ACCOUNT_ID_LEN + 1);
+        assert_eq!(
+            validate_account_id_str(&too_long),
+            Err(format!("Account id {} must be between 2 and 64 characters long", too_long))
+        );
+    }
+
+    #[test]
+    fn test_validate_account_id_format_characters() {
+        assert_eq!(
+            validate_account_id_str("Bob.near"),
+            Err("Account id Bob.near contains B, only lowercase alphanumerics, -, _ and . are allowed".to_string())
+        );
+        assert!(validate_account_id_str("bob@near").is_err());
+    }
+
+    #[test]
+    fn test_validate_account_id_format_separators() {
+        assert_eq!(
+            validate_account_id_str("bob..near"),
+            Err("Account id bob..near has a misplaced separator .".to_string())
+        );
+        assert!(validate_account_id_str(".bob").is_err());
+        assert!(validate_account_id_str("bob-.near").is_err());
+        assert_eq!(validate_account_id_str("bob_"), Err("Account id bob_ ends with a separator".to_string()));
+    }
+
+    #[test]
+    fn test_check_method_names() {
+        assert!(check_method_names(&[]).is_ok());
+        assert!(check_method_names(&["a".repeat(MAX_METHOD_NAME_BYTES)]).is_ok());
+        assert!(check_method_names(&vec!["claim".to_string(); MAX_METHOD_NAMES]).is_ok());
+    }
+
+    #[test]
+    fn test_check_method_names_too_long() {
+        let error = check_method_names(&["claim".to_string(), "a".repeat(MAX_METHOD_NAME_BYTES + 1)]).unwrap_err();
+        assert!(error.ends_with("is longer than 256 bytes"));
+    }
+
+    #[test]
+    fn test_check_method_names_too_many() {
+        assert_eq!(
+            check_method_names(&vec!["claim".to_string(); MAX_METHOD_NAMES + 1]),
+            Err("Cannot restrict an access key to 101 method names, at most 100 are allowed".to_string())
+        );
+    }
+
+    #[test]
+    fn test_check_public_key() {
+        let ed25519 = PublicKey::try_from([vec![0], vec![1; 32]].concat()).unwrap();
+        let secp256k1 = PublicKey::try_from([vec![1], vec![1; 64]].concat()).unwrap();
+
+        assert_eq!(check_public_key(&ed25519), Ok(()));
+        assert_eq!(check_public_key(&secp256k1), Ok(()));
+    }
+
+    #[test]
+    fn test_truncated_public_key() {
+        // A truncated key can't even be deserialized, from bytes or base58
+        assert!(PublicKey::try_from([vec![0], vec![1; 31]].concat()).is_err());
+        assert!(PublicKey::try_from([vec![1], vec![1; 32]].concat()).is_err());
+        assert!("ed25519:6E8sCci9badyRkXb3JoRpBj5p8C6Tw41ELDZoiihKE".parse::<PublicKey>().is_err());
+    }
 }
-    
\ No newline at end of file
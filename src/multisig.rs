@@ -0,0 +1,303 @@
+use crate::*;
+
+/// Nanoseconds a request can be confirmed for once proposed: 7 days.
+pub const REQUEST_LIFETIME: u64 = 7 * 24 * 60 * 60 * 1_000_000_000;
+
+#[near_bindgen]
+impl LinkDrop {
+    /// Set the council confirming the privileged operations and how many of its members must confirm each of
+    /// them. Only callable by the owner while multi-sig is disabled, afterwards through a `SetCouncil` request.
+    /// Requiring 0 confirmations disables multi-sig again.
+    pub fn set_council(&mut self, council: Vec<AccountId>, required_confirmations: u32) {
+        self.assert_owner_action();
+        self.internal_set_council(council, required_confirmations);
+    }
+
+    /// Propose `action` as a council member, which counts as its first confirmation. Returns the id of the
+    /// request, executed right away if a single confirmation is enough.
+    pub fn add_request(&mut self, action: RequestAction) -> u64 {
+        self.assert_council_member();
+        let request_id = self.next_request_id;
+        self.next_request_id += 1;
+        let request = Request {
+            action,
+            confirmations: vec![],
+            expires_at: U64(env::block_timestamp() + REQUEST_LIFETIME),
+        };
+        self.requests.insert(&request_id, &request);
+        self.confirm(request_id);
+        request_id
+    }
+
+    /// Confirm a request as a council member, executing it once it has `required_confirmations`. Returns whether
+    /// it was executed. An expired request is removed instead, unexecuted.
+    pub fn confirm(&mut self, request_id: u64) -> bool {
+        self.assert_council_member();
+        let mut request = self.requests.get(&request_id).expect("Request is missing");
+        if env::block_timestamp() > request.expires_at.0 {
+            self.requests.remove(&request_id);
+            env::log_str(&format!("Request {} has expired", request_id));
+            return false;
+        }
+        let member_id = env::predecessor_account_id();
+        assert!(
+            !request.confirmations.contains(&member_id),
+            "The request is already confirmed by this member"
+        );
+        // Members who left the council since they confirmed don't count anymore
+        request.confirmations.retain(|confirmed_by| self.council.contains(confirmed_by));
+        request.confirmations.push(member_id);
+        if (request.confirmations.len() as u32) < self.required_confirmations {
+            self.requests.insert(&request_id, &request);
+            return false;
+        }
+        self.requests.remove(&request_id);
+        self.internal_execute_request(request.action);
+        true
+    }
+
+    /// Returns the pending request with the given id, if any.
+    pub fn get_request(&self, request_id: u64) -> Option<Request> {
+        self.requests.get(&request_id)
+    }
+
+    /// Returns the members of the council.
+    pub fn get_council(&self) -> Vec<AccountId> {
+        self.council.to_vec()
+    }
+
+    /// Returns the council confirmations needed to execute a privileged operation, 0 while multi-sig is disabled.
+    pub fn get_required_confirmations(&self) -> u32 {
+        self.required_confirmations
+    }
+}
+
+impl LinkDrop {
+    /// Perform a confirmed privileged operation.
+    fn internal_execute_request(&mut self, action: RequestAction) {
+        match action {
+            RequestAction::SetCreationFee { creation_fee } => self.creation_fee = creation_fee.0,
+            RequestAction::SetFeeBps { fee_bps } => {
+                self.internal_set_fee_bps(fee_bps).unwrap_or_else(|error| panic!("{}", error));
+            }
+            RequestAction::SetFeeOverride { account_id, fee_config } => {
+                self.internal_set_fee_override(account_id, fee_config).unwrap_or_else(|error| panic!("{}", error));
+            }
+            RequestAction::SetTreasury { treasury_id } => self.treasury_id = treasury_id,
+            RequestAction::SetMinNewAccountBalance { min_new_account_balance } => {
+                self.min_new_account_balance = min_new_account_balance.0
+            }
+            RequestAction::SetMaxDepositPerCall { max_deposit_per_call } => {
+                self.max_deposit_per_call = max_deposit_per_call.0
+            }
+            RequestAction::SetMaxTotalLocked { max_total_locked } => self.max_total_locked = max_total_locked.0,
+            RequestAction::SetMaxConsecutiveFailures { max_consecutive_failures } => {
+                self.max_consecutive_failures = max_consecutive_failures
+            }
+            RequestAction::SetRefundPolicy { refund_policy } => self.refund_policy = refund_policy,
+            RequestAction::SetPerAccountCooldown { per_account_cooldown } => {
+                self.per_account_cooldown = per_account_cooldown.0
+            }
+            RequestAction::SetMaxContractSize { max_contract_size } => self.max_contract_size = max_contract_size.0,
+            RequestAction::WithdrawContractBalance { amount, receiver_id } => {
+                drop(self.internal_withdraw_contract_balance(amount, receiver_id).unwrap_or_else(|error| panic!("{}", error)));
+            }
+            RequestAction::AddAllowedContractHash { code_hash } => {
+                self.allowed_contract_hashes.insert(&CryptoHash::from(code_hash).to_vec());
+            }
+            RequestAction::RemoveAllowedContractHash { code_hash } => {
+                self.allowed_contract_hashes.remove(&CryptoHash::from(code_hash).to_vec());
+            }
+            RequestAction::AddAuthorizedCaller { account_id } => {
+                self.authorized_callers.insert(&account_id);
+            }
+            RequestAction::RemoveAuthorizedCaller { account_id } => {
+                self.authorized_callers.remove(&account_id);
+            }
+            RequestAction::AddReservedLabel { label } => {
+                self.reserved_labels.insert(&label.to_lowercase());
+            }
+            RequestAction::RemoveReservedLabel { label } => {
+                self.reserved_labels.remove(&label.to_lowercase());
+            }
+            RequestAction::SetDefaultFullAccessKeys { public_keys } => {
+                self.internal_set_default_full_access_keys(public_keys).unwrap_or_else(|error| panic!("{}", error));
+            }
+            RequestAction::ClearTombstone { public_key } => {
+                self.claimed_keys.remove(&public_key);
+            }
+            RequestAction::SetCategoryBudget { category, limit } => {
+                self.internal_set_category_budget(category, limit).unwrap_or_else(|error| panic!("{}", error));
+            }
+            RequestAction::DeleteKey { public_key, recipient_id } => {
+                self.internal_delete_key(public_key, recipient_id).unwrap_or_else(|error| panic!("{}", error));
+            }
+            RequestAction::BatchReclaimExpired { keys } => {
+                self.internal_batch_reclaim_expired(keys).unwrap_or_else(|error| panic!("{}", error));
+            }
+            RequestAction::SetCouncil { council, required_confirmations } => {
+                self.internal_set_council(council, required_confirmations)
+            }
+        }
+    }
+
+    fn internal_set_council(&mut self, council: Vec<AccountId>, required_confirmations: u32) {
+        self.council.clear();
+        for member_id in council.iter() {
+            self.council.insert(member_id);
+        }
+        assert!(
+            required_confirmations as u64 <= self.council.len(),
+            "Cannot require {} confirmations from a council of {} members",
+            required_confirmations,
+            self.council.len()
+        );
+        self.required_confirmations = required_confirmations;
+    }
+
+    /// Panics unless multi-sig is enabled and the predecessor is a member of the council.
+    fn assert_council_member(&self) {
+        assert!(self.required_confirmations > 0, "Multi-sig is disabled, the owner acts alone");
+        assert!(
+            self.council.contains(&env::predecessor_account_id()),
+            "Only council members can call this method"
+        );
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[cfg(test)]
+mod tests {
+    use near_sdk::test_utils::{get_logs, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use crate::tests::{bob, drop_info, funding_key, linkdrop, owner};
+
+    use super::*;
+
+    fn carol() -> AccountId {
+        "carol".parse().unwrap()
+    }
+
+    fn set_context(predecessor_account_id: AccountId, block_timestamp: u64) {
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(predecessor_account_id)
+            .block_timestamp(block_timestamp)
+            .context.clone()
+        );
+    }
+
+    /// A contract whose privileged operations need 2 confirmations from bob, carol and the owner.
+    fn multisig_contract() -> LinkDrop {
+        set_context(owner(), 0);
+        let mut contract = LinkDrop::new(owner(), None);
+        contract.set_council(vec![bob(), carol(), owner()], 2);
+        contract
+    }
+
+    fn fee_request() -> RequestAction {
+        RequestAction::SetCreationFee { creation_fee: U128(100) }
+    }
+
+    #[test]
+    fn test_request_executes_at_threshold() {
+        let mut contract = multisig_contract();
+
+        // The proposal only counts as one confirmation
+        set_context(bob(), 0);
+        let request_id = contract.add_request(fee_request());
+        assert_eq!(contract.get_request(request_id).unwrap().confirmations, vec![bob()]);
+        assert_eq!(contract.get_creation_fee(), U128(0));
+
+        set_context(carol(), 0);
+        assert!(contract.confirm(request_id));
+        assert_eq!(contract.get_creation_fee(), U128(100));
+        assert!(contract.get_request(request_id).is_none());
+    }
+
+    #[test]
+    fn test_batch_reclaim_request() {
+        let mut contract = multisig_contract();
+        contract.accounts.insert(&funding_key(), &DropInfo { expires_at: Some(U64(1_000)), ..drop_info(1_000) });
+        contract.total_locked = 1_000;
+
+        // Reclaiming is privileged, it waits on the council like deleting a key
+        set_context(bob(), 1_001);
+        let request_id = contract.add_request(RequestAction::BatchReclaimExpired { keys: vec![funding_key()] });
+        assert!(contract.accounts.get(&funding_key()).is_some());
+        set_context(carol(), 1_001);
+        assert!(contract.confirm(request_id));
+        assert!(contract.accounts.get(&funding_key()).is_none());
+        assert_eq!(contract.get_total_locked_balance(), U128(0));
+    }
+
+    #[test]
+    fn test_request_expires() {
+        let mut contract = multisig_contract();
+
+        set_context(bob(), 0);
+        let request_id = contract.add_request(fee_request());
+
+        // Confirming too late drops the request without executing it
+        set_context(carol(), REQUEST_LIFETIME + 1);
+        assert!(!contract.confirm(request_id));
+        assert_eq!(get_logs(), vec![format!("Request {} has expired", request_id)]);
+        assert_eq!(contract.get_creation_fee(), U128(0));
+        assert!(contract.get_request(request_id).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "The request is already confirmed by this member")]
+    fn test_confirm_twice() {
+        let mut contract = multisig_contract();
+
+        set_context(bob(), 0);
+        let request_id = contract.add_request(fee_request());
+        contract.confirm(request_id);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only council members can call this method")]
+    fn test_add_request_not_member() {
+        let mut contract = multisig_contract();
+
+        set_context(linkdrop(), 0);
+        contract.add_request(fee_request());
+    }
+
+    #[test]
+    fn test_owner_needs_council() {
+        let mut contract = multisig_contract();
+
+        set_context(owner(), 0);
+        assert_eq!(
+            contract.set_creation_fee(U128(100)).err(),
+            Some(ContractError::Unauthorized("This operation needs the confirmation of the council, propose it with add_request"))
+        );
+    }
+
+    #[test]
+    fn test_disable_multisig() {
+        let mut contract = multisig_contract();
+
+        set_context(bob(), 0);
+        let request_id = contract.add_request(RequestAction::SetCouncil { council: vec![], required_confirmations: 0 });
+        set_context(owner(), 0);
+        contract.confirm(request_id);
+
+        // The owner acts alone again
+        contract.set_creation_fee(U128(100)).unwrap();
+        assert_eq!(contract.get_creation_fee(), U128(100));
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot require 3 confirmations from a council of 2 members")]
+    fn test_set_council_above_size() {
+        set_context(owner(), 0);
+        let mut contract = LinkDrop::new(owner(), None);
+
+        contract.set_council(vec![bob(), carol()], 3);
+    }
+}
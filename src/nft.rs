@@ -0,0 +1,217 @@
+use crate::*;
+
+/// Gas attached to `nft_transfer` on the token contract.
+pub const NFT_TRANSFER_GAS: Gas = Gas(10_000_000_000_000);
+
+/// Gas attached to the callback after sending the NFT of an NFT drop.
+pub const ON_NFT_CLAIMED_CALLBACK_GAS: Gas = Gas(10_000_000_000_000);
+
+/// Gas attached to the claim callback of an NFT drop, which also pays for sending the token and the callback
+/// after it.
+pub const ON_NFT_DROP_CLAIMED_CALLBACK_GAS: Gas =
+    Gas(ON_CREATE_ACCOUNT_CALLBACK_GAS.0 + NFT_TRANSFER_GAS.0 + ON_NFT_CLAIMED_CALLBACK_GAS.0);
+
+/// Longest token id accepted for an NFT drop, bounding the storage of the linkdrop.
+pub const MAX_NFT_TOKEN_ID_BYTES: usize = 128;
+
+/// Send the NFT of `nft` back to the funder.
+pub(crate) fn internal_refund_nft(nft: &NftDropData, funder_id: &AccountId) -> Promise {
+    ext_nft::ext(nft.contract_id.clone())
+        .with_attached_deposit(1)
+        .with_static_gas(NFT_TRANSFER_GAS)
+        .nft_transfer(funder_id.clone(), nft.token_id.clone(), None, None)
+}
+
+#[near_bindgen]
+impl LinkDrop {
+    /// NEP-171 receiver: add the token transferred with `nft_transfer_call` to the linkdrop of the public key
+    /// given as `msg`. The key must already be funded by `previous_owner_id` with `send`. A linkdrop holds a
+    /// single NFT, and no fungible tokens along with it. Returns whether the token should go back to its owner.
+    pub fn nft_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        previous_owner_id: AccountId,
+        token_id: String,
+        msg: String,
+    ) -> PromiseOrValue<bool> {
+        self.assert_not_paused();
+        // The sender may only be approved for the token, the linkdrop has to be funded by its owner
+        let _ = sender_id;
+        let contract_id = env::predecessor_account_id();
+        let public_key: PublicKey = msg.parse().expect("The message must be the public key of the linkdrop");
+        let mut drop = self.accounts.get(&public_key).expect("Key is missing");
+        assert_eq!(drop.funder_id, previous_owner_id, "Key is already funded by another account");
+        assert!(!drop.is_expired(), "Linkdrop has expired");
+        assert_eq!(drop.uses_remaining, 1, "NFT linkdrops can only be claimed once");
+        assert!(drop.ft.is_none(), "Key already holds fungible tokens");
+        assert!(drop.nft.is_none(), "Key already holds an NFT");
+        assert!(
+            token_id.len() <= MAX_NFT_TOKEN_ID_BYTES,
+            "Token id is longer than {} bytes",
+            MAX_NFT_TOKEN_ID_BYTES
+        );
+
+        drop.nft = Some(NftDropData { contract_id, token_id });
+        self.accounts.insert(&public_key, &drop);
+
+        // The token is kept
+        PromiseOrValue::Value(false)
+    }
+
+    /// Callback after sending the NFT of the drop claimed with `public_key` to `account_id`. If the transfer
+    /// failed the token stays claimable with the key, the NEAR balance having already been claimed.
+    pub fn on_nft_claimed(&mut self, account_id: AccountId, public_key: PublicKey, drop: DropInfo) -> bool {
+        assert_eq!(
+            env::predecessor_account_id(),
+            env::current_account_id(),
+            "Callback can only be called from the contract"
+        );
+        let transferred = is_promise_success();
+        if transferred {
+            // Tokens only go with the last use
+            self.internal_finish_claim(account_id, public_key, &drop, true);
+        } else {
+            self.internal_restore_drop(
+                public_key,
+                DropInfo {
+                    balance: U128(0),
+                    ..drop
+                },
+            );
+        }
+        transferred
+    }
+}
+
+impl LinkDrop {
+    /// Send the NFT of the drop claimed with `public_key` to `account_id`.
+    pub(crate) fn internal_claim_nft(&self, account_id: AccountId, public_key: PublicKey, drop: DropInfo) -> Promise {
+        let nft = drop.nft.clone().unwrap();
+        ext_nft::ext(nft.contract_id)
+            .with_attached_deposit(1)
+            .with_static_gas(NFT_TRANSFER_GAS)
+            .nft_transfer(account_id.clone(), nft.token_id, None, None)
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(ON_NFT_CLAIMED_CALLBACK_GAS + claim_notification_gas(&drop))
+                    .on_nft_claimed(account_id, public_key, drop)
+            )
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[cfg(test)]
+mod tests {
+    use near_sdk::mock::VmAction;
+    use near_sdk::serde_json::{self, json};
+    use near_sdk::test_utils::{get_created_receipts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use crate::tests::{bob, callback_env, drop_info, funding_key, linkdrop, new_account, owner, register_storage};
+
+    use super::*;
+
+    fn nft_contract() -> AccountId {
+        "nft".parse().unwrap()
+    }
+
+    fn nft_drop_info(balance: Balance) -> DropInfo {
+        DropInfo {
+            nft: Some(NftDropData { contract_id: nft_contract(), token_id: "1".to_string() }),
+            ..drop_info(balance)
+        }
+    }
+
+    /// Call `nft_on_transfer` as the NFT contract, for token "1" previously owned by `previous_owner_id`.
+    fn transfer_nft(contract: &mut LinkDrop, previous_owner_id: AccountId, msg: String) -> PromiseOrValue<bool> {
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(nft_contract())
+            .context.clone()
+        );
+        contract.nft_on_transfer(previous_owner_id.clone(), previous_owner_id, "1".to_string(), msg)
+    }
+
+    #[test]
+    fn test_nft_on_transfer() {
+        let mut contract = LinkDrop::new(owner(), None);
+        contract.accounts.insert(&funding_key(), &drop_info(1_000));
+
+        // The token is kept and recorded under the key, next to the NEAR balance
+        let returned = transfer_nft(&mut contract, bob(), String::from(&funding_key()));
+        assert!(matches!(returned, PromiseOrValue::Value(false)));
+        assert_eq!(contract.accounts.get(&funding_key()).unwrap(), nft_drop_info(1_000));
+    }
+
+    #[test]
+    #[should_panic(expected = "Key is already funded by another account")]
+    fn test_nft_on_transfer_other_funder() {
+        let mut contract = LinkDrop::new(owner(), None);
+        contract.accounts.insert(&funding_key(), &drop_info(1_000));
+
+        transfer_nft(&mut contract, owner(), String::from(&funding_key()));
+    }
+
+    #[test]
+    #[should_panic(expected = "Key already holds an NFT")]
+    fn test_nft_on_transfer_twice() {
+        let mut contract = LinkDrop::new(owner(), None);
+        contract.accounts.insert(&funding_key(), &nft_drop_info(1_000));
+
+        transfer_nft(&mut contract, bob(), String::from(&funding_key()));
+    }
+
+    #[test]
+    fn test_nft_claim() {
+        let mut contract = LinkDrop::new(owner(), None);
+        register_storage(&mut contract);
+        contract.accounts.insert(&funding_key(), &nft_drop_info(1_000));
+        contract.total_locked = 1_000;
+
+        // The NEAR balance is sent first
+        callback_env(PromiseResult::Successful(vec![]));
+        contract.claim(new_account(), None).unwrap();
+        assert_eq!(get_created_receipts()[0].actions, vec![VmAction::Transfer { deposit: 1_000 }]);
+
+        // Then the token
+        callback_env(PromiseResult::Successful(vec![]));
+        assert!(contract.on_account_created_and_claimed(new_account(), funding_key(), nft_drop_info(1_000), false, None, true, false));
+        let receipts = get_created_receipts();
+        assert_eq!(receipts[0].receiver_id, nft_contract());
+        match &receipts[0].actions[0] {
+            VmAction::FunctionCall { function_name, args, deposit, .. } => {
+                assert_eq!(function_name, "nft_transfer");
+                assert_eq!(*deposit, 1);
+                let args: serde_json::Value = serde_json::from_slice(args).unwrap();
+                assert_eq!(args, json!({
+                    "receiver_id": "bob.linkdrop",
+                    "token_id": "1",
+                    "approval_id": null,
+                    "memo": null,
+                }));
+            }
+            action => panic!("Unexpected action {:?}", action),
+        }
+
+        // The record is cleared and the key deleted once the token is sent
+        callback_env(PromiseResult::Successful(vec![]));
+        assert!(contract.on_nft_claimed(new_account(), funding_key(), nft_drop_info(1_000)));
+        assert_eq!(
+            get_created_receipts()[0].actions,
+            vec![VmAction::DeleteKey { public_key: funding_key() }]
+        );
+        assert!(contract.accounts.get(&funding_key()).is_none());
+    }
+
+    #[test]
+    fn test_nft_claim_failure_restores_token() {
+        let mut contract = LinkDrop::new(owner(), None);
+
+        // The token stays claimable with the key, without the balance already claimed
+        callback_env(PromiseResult::Failed);
+        assert!(!contract.on_nft_claimed(new_account(), funding_key(), nft_drop_info(1_000)));
+        assert_eq!(contract.accounts.get(&funding_key()).unwrap(), DropInfo { amount_per_use: U128(0), ..nft_drop_info(0) });
+        assert!(get_created_receipts().is_empty());
+    }
+}
@@ -0,0 +1,1610 @@
+use crate::*;
+
+/// yoctoNEAR kept in the contract balance on top of what `withdraw_contract_balance` protects: 1 N.
+pub const WITHDRAW_SAFETY_MARGIN: Balance = 1_000_000_000_000_000_000_000_000;
+
+#[near_bindgen]
+impl LinkDrop {
+    /// Propose a new owner for the contract. Ownership only changes once they call `accept_owner`.
+    #[handle_result]
+    pub fn propose_owner(&mut self, new_owner_id: AccountId) -> Result<(), ContractError> {
+        self.check_owner()?;
+        self.proposed_owner_id = Some(new_owner_id);
+        Ok(())
+    }
+
+    /// Take over ownership of the contract. Only callable by the proposed owner.
+    #[handle_result]
+    pub fn accept_owner(&mut self) -> Result<(), ContractError> {
+        let predecessor_account_id = env::predecessor_account_id();
+        ensure(self.proposed_owner_id.as_ref() == Some(&predecessor_account_id), || {
+            ContractError::Unauthorized("Only the proposed owner can accept ownership")
+        })?;
+        self.owner_id = predecessor_account_id;
+        self.proposed_owner_id = None;
+        Ok(())
+    }
+
+    /// Returns the owner of the contract.
+    pub fn get_owner(&self) -> AccountId {
+        self.owner_id.clone()
+    }
+
+    /// Returns the account proposed as the next owner, if any.
+    pub fn get_proposed_owner(&self) -> Option<AccountId> {
+        self.proposed_owner_id.clone()
+    }
+
+    /// Halt account creation and linkdrop funding. Callbacks of in-flight operations still settle.
+    #[handle_result]
+    pub fn pause(&mut self) -> Result<(), ContractError> {
+        self.check_owner()?;
+        self.paused = true;
+        Ok(())
+    }
+
+    /// Resume account creation and linkdrop funding, also after the circuit breaker tripped.
+    #[handle_result]
+    pub fn unpause(&mut self) -> Result<(), ContractError> {
+        self.check_owner()?;
+        self.paused = false;
+        self.internal_reset_failures();
+        Ok(())
+    }
+
+    /// Returns whether the contract is paused.
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Remove the linkdrop funded under `public_key` during an incident, sending its balance to `recipient_id`
+    /// whether or not it expired, its tokens going back to the funder. Unlike `delete_key`, the owner can call it
+    /// alone even while privileged operations need the confirmation of the council, but then `recipient_id` must
+    /// be the funder. Emits an `emergency_reclaim` event.
+    #[handle_result]
+    pub fn force_delete_key(&mut self, public_key: PublicKey, recipient_id: AccountId) -> Result<Promise, ContractError> {
+        self.check_owner()?;
+        let drop = self.accounts.get(&public_key).ok_or(ContractError::KeyMissing)?;
+        ensure(self.required_confirmations == 0 || recipient_id == drop.funder_id, || {
+            ContractError::Unauthorized(
+                "While the council confirms privileged operations, only the funder can get the balance back",
+            )
+        })?;
+        let promise = self.internal_delete_key(public_key.clone(), recipient_id.clone())?;
+        EventLog::new(EventLogVariant::EmergencyReclaim(vec![EmergencyReclaimLog {
+            public_key,
+            funder_id: drop.funder_id,
+            recipient_id,
+            amount: drop.balance,
+        }]))
+        .emit();
+        Ok(promise)
+    }
+
+    /// Set the fee taken out of the deposit of every `create_account_advanced` call.
+    #[handle_result]
+    pub fn set_creation_fee(&mut self, creation_fee: U128) -> Result<(), ContractError> {
+        self.check_owner_action()?;
+        self.creation_fee = creation_fee.0;
+        Ok(())
+    }
+
+    /// Set the basis points of the deposit of every `create_account_advanced` call, after the flat creation fee,
+    /// also taken out as a fee for the treasury, at most `MAX_FEE_BPS`. Fees of a failed creation aren't refunded.
+    #[handle_result]
+    pub fn set_fee_bps(&mut self, fee_bps: u16) -> Result<(), ContractError> {
+        self.check_owner_action()?;
+        self.internal_set_fee_bps(fee_bps)
+    }
+
+    /// Returns the basis points of the deposit taken out as a fee after the flat creation fee.
+    pub fn get_fee_bps(&self) -> u16 {
+        self.fee_bps
+    }
+
+    /// Charge the creations called by `account_id` with `fee_config` rather than the global fees, e.g. a zero
+    /// fee for a partner relayer. `None` removes the override.
+    #[handle_result]
+    pub fn set_fee_override(&mut self, account_id: AccountId, fee_config: Option<FeeConfig>) -> Result<(), ContractError> {
+        self.check_owner_action()?;
+        self.internal_set_fee_override(account_id, fee_config)
+    }
+
+    /// Returns the fees charged on the creations of `account_id` instead of the global ones, if any.
+    pub fn get_fee_override(&self, account_id: AccountId) -> Option<FeeConfig> {
+        self.fee_overrides.get(&account_id)
+    }
+
+    /// Set the account receiving the creation fees.
+    #[handle_result]
+    pub fn set_treasury(&mut self, treasury_id: AccountId) -> Result<(), ContractError> {
+        self.check_owner_action()?;
+        self.treasury_id = treasury_id;
+        Ok(())
+    }
+
+    /// Set the minimum balance of the accounts created by `create_account_advanced`, after the creation fee and
+    /// on top of the storage of their contract. 0 disables the minimum.
+    #[handle_result]
+    pub fn set_min_new_account_balance(&mut self, min_new_account_balance: U128) -> Result<(), ContractError> {
+        self.check_owner_action()?;
+        self.min_new_account_balance = min_new_account_balance.0;
+        Ok(())
+    }
+
+    /// Returns the minimum balance of the accounts created by `create_account_advanced`, 0 when disabled.
+    pub fn get_min_new_account_balance(&self) -> U128 {
+        U128(self.min_new_account_balance)
+    }
+
+    /// Set the deposit a call creating accounts without a linkdrop can attach at most, limiting what a
+    /// compromised authorized caller can move at once.
+    #[handle_result]
+    pub fn set_max_deposit_per_call(&mut self, max_deposit_per_call: U128) -> Result<(), ContractError> {
+        self.check_owner_action()?;
+        self.max_deposit_per_call = max_deposit_per_call.0;
+        Ok(())
+    }
+
+    /// Returns the deposit a call creating accounts without a linkdrop can attach at most.
+    pub fn get_max_deposit_per_call(&self) -> U128 {
+        U128(self.max_deposit_per_call)
+    }
+
+    /// Set the balance the linkdrops can lock in total. Linkdrops funded above it stay locked, but no more can
+    /// be funded until enough are claimed.
+    #[handle_result]
+    pub fn set_max_total_locked(&mut self, max_total_locked: U128) -> Result<(), ContractError> {
+        self.check_owner_action()?;
+        self.max_total_locked = max_total_locked.0;
+        Ok(())
+    }
+
+    /// Returns the balance the linkdrops can lock in total.
+    pub fn get_max_total_locked(&self) -> U128 {
+        U128(self.max_total_locked)
+    }
+
+    /// Set how many creations can fail in a row before the contract pauses itself, e.g. when misconfigured, until
+    /// the owner calls `unpause`. The failures of a caller only count once in a row. 0 disables the circuit breaker.
+    #[handle_result]
+    pub fn set_max_consecutive_failures(&mut self, max_consecutive_failures: u32) -> Result<(), ContractError> {
+        self.check_owner_action()?;
+        self.max_consecutive_failures = max_consecutive_failures;
+        Ok(())
+    }
+
+    /// Returns how many creations can fail in a row before the contract pauses itself, 0 when disabled.
+    pub fn get_max_consecutive_failures(&self) -> u32 {
+        self.max_consecutive_failures
+    }
+
+    /// Returns how many creations failed in a row since the last successful one.
+    pub fn get_consecutive_failures(&self) -> u32 {
+        self.consecutive_failures
+    }
+
+    /// Set how many nanoseconds a caller must wait after one of its creations succeeded before creating another
+    /// account, to keep a single relayer from farming creations. Failed creations don't count. 0 disables it.
+    #[handle_result]
+    pub fn set_per_account_cooldown(&mut self, per_account_cooldown: U64) -> Result<(), ContractError> {
+        self.check_owner_action()?;
+        self.per_account_cooldown = per_account_cooldown.0;
+        Ok(())
+    }
+
+    /// Returns how many nanoseconds a caller must wait between its creations, 0 when disabled.
+    pub fn get_per_account_cooldown(&self) -> U64 {
+        U64(self.per_account_cooldown)
+    }
+
+    /// Set how many bytes a contract deployed to a new account can take at most, `DEFAULT_MAX_CONTRACT_SIZE` by
+    /// default. Larger contracts are refused before any promise is scheduled.
+    #[handle_result]
+    pub fn set_max_contract_size(&mut self, max_contract_size: U64) -> Result<(), ContractError> {
+        self.check_owner_action()?;
+        self.max_contract_size = max_contract_size.0;
+        Ok(())
+    }
+
+    /// Returns how many bytes a contract deployed to a new account can take at most.
+    pub fn get_max_contract_size(&self) -> U64 {
+        U64(self.max_contract_size)
+    }
+
+    /// Set where the deposits of failed creations go, `RefundToPredecessor` by default.
+    #[handle_result]
+    pub fn set_refund_policy(&mut self, refund_policy: RefundPolicy) -> Result<(), ContractError> {
+        self.check_owner_action()?;
+        self.refund_policy = refund_policy;
+        Ok(())
+    }
+
+    /// Returns where the deposits of failed creations go.
+    pub fn get_refund_policy(&self) -> RefundPolicy {
+        self.refund_policy.clone()
+    }
+
+    /// Returns the deposits of failed creations retained for the treasury and not withdrawn yet.
+    pub fn get_retained_refunds(&self) -> U128 {
+        U128(self.retained_refunds)
+    }
+
+    /// Send the deposits of failed creations retained under `RefundPolicy::Retain` to the treasury, e.g. to
+    /// refund them by hand. Only callable by the owner.
+    #[handle_result]
+    pub fn withdraw_retained_refunds(&mut self) -> Result<Promise, ContractError> {
+        self.check_owner()?;
+        ensure(self.retained_refunds > 0, || ContractError::InvalidArgument("No refunds are retained".to_string()))?;
+        let amount = self.retained_refunds;
+        self.retained_refunds = 0;
+        Ok(Promise::new(self.treasury_id.clone()).transfer(amount))
+    }
+
+    /// Send `amount` of the leftover contract balance, e.g. rounding or refund residue, to `to`. Never touches
+    /// the linkdrop balances, the storage balances of the funders, the retained and pending refunds, the storage
+    /// reserve, the storage staking of the contract nor the `WITHDRAW_SAFETY_MARGIN`: see `get_available_balance`.
+    #[handle_result]
+    pub fn withdraw_contract_balance(&mut self, amount: U128, to: AccountId) -> Result<Promise, ContractError> {
+        self.check_owner_action()?;
+        self.internal_withdraw_contract_balance(amount, to)
+    }
+
+    /// Returns the part of the contract balance `withdraw_contract_balance` can send.
+    pub fn get_available_balance(&self) -> U128 {
+        let protected = self.total_locked
+            + self.storage_deposits
+            + self.retained_refunds
+            + self.pending_refunds
+            + self.storage_reserve
+            + self.get_storage_cost().0
+            + WITHDRAW_SAFETY_MARGIN;
+        U128(env::account_balance().saturating_sub(protected))
+    }
+
+    /// Returns the fee taken out of the deposit of every `create_account_advanced` call.
+    pub fn get_creation_fee(&self) -> U128 {
+        U128(self.creation_fee)
+    }
+
+    /// Returns the account receiving the creation fees.
+    pub fn get_treasury(&self) -> AccountId {
+        self.treasury_id.clone()
+    }
+
+    /// Allow `account_id` to create accounts. Once any caller is authorized, all others are rejected.
+    #[handle_result]
+    pub fn add_authorized_caller(&mut self, account_id: AccountId) -> Result<(), ContractError> {
+        self.check_owner_action()?;
+        self.authorized_callers.insert(&account_id);
+        Ok(())
+    }
+
+    /// Stop `account_id` from creating accounts. Removing the last caller lets anyone create accounts again.
+    #[handle_result]
+    pub fn remove_authorized_caller(&mut self, account_id: AccountId) -> Result<(), ContractError> {
+        self.check_owner_action()?;
+        self.authorized_callers.remove(&account_id);
+        Ok(())
+    }
+
+    /// Returns the accounts allowed to create accounts, anyone can when empty.
+    pub fn get_authorized_callers(&self) -> Vec<AccountId> {
+        self.authorized_callers.to_vec()
+    }
+
+    /// Reserve `label`: no account whose leftmost label is exactly `label` can be created through this
+    /// contract, with or without a linkdrop. Reserving `admin` refuses `admin.<this contract>` but not
+    /// `admins.<this contract>`. Account ids are lowercase, so the label is lowercased.
+    #[handle_result]
+    pub fn add_reserved_label(&mut self, label: String) -> Result<(), ContractError> {
+        self.check_owner_action()?;
+        self.reserved_labels.insert(&label.to_lowercase());
+        Ok(())
+    }
+
+    /// Release a label reserved with `add_reserved_label`.
+    #[handle_result]
+    pub fn remove_reserved_label(&mut self, label: String) -> Result<(), ContractError> {
+        self.check_owner_action()?;
+        self.reserved_labels.remove(&label.to_lowercase());
+        Ok(())
+    }
+
+    /// Returns the reserved labels, in lowercase.
+    pub fn get_reserved_labels(&self) -> Vec<String> {
+        self.reserved_labels.to_vec()
+    }
+
+    /// Set the full access keys added to every account created without a linkdrop on top of the caller's, e.g.
+    /// a recovery key for custodial flows. Callers pay for their storage. An empty list stops adding any.
+    #[handle_result]
+    pub fn set_default_full_access_keys(&mut self, public_keys: Vec<PublicKey>) -> Result<(), ContractError> {
+        self.check_owner_action()?;
+        self.internal_set_default_full_access_keys(public_keys)
+    }
+
+    /// Returns the full access keys added to every account created without a linkdrop, none when empty.
+    pub fn get_default_full_access_keys(&self) -> Vec<PublicKey> {
+        self.default_full_access_keys.clone()
+    }
+
+    /// Let `send` and `top_up` fund `public_key` again after its last use was claimed, e.g. when its holder
+    /// reuses it on purpose.
+    #[handle_result]
+    pub fn clear_tombstone(&mut self, public_key: PublicKey) -> Result<(), ContractError> {
+        self.check_owner_action()?;
+        self.claimed_keys.remove(&public_key);
+        Ok(())
+    }
+
+    /// Returns the number of claimed keys that can't be funded again until their tombstone is cleared.
+    pub fn get_tombstone_count(&self) -> u64 {
+        self.claimed_keys.len()
+    }
+}
+
+impl LinkDrop {
+    /// Panics unless the predecessor is the owner, see `check_owner`.
+    pub(crate) fn assert_owner(&self) {
+        self.check_owner().unwrap_or_else(|error| panic!("{}", error));
+    }
+
+    /// Fails unless the predecessor is the owner. Guards every privileged method.
+    pub(crate) fn check_owner(&self) -> Result<(), ContractError> {
+        ensure(env::predecessor_account_id() == self.owner_id, || {
+            ContractError::Unauthorized("Only the owner can call this method")
+        })
+    }
+
+    /// Fails unless the predecessor is among the authorized callers, if there are any. Guards every method
+    /// creating accounts without a linkdrop.
+    pub(crate) fn check_authorized(&self) -> Result<(), ContractError> {
+        ensure(
+            self.authorized_callers.is_empty() || self.authorized_callers.contains(&env::predecessor_account_id()),
+            || ContractError::Unauthorized("The predecessor is not authorized to create accounts"),
+        )
+    }
+
+    /// Set `default_full_access_keys`, see `set_default_full_access_keys`.
+    pub(crate) fn internal_set_default_full_access_keys(&mut self, public_keys: Vec<PublicKey>) -> Result<(), ContractError> {
+        let options = CreateAccountOptions {
+            full_access_keys: Some(public_keys),
+            full_access_key_nonce: None,
+            limited_access_keys: None,
+            templated_access_keys: None,
+            contract_bytes: None,
+            deploy_init: None,
+            stake: None,
+        };
+        options.check_unique_keys()?;
+        options.full_access_keys.iter().flatten().try_for_each(check_public_key)?;
+        self.default_full_access_keys = options.full_access_keys.unwrap_or_default();
+        Ok(())
+    }
+
+    /// Set `fee_bps`, see `set_fee_bps`.
+    pub(crate) fn internal_set_fee_bps(&mut self, fee_bps: u16) -> Result<(), ContractError> {
+        check_fee_bps(fee_bps)?;
+        self.fee_bps = fee_bps;
+        Ok(())
+    }
+
+    /// Set or remove the fee override of `account_id`, see `set_fee_override`.
+    pub(crate) fn internal_set_fee_override(
+        &mut self,
+        account_id: AccountId,
+        fee_config: Option<FeeConfig>,
+    ) -> Result<(), ContractError> {
+        match fee_config {
+            Some(fee_config) => {
+                check_fee_bps(fee_config.fee_bps)?;
+                self.fee_overrides.insert(&account_id, &fee_config);
+            }
+            None => {
+                self.fee_overrides.remove(&account_id);
+            }
+        }
+        Ok(())
+    }
+
+    /// Send `amount` of the available balance to `receiver_id`, see `withdraw_contract_balance`.
+    pub(crate) fn internal_withdraw_contract_balance(
+        &self,
+        amount: U128,
+        receiver_id: AccountId,
+    ) -> Result<Promise, ContractError> {
+        let available = self.get_available_balance().0;
+        ensure(amount.0 <= available, || {
+            ContractError::InvalidArgument(format!(
+                "Cannot withdraw {} yoctoNEAR, only {} yoctoNEAR are available",
+                amount.0, available
+            ))
+        })?;
+        Ok(Promise::new(receiver_id).transfer(amount.0))
+    }
+
+    /// Fails if the leftmost label of `new_account_id` is reserved, see `add_reserved_label`.
+    pub(crate) fn check_not_reserved(&self, new_account_id: &AccountId) -> Result<(), ContractError> {
+        let label = new_account_id.as_str().split('.').next().unwrap_or_default();
+        ensure(!self.reserved_labels.contains(&label.to_string()), || {
+            ContractError::InvalidArgument(format!("Cannot create {}: the name {} is reserved", new_account_id, label))
+        })
+    }
+
+    /// Fails if `creator_id` created an account less than `per_account_cooldown` ago. Creations still in flight
+    /// only count once they succeed.
+    pub(crate) fn check_cooldown_elapsed(&self, creator_id: &AccountId) -> Result<(), ContractError> {
+        if self.per_account_cooldown == 0 {
+            return Ok(());
+        }
+        match self.last_creation_at.get(creator_id) {
+            Some(last_creation_at) => {
+                let available_at = last_creation_at.saturating_add(self.per_account_cooldown);
+                ensure(env::block_timestamp() >= available_at, || {
+                    ContractError::InvalidArgument(format!(
+                        "{} must wait until {} to create another account",
+                        creator_id, available_at
+                    ))
+                })
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Fails if the attached deposit is above `max_deposit_per_call`. Guards every method creating accounts
+    /// without a linkdrop.
+    pub(crate) fn check_deposit_within_limit(&self) -> Result<(), ContractError> {
+        ensure(env::attached_deposit() <= self.max_deposit_per_call, || {
+            ContractError::InvalidArgument(format!(
+                "Cannot attach more than {} yoctoNEAR to a call",
+                self.max_deposit_per_call
+            ))
+        })
+    }
+
+    /// Panics unless the owner can act alone, see `check_owner_action`.
+    pub(crate) fn assert_owner_action(&self) {
+        self.check_owner_action().unwrap_or_else(|error| panic!("{}", error));
+    }
+
+    /// Fails unless the predecessor is the owner and privileged operations don't need council confirmations.
+    /// Guards every method also available as a `RequestAction`.
+    pub(crate) fn check_owner_action(&self) -> Result<(), ContractError> {
+        self.check_owner()?;
+        ensure(self.required_confirmations == 0, || {
+            ContractError::Unauthorized(
+                "This operation needs the confirmation of the council, propose it with add_request",
+            )
+        })
+    }
+
+    /// Count a failed creation of `creator_id`, pausing the contract once more than `max_consecutive_failures`
+    /// failed in a row. While the circuit breaker is enabled, each caller only counts once in a row: retrying a
+    /// creation bound to fail, e.g. of an account that already exists, can't pause the contract for everyone.
+    pub(crate) fn internal_count_failure(&mut self, creator_id: &AccountId) {
+        if self.max_consecutive_failures == 0 {
+            self.consecutive_failures += 1;
+            return;
+        }
+        // The set stays bounded by the threshold as nothing more is tracked once paused
+        if self.paused || !self.failing_creators.insert(creator_id) {
+            return;
+        }
+        self.consecutive_failures += 1;
+        if self.consecutive_failures > self.max_consecutive_failures {
+            self.paused = true;
+            EventLog::new(EventLogVariant::CircuitBreakerTripped(vec![CircuitBreakerTrippedLog {
+                consecutive_failures: self.consecutive_failures,
+            }]))
+            .emit();
+        }
+    }
+
+    /// Start a new run of failed creations, after a successful one or once the owner resumes the contract.
+    pub(crate) fn internal_reset_failures(&mut self) {
+        self.consecutive_failures = 0;
+        self.failing_creators.clear();
+    }
+
+    /// Panics while the contract is paused, see `check_not_paused`.
+    pub(crate) fn assert_not_paused(&self) {
+        self.check_not_paused().unwrap_or_else(|error| panic!("{}", error));
+    }
+
+    /// Fails while the contract is paused. Guards every method starting a new creation or funding a key.
+    pub(crate) fn check_not_paused(&self) -> Result<(), ContractError> {
+        ensure(!self.paused, || ContractError::Paused)
+    }
+}
+
+/// Fails if `fee_bps` is above `MAX_FEE_BPS`.
+fn check_fee_bps(fee_bps: u16) -> Result<(), ContractError> {
+    ensure(fee_bps <= MAX_FEE_BPS, || {
+        ContractError::InvalidArgument(format!("The fee can be at most {} basis points", MAX_FEE_BPS))
+    })
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[cfg(test)]
+mod tests {
+    use near_sdk::mock::VmAction;
+    use near_sdk::test_utils::{get_created_receipts, get_logs, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use crate::tests::{bob, callback_env, drop_info, funding_key, key, linkdrop, new_account, owner, refunded_outcome, register_storage};
+
+    use super::*;
+
+    fn set_predecessor(predecessor_account_id: AccountId) {
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(predecessor_account_id)
+            .context.clone()
+        );
+    }
+
+    #[test]
+    fn test_transfer_ownership() {
+        set_predecessor(owner());
+        let mut contract = LinkDrop::new(owner(), None);
+        assert_eq!(contract.get_owner(), owner());
+
+        // The proposal alone doesn't change the owner
+        contract.propose_owner(bob()).unwrap();
+        assert_eq!(contract.get_owner(), owner());
+        assert_eq!(contract.get_proposed_owner(), Some(bob()));
+
+        set_predecessor(bob());
+        contract.accept_owner().unwrap();
+        assert_eq!(contract.get_owner(), bob());
+        assert_eq!(contract.get_proposed_owner(), None);
+    }
+
+    #[test]
+    fn test_accept_owner_wrong_account() {
+        set_predecessor(owner());
+        let mut contract = LinkDrop::new(owner(), None);
+        contract.propose_owner(bob()).unwrap();
+
+        set_predecessor(linkdrop());
+        assert_eq!(
+            contract.accept_owner().err(),
+            Some(ContractError::Unauthorized("Only the proposed owner can accept ownership"))
+        );
+    }
+
+    #[test]
+    fn test_accept_owner_without_proposal() {
+        set_predecessor(owner());
+        let mut contract = LinkDrop::new(owner(), None);
+
+        assert_eq!(
+            contract.accept_owner().err(),
+            Some(ContractError::Unauthorized("Only the proposed owner can accept ownership"))
+        );
+    }
+
+    #[test]
+    fn test_propose_owner_not_owner() {
+        set_predecessor(bob());
+        let mut contract = LinkDrop::new(owner(), None);
+
+        assert_eq!(
+            contract.propose_owner(bob()).err(),
+            Some(ContractError::Unauthorized("Only the owner can call this method"))
+        );
+    }
+
+    /// A contract paused by its owner.
+    fn paused_contract() -> LinkDrop {
+        set_predecessor(owner());
+        let mut contract = LinkDrop::new(owner(), None);
+        contract.pause().unwrap();
+        assert!(contract.is_paused());
+        contract
+    }
+
+    #[test]
+    fn test_paused_create_account_advanced() {
+        let mut contract = paused_contract();
+
+        let result = contract.create_account_advanced(
+            new_account(),
+            CreateAccountOptions {
+                full_access_keys: Some(vec![funding_key()]),
+                full_access_key_nonce: None,
+                limited_access_keys: None,
+                templated_access_keys: None,
+                contract_bytes: None,
+                deploy_init: None,
+                stake: None,
+            },
+            None,
+            None,
+            None,
+            None,
+        );
+        assert_eq!(result.err(), Some(ContractError::Paused));
+    }
+
+    #[test]
+    fn test_paused_create_account_and_claim() {
+        let mut contract = paused_contract();
+        contract.accounts.insert(&funding_key(), &drop_info(1_000));
+
+        set_predecessor(linkdrop());
+        let result = contract.create_account_and_claim(new_account(), funding_key(), None, None);
+        assert_eq!(result.err(), Some(ContractError::Paused));
+    }
+
+    #[test]
+    fn test_paused_send() {
+        let mut contract = paused_contract();
+
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .attached_deposit(MIN_SEND_DEPOSIT)
+            .context.clone()
+        );
+        let result = contract.send(funding_key(), None, None, None, None, None, None);
+        assert_eq!(result.err(), Some(ContractError::Paused));
+    }
+
+    #[test]
+    fn test_paused_callbacks_settle() {
+        let mut contract = paused_contract();
+
+        // Refunds of creations started before the pause still go out
+        callback_env(PromiseResult::Failed);
+        contract.on_account_created(new_account(), bob(), bob(), U128(1_000), None, None, None);
+        assert_eq!(refunded_outcome().refunded, U128(1_000));
+        assert_eq!(get_created_receipts()[0].actions, vec![VmAction::Transfer { deposit: 1_000 }]);
+
+        // And failed claims are restored
+        assert!(!contract.on_account_created_and_claimed(new_account(), funding_key(), drop_info(1_000), true, None, true, false));
+        assert_eq!(contract.get_key_balance_or_panic(funding_key()), U128(1_000));
+    }
+
+    #[test]
+    fn test_unpause() {
+        let mut contract = paused_contract();
+        register_storage(&mut contract);
+        contract.unpause().unwrap();
+        assert!(!contract.is_paused());
+
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob())
+            .attached_deposit(MIN_SEND_DEPOSIT)
+            .context.clone()
+        );
+        contract.send(funding_key(), None, None, None, None, None, None).unwrap();
+    }
+
+    #[test]
+    fn test_pause_not_owner() {
+        set_predecessor(bob());
+        let mut contract = LinkDrop::new(owner(), None);
+
+        assert_eq!(
+            contract.pause().err(),
+            Some(ContractError::Unauthorized("Only the owner can call this method"))
+        );
+    }
+
+    #[test]
+    fn test_force_delete_key() {
+        set_predecessor(owner());
+        let mut contract = LinkDrop::new(owner(), None);
+        let drop = DropInfo { expires_at: Some(U64(1_000)), ..drop_info(1_000) };
+        contract.accounts.insert(&funding_key(), &drop);
+        contract.total_locked = 1_000;
+
+        contract.force_delete_key(funding_key(), owner()).unwrap();
+        assert!(contract.accounts.get(&funding_key()).is_none());
+        assert_eq!(contract.get_total_locked_balance(), U128(0));
+        let receipts = get_created_receipts();
+        assert_eq!(receipts[0].actions, vec![VmAction::DeleteKey { public_key: funding_key() }]);
+        assert_eq!(receipts[1].receiver_id, owner());
+        assert_eq!(receipts[1].actions, vec![VmAction::Transfer { deposit: 1_000 }]);
+        assert_eq!(
+            get_logs(),
+            vec![format!(
+                r#"EVENT_JSON:{{"standard":"arkana_account","version":"1.1.0","event":"emergency_reclaim","data":[{{"public_key":"{}","funder_id":"bob","recipient_id":"owner","amount":"1000"}}]}}"#,
+                String::from(&funding_key())
+            )]
+        );
+    }
+
+    #[test]
+    fn test_force_delete_key_with_council() {
+        set_predecessor(owner());
+        let mut contract = LinkDrop::new(owner(), None);
+        contract.accounts.insert(&funding_key(), &drop_info(1_000));
+        contract.total_locked = 1_000;
+        contract.required_confirmations = 1;
+
+        // The owner alone can't send the balance anywhere but back to the funder
+        assert_eq!(
+            contract.force_delete_key(funding_key(), owner()).err(),
+            Some(ContractError::Unauthorized(
+                "While the council confirms privileged operations, only the funder can get the balance back"
+            ))
+        );
+        assert!(contract.accounts.get(&funding_key()).is_some());
+        contract.force_delete_key(funding_key(), bob()).unwrap();
+        assert!(contract.accounts.get(&funding_key()).is_none());
+        assert_eq!(get_created_receipts()[1].receiver_id, bob());
+    }
+
+    #[test]
+    fn test_force_delete_key_not_owner() {
+        set_predecessor(owner());
+        let mut contract = LinkDrop::new(owner(), None);
+        contract.accounts.insert(&funding_key(), &drop_info(1_000));
+
+        set_predecessor(bob());
+        assert_eq!(
+            contract.force_delete_key(funding_key(), bob()).err(),
+            Some(ContractError::Unauthorized("Only the owner can call this method"))
+        );
+        assert!(contract.accounts.get(&funding_key()).is_some());
+    }
+
+    fn treasury() -> AccountId {
+        "treasury".parse().unwrap()
+    }
+
+    /// A contract charging a 100 yoctoNEAR$ creation fee.
+    fn fee_contract() -> LinkDrop {
+        set_predecessor(owner());
+        let mut contract = LinkDrop::new(owner(), None);
+        assert_eq!(contract.get_treasury(), owner());
+        contract.set_treasury(treasury()).unwrap();
+        contract.set_creation_fee(U128(100)).unwrap();
+        assert_eq!(contract.get_creation_fee(), U128(100));
+        contract
+    }
+
+    fn key_options() -> CreateAccountOptions {
+        CreateAccountOptions {
+            full_access_keys: Some(vec![funding_key()]),
+            full_access_key_nonce: None,
+            limited_access_keys: None,
+            templated_access_keys: None,
+            contract_bytes: None,
+            deploy_init: None,
+            stake: None,
+        }
+    }
+
+    #[test]
+    fn test_creation_fee() {
+        let mut contract = fee_contract();
+
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob())
+            .attached_deposit(1_000)
+            .context.clone()
+        );
+        contract.create_account_advanced(new_account(), key_options(), None, None, None, None).unwrap();
+
+        // The treasury receives the fee and the new account the rest
+        let receipts = get_created_receipts();
+        let treasury_receipt = receipts.iter().find(|receipt| receipt.receiver_id == treasury()).unwrap();
+        assert_eq!(treasury_receipt.actions, vec![VmAction::Transfer { deposit: 100 }]);
+        let creation_receipt = receipts.iter().find(|receipt| receipt.receiver_id == new_account()).unwrap();
+        assert!(creation_receipt.actions.contains(&VmAction::Transfer { deposit: 900 }));
+
+        // Only the transferred amount is refunded if the creation fails
+        callback_env(PromiseResult::Failed);
+        contract.on_account_created(new_account(), bob(), bob(), U128(900), None, None, None);
+        assert_eq!(refunded_outcome().refunded, U128(900));
+    }
+
+    #[test]
+    fn test_no_creation_fee() {
+        set_predecessor(owner());
+        let mut contract = LinkDrop::new(owner(), None);
+
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob())
+            .attached_deposit(1_000)
+            .context.clone()
+        );
+        contract.create_account_advanced(new_account(), key_options(), None, None, None, None).unwrap();
+
+        // Nothing is sent to the treasury
+        let receipts = get_created_receipts();
+        assert_eq!(receipts.len(), 2);
+        assert!(receipts[0].actions.contains(&VmAction::Transfer { deposit: 1_000 }));
+    }
+
+    #[test]
+    #[should_panic(expected = "Attached deposit must cover the creation fee of 100 yoctoNEAR")]
+    fn test_creation_fee_not_covered() {
+        let mut contract = fee_contract();
+
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .attached_deposit(99)
+            .context.clone()
+        );
+        contract.create_account_advanced(new_account(), key_options(), None, None, None, None).unwrap();
+    }
+
+    #[test]
+    fn test_percentage_fee() {
+        let mut contract = fee_contract();
+        contract.set_fee_bps(250).unwrap();
+        assert_eq!(contract.get_fee_bps(), 250);
+
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob())
+            .attached_deposit(1_100)
+            .context.clone()
+        );
+        contract.create_account_advanced(new_account(), key_options(), None, None, None, None).unwrap();
+
+        // The treasury receives the flat fee plus 2.5% of the rest in a single transfer
+        let receipts = get_created_receipts();
+        let treasury_receipt = receipts.iter().find(|receipt| receipt.receiver_id == treasury()).unwrap();
+        assert_eq!(treasury_receipt.actions, vec![VmAction::Transfer { deposit: 125 }]);
+        let creation_receipt = receipts.iter().find(|receipt| receipt.receiver_id == new_account()).unwrap();
+        assert!(creation_receipt.actions.contains(&VmAction::Transfer { deposit: 975 }));
+
+        // Neither fee is refunded if the creation fails
+        callback_env(PromiseResult::Failed);
+        contract.on_account_created(new_account(), bob(), bob(), U128(975), None, None, None);
+        assert_eq!(refunded_outcome().refunded, U128(975));
+    }
+
+    /// Create `new_account()` as `relayer` with 1100 yoctoNEAR, returning what the treasury and the new account get.
+    fn create_as_relayer(contract: &mut LinkDrop, relayer: AccountId) -> (Option<Balance>, Balance) {
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(relayer)
+            .attached_deposit(1_100)
+            .context.clone()
+        );
+        contract.create_account_advanced(new_account(), key_options(), None, None, None, None).unwrap();
+        let receipts = get_created_receipts();
+        let treasury_fees = receipts.iter().find(|receipt| receipt.receiver_id == treasury()).map(|receipt| {
+            match receipt.actions[..] {
+                [VmAction::Transfer { deposit }] => deposit,
+                _ => panic!("Unexpected actions {:?}", receipt.actions),
+            }
+        });
+        let creation_receipt = receipts.iter().find(|receipt| receipt.receiver_id == new_account()).unwrap();
+        let amount = creation_receipt
+            .actions
+            .iter()
+            .find_map(|action| match action {
+                VmAction::Transfer { deposit } => Some(*deposit),
+                _ => None,
+            })
+            .unwrap();
+        (treasury_fees, amount)
+    }
+
+    #[test]
+    fn test_zero_fee_override() {
+        let mut contract = fee_contract();
+        let waiver = FeeConfig { creation_fee: U128(0), fee_bps: 0 };
+        contract.set_fee_override(bob(), Some(waiver.clone())).unwrap();
+        assert_eq!(contract.get_fee_override(bob()), Some(waiver));
+
+        // Nothing goes to the treasury
+        assert_eq!(create_as_relayer(&mut contract, bob()), (None, 1_100));
+    }
+
+    #[test]
+    fn test_reduced_fee_override() {
+        let mut contract = fee_contract();
+        contract.set_fee_bps(250).unwrap();
+        contract.set_fee_override(bob(), Some(FeeConfig { creation_fee: U128(50), fee_bps: 100 })).unwrap();
+
+        // 50 plus 1% of the rest
+        assert_eq!(create_as_relayer(&mut contract, bob()), (Some(60), 1_040));
+    }
+
+    #[test]
+    fn test_no_fee_override() {
+        let mut contract = fee_contract();
+        contract.set_fee_override(new_account(), Some(FeeConfig { creation_fee: U128(0), fee_bps: 0 })).unwrap();
+        assert_eq!(contract.get_fee_override(bob()), None);
+
+        // Callers without an override pay the global fee
+        assert_eq!(create_as_relayer(&mut contract, bob()), (Some(100), 1_000));
+
+        // Nor once the override is removed
+        set_predecessor(owner());
+        contract.set_fee_override(bob(), Some(FeeConfig { creation_fee: U128(0), fee_bps: 0 })).unwrap();
+        contract.set_fee_override(bob(), None).unwrap();
+        assert_eq!(contract.get_fee_override(bob()), None);
+    }
+
+    #[test]
+    fn test_fee_override_above_max() {
+        let mut contract = fee_contract();
+        assert_eq!(
+            contract.set_fee_override(bob(), Some(FeeConfig { creation_fee: U128(0), fee_bps: MAX_FEE_BPS + 1 })).err(),
+            Some(ContractError::InvalidArgument("The fee can be at most 10000 basis points".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_set_fee_override_not_owner() {
+        let mut contract = fee_contract();
+        set_predecessor(bob());
+        assert_eq!(
+            contract.set_fee_override(bob(), None).err(),
+            Some(ContractError::Unauthorized("Only the owner can call this method"))
+        );
+    }
+
+    #[test]
+    fn test_fees_of() {
+        let mut contract = fee_contract();
+        assert_eq!(contract.global_fee_config().fees_of(1_100), 100);
+
+        // The percentage is rounded down
+        contract.set_fee_bps(1).unwrap();
+        assert_eq!(contract.global_fee_config().fees_of(10_099), 100);
+        assert_eq!(contract.global_fee_config().fees_of(10_100), 101);
+        contract.set_fee_bps(3_333).unwrap();
+        assert_eq!(contract.global_fee_config().fees_of(101), 100);
+        assert_eq!(contract.global_fee_config().fees_of(103), 100);
+        assert_eq!(contract.global_fee_config().fees_of(104), 101);
+
+        // At 100% the whole deposit is taken, without overflowing on large deposits
+        contract.set_fee_bps(MAX_FEE_BPS).unwrap();
+        assert_eq!(contract.global_fee_config().fees_of(1_100), 1_100);
+        assert_eq!(contract.global_fee_config().fees_of(Balance::MAX), Balance::MAX);
+    }
+
+    #[test]
+    fn test_fee_bps_above_max() {
+        let mut contract = fee_contract();
+        assert_eq!(
+            contract.set_fee_bps(MAX_FEE_BPS + 1).err(),
+            Some(ContractError::InvalidArgument("The fee can be at most 10000 basis points".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_set_fee_bps_not_owner() {
+        set_predecessor(bob());
+        let mut contract = LinkDrop::new(owner(), None);
+
+        assert_eq!(
+            contract.set_fee_bps(100).err(),
+            Some(ContractError::Unauthorized("Only the owner can call this method"))
+        );
+    }
+
+    /// Create `new_account()` with `options` and `deposit`, the contract charging `fee_contract`'s fee and
+    /// requiring new accounts to get at least 1000 yoctoNEAR.
+    fn create_with_min_balance(mut options: CreateAccountOptions, deposit: Balance) {
+        let mut contract = fee_contract();
+        contract.set_min_new_account_balance(U128(1_000)).unwrap();
+        assert_eq!(contract.get_min_new_account_balance(), U128(1_000));
+
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob())
+            .attached_deposit(deposit)
+            .context.clone()
+        );
+        match options.contract_bytes.take() {
+            Some(bytes) => {
+                contract.create_account_with_contract(new_account(), Base64VecU8(bytes), None, options).unwrap();
+            }
+            None => {
+                contract.create_account_advanced(new_account(), options, None, None, None, None).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn test_min_new_account_balance() {
+        create_with_min_balance(key_options(), 1_100);
+        let receipts = get_created_receipts();
+        let creation_receipt = receipts.iter().find(|receipt| receipt.receiver_id == new_account()).unwrap();
+        assert!(creation_receipt.actions.contains(&VmAction::Transfer { deposit: 1_000 }));
+    }
+
+    #[test]
+    #[should_panic(expected = "The new account would get 999 yoctoNEAR, below the minimum of 1000 yoctoNEAR")]
+    fn test_below_min_new_account_balance() {
+        create_with_min_balance(key_options(), 1_099);
+    }
+
+    #[test]
+    #[should_panic(expected = "below the minimum of")]
+    fn test_min_new_account_balance_with_contract() {
+        // The storage of the contract comes on top of the minimum
+        let options = CreateAccountOptions {
+            full_access_keys: None,
+            full_access_key_nonce: None,
+            limited_access_keys: None,
+            templated_access_keys: None,
+            contract_bytes: Some(vec![0; 10]),
+            deploy_init: None,
+            stake: None,
+        };
+        create_with_min_balance(options, 1_100);
+    }
+
+    #[test]
+    fn test_set_creation_fee_not_owner() {
+        set_predecessor(bob());
+        let mut contract = LinkDrop::new(owner(), None);
+
+        assert_eq!(
+            contract.set_creation_fee(U128(100)).err(),
+            Some(ContractError::Unauthorized("Only the owner can call this method"))
+        );
+    }
+
+    /// Create an account as `predecessor_account_id` with the contract allowing `authorized` callers.
+    fn create_as(predecessor_account_id: AccountId, authorized: &[AccountId]) {
+        set_predecessor(owner());
+        let mut contract = LinkDrop::new(owner(), None);
+        for account_id in authorized {
+            contract.add_authorized_caller(account_id.clone()).unwrap();
+        }
+
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(predecessor_account_id)
+            .attached_deposit(1_000)
+            .context.clone()
+        );
+        contract.create_account_advanced(new_account(), key_options(), None, None, None, None).unwrap();
+    }
+
+    #[test]
+    fn test_authorized_caller() {
+        create_as(bob(), &[bob()]);
+        assert!(get_created_receipts()[0].actions.contains(&VmAction::Transfer { deposit: 1_000 }));
+    }
+
+    #[test]
+    #[should_panic(expected = "The predecessor is not authorized to create accounts")]
+    fn test_unauthorized_caller() {
+        create_as(bob(), &[owner()]);
+    }
+
+    #[test]
+    fn test_no_authorized_callers() {
+        // Anyone can create accounts while nobody is authorized
+        create_as(bob(), &[]);
+        assert!(get_created_receipts()[0].actions.contains(&VmAction::Transfer { deposit: 1_000 }));
+    }
+
+    #[test]
+    fn test_remove_authorized_caller() {
+        set_predecessor(owner());
+        let mut contract = LinkDrop::new(owner(), None);
+
+        contract.add_authorized_caller(bob()).unwrap();
+        assert_eq!(contract.get_authorized_callers(), vec![bob()]);
+        contract.remove_authorized_caller(bob()).unwrap();
+        assert!(contract.get_authorized_callers().is_empty());
+    }
+
+    #[test]
+    fn test_add_authorized_caller_not_owner() {
+        set_predecessor(bob());
+        let mut contract = LinkDrop::new(owner(), None);
+
+        assert_eq!(
+            contract.add_authorized_caller(bob()).err(),
+            Some(ContractError::Unauthorized("Only the owner can call this method"))
+        );
+    }
+
+    /// Create `new_account_id` with the contract reserving the `admin` label, added as `Admin`.
+    fn create_with_reserved_admin(new_account_id: &str) {
+        set_predecessor(owner());
+        let mut contract = LinkDrop::new(owner(), None);
+        contract.add_reserved_label("Admin".to_string()).unwrap();
+        assert_eq!(contract.get_reserved_labels(), vec!["admin".to_string()]);
+
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob())
+            .attached_deposit(1_000)
+            .context.clone()
+        );
+        contract.create_account_advanced(new_account_id.parse().unwrap(), key_options(), None, None, None, None).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot create admin.linkdrop: the name admin is reserved")]
+    fn test_reserved_label() {
+        create_with_reserved_admin("admin.linkdrop");
+    }
+
+    #[test]
+    fn test_label_not_reserved() {
+        // Only the exact leftmost label is reserved
+        create_with_reserved_admin("admins.linkdrop");
+        assert!(get_created_receipts()[0].actions.contains(&VmAction::Transfer { deposit: 1_000 }));
+    }
+
+    #[test]
+    fn test_remove_reserved_label() {
+        set_predecessor(owner());
+        let mut contract = LinkDrop::new(owner(), None);
+
+        contract.add_reserved_label("admin".to_string()).unwrap();
+        contract.remove_reserved_label("ADMIN".to_string()).unwrap();
+        assert!(contract.get_reserved_labels().is_empty());
+    }
+
+    #[test]
+    fn test_clear_tombstone_not_owner() {
+        set_predecessor(bob());
+        let mut contract = LinkDrop::new(owner(), None);
+
+        assert_eq!(
+            contract.clear_tombstone(key(1)).err(),
+            Some(ContractError::Unauthorized("Only the owner can call this method"))
+        );
+    }
+
+    /// Create `new_account()` with `key_options` and the given default full access keys, returning the full
+    /// access keys added to it.
+    fn create_with_default_keys(default_keys: Vec<PublicKey>) -> Vec<PublicKey> {
+        set_predecessor(owner());
+        let mut contract = LinkDrop::new(owner(), None);
+        contract.set_default_full_access_keys(default_keys.clone()).unwrap();
+        assert_eq!(contract.get_default_full_access_keys(), default_keys);
+
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob())
+            .attached_deposit(1_000)
+            .context.clone()
+        );
+        contract.create_account_advanced(new_account(), key_options(), None, None, None, None).unwrap();
+        get_created_receipts()[0]
+            .actions
+            .iter()
+            .filter_map(|action| match action {
+                VmAction::AddKeyWithFullAccess { public_key, .. } => Some(public_key.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_default_full_access_keys() {
+        // The defaults come after the caller's keys, once even if the caller gave them too
+        assert_eq!(create_with_default_keys(vec![key(1), funding_key()]), vec![funding_key(), key(1)]);
+        assert_eq!(
+            get_logs(),
+            vec![format!(
+                r#"EVENT_JSON:{{"standard":"arkana_account","version":"1.1.0","event":"default_keys_added","data":[{{"new_account_id":"bob.linkdrop","public_keys":["{}","{}"]}}]}}"#,
+                String::from(&key(1)),
+                String::from(&funding_key())
+            )]
+        );
+    }
+
+    #[test]
+    fn test_no_default_full_access_keys() {
+        assert_eq!(create_with_default_keys(vec![]), vec![funding_key()]);
+        assert!(get_logs().is_empty());
+    }
+
+    #[test]
+    fn test_duplicate_default_full_access_keys() {
+        set_predecessor(owner());
+        let mut contract = LinkDrop::new(owner(), None);
+
+        assert_eq!(
+            contract.set_default_full_access_keys(vec![key(1), key(1)]).err(),
+            Some(ContractError::InvalidArgument(format!("Public key {} is added more than once", String::from(&key(1)))))
+        );
+    }
+
+    #[test]
+    fn test_set_default_full_access_keys_not_owner() {
+        set_predecessor(bob());
+        let mut contract = LinkDrop::new(owner(), None);
+
+        assert_eq!(
+            contract.set_default_full_access_keys(vec![key(1)]).err(),
+            Some(ContractError::Unauthorized("Only the owner can call this method"))
+        );
+    }
+
+    /// Create an account attaching `deposit` with the contract allowing at most 1000 yoctoNEAR per call.
+    fn create_with_max_deposit(deposit: Balance) {
+        set_predecessor(owner());
+        let mut contract = LinkDrop::new(owner(), None);
+        assert_eq!(contract.get_max_deposit_per_call(), U128(Balance::MAX));
+        contract.set_max_deposit_per_call(U128(1_000)).unwrap();
+
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob())
+            .attached_deposit(deposit)
+            .context.clone()
+        );
+        contract.create_account_advanced(new_account(), key_options(), None, None, None, None).unwrap();
+    }
+
+    #[test]
+    fn test_max_deposit_per_call() {
+        create_with_max_deposit(1_000);
+        assert!(get_created_receipts()[0].actions.contains(&VmAction::Transfer { deposit: 1_000 }));
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot attach more than 1000 yoctoNEAR to a call")]
+    fn test_above_max_deposit_per_call() {
+        create_with_max_deposit(1_001);
+    }
+
+    /// Create `new_account()` with a contract of `size` bytes, the contract accepting contracts of 10 bytes at most.
+    fn create_with_max_contract_size(size: usize) -> Result<Promise, ContractError> {
+        set_predecessor(owner());
+        let mut contract = LinkDrop::new(owner(), None);
+        assert_eq!(contract.get_max_contract_size(), U64(DEFAULT_MAX_CONTRACT_SIZE));
+        contract.set_max_contract_size(U64(10)).unwrap();
+        assert_eq!(contract.get_max_contract_size(), U64(10));
+
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob())
+            .attached_deposit(1_000)
+            .context.clone()
+        );
+        contract.create_account_with_contract(new_account(), Base64VecU8(vec![0; size]), None, key_options())
+    }
+
+    #[test]
+    fn test_max_contract_size() {
+        create_with_max_contract_size(10).unwrap();
+        assert!(get_created_receipts()[0].actions.contains(&VmAction::DeployContract { code: vec![0; 10] }));
+    }
+
+    #[test]
+    fn test_above_max_contract_size() {
+        assert_eq!(
+            create_with_max_contract_size(11).err(),
+            Some(ContractError::InvalidArgument("Contract is 11 bytes long, at most 10 bytes are allowed".to_string()))
+        );
+        assert!(get_created_receipts().is_empty());
+    }
+
+    /// Fund a linkdrop with `deposit` with the contract locking at most `MIN_SEND_DEPOSIT` in total, of which
+    /// `ACCESS_KEY_ALLOWANCE` is already locked: exactly what a `MIN_SEND_DEPOSIT` send adds.
+    fn send_with_max_total_locked(deposit: Balance) {
+        set_predecessor(owner());
+        let mut contract = LinkDrop::new(owner(), None);
+        assert_eq!(contract.get_max_total_locked(), U128(Balance::MAX));
+        contract.set_max_total_locked(U128(MIN_SEND_DEPOSIT)).unwrap();
+        contract.total_locked = ACCESS_KEY_ALLOWANCE;
+        register_storage(&mut contract);
+
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob())
+            .attached_deposit(deposit)
+            .context.clone()
+        );
+        contract.send(funding_key(), None, None, None, None, None, None).unwrap();
+        assert_eq!(contract.total_locked, deposit);
+    }
+
+    #[test]
+    fn test_max_total_locked() {
+        send_with_max_total_locked(MIN_SEND_DEPOSIT);
+    }
+
+    #[test]
+    #[should_panic(expected = "Funding the linkdrop would lock more than the maximum of")]
+    fn test_above_max_total_locked() {
+        send_with_max_total_locked(MIN_SEND_DEPOSIT + 1);
+    }
+
+    /// Settle a creation with `result` through the `on_account_created` callback.
+    fn settle_creation(contract: &mut LinkDrop, result: PromiseResult) {
+        settle_creation_of(contract, bob(), result);
+    }
+
+    fn settle_creation_of(contract: &mut LinkDrop, creator_id: AccountId, result: PromiseResult) {
+        callback_env(result);
+        drop(contract.on_account_created(new_account(), creator_id.clone(), creator_id, U128(1_000), None, None, None));
+    }
+
+    fn creator(i: u8) -> AccountId {
+        format!("creator{}.near", i).parse().unwrap()
+    }
+
+    /// A contract pausing itself after more than 2 failed creations in a row.
+    fn circuit_breaker_contract() -> LinkDrop {
+        set_predecessor(owner());
+        let mut contract = LinkDrop::new(owner(), None);
+        contract.set_max_consecutive_failures(2).unwrap();
+        assert_eq!(contract.get_max_consecutive_failures(), 2);
+        contract
+    }
+
+    #[test]
+    fn test_circuit_breaker_trips() {
+        let mut contract = circuit_breaker_contract();
+
+        settle_creation_of(&mut contract, creator(1), PromiseResult::Failed);
+        settle_creation_of(&mut contract, creator(2), PromiseResult::Failed);
+        assert!(!contract.is_paused());
+
+        settle_creation_of(&mut contract, creator(3), PromiseResult::Failed);
+        assert!(contract.is_paused());
+        assert_eq!(contract.get_consecutive_failures(), 3);
+        assert_eq!(
+            get_logs().last().unwrap(),
+            r#"EVENT_JSON:{"standard":"arkana_account","version":"1.1.0","event":"circuit_breaker_tripped","data":[{"consecutive_failures":3}]}"#
+        );
+
+        // Only the owner resumes, with a fresh count
+        set_predecessor(owner());
+        contract.unpause().unwrap();
+        assert!(!contract.is_paused());
+        assert_eq!(contract.get_consecutive_failures(), 0);
+        assert!(contract.failing_creators.is_empty());
+    }
+
+    #[test]
+    fn test_circuit_breaker_reset_on_success() {
+        let mut contract = circuit_breaker_contract();
+
+        settle_creation_of(&mut contract, creator(1), PromiseResult::Failed);
+        settle_creation_of(&mut contract, creator(2), PromiseResult::Failed);
+        settle_creation(&mut contract, PromiseResult::Successful(vec![]));
+        assert_eq!(contract.get_consecutive_failures(), 0);
+
+        // The callers count again in the next run of failures
+        settle_creation_of(&mut contract, creator(1), PromiseResult::Failed);
+        settle_creation_of(&mut contract, creator(2), PromiseResult::Failed);
+        assert!(!contract.is_paused());
+        assert_eq!(contract.get_consecutive_failures(), 2);
+    }
+
+    #[test]
+    fn test_circuit_breaker_counts_caller_once() {
+        let mut contract = circuit_breaker_contract();
+
+        // A caller retrying the creation of an account that already exists
+        for _ in 0..10 {
+            settle_creation(&mut contract, PromiseResult::Failed);
+        }
+        assert!(!contract.is_paused());
+        assert_eq!(contract.get_consecutive_failures(), 1);
+    }
+
+    #[test]
+    fn test_circuit_breaker_disabled() {
+        set_predecessor(owner());
+        let mut contract = LinkDrop::new(owner(), None);
+
+        for _ in 0..10 {
+            settle_creation(&mut contract, PromiseResult::Failed);
+        }
+        assert!(!contract.is_paused());
+        assert_eq!(contract.get_consecutive_failures(), 10);
+    }
+
+    /// A contract making a caller wait 100 nanoseconds after each successful creation.
+    fn cooldown_contract() -> LinkDrop {
+        set_predecessor(owner());
+        let mut contract = LinkDrop::new(owner(), None);
+        contract.set_per_account_cooldown(U64(100)).unwrap();
+        assert_eq!(contract.get_per_account_cooldown(), U64(100));
+        contract
+    }
+
+    fn create_at(contract: &mut LinkDrop, block_timestamp: u64) {
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob())
+            .attached_deposit(1_000)
+            .block_timestamp(block_timestamp)
+            .context.clone()
+        );
+        contract.create_account_advanced(new_account(), key_options(), None, None, None, None).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "bob must wait until 100 to create another account")]
+    fn test_cooldown_rejects_rapid_creation() {
+        let mut contract = cooldown_contract();
+
+        create_at(&mut contract, 0);
+        settle_creation(&mut contract, PromiseResult::Successful(vec![]));
+        create_at(&mut contract, 99);
+    }
+
+    #[test]
+    fn test_cooldown_elapsed() {
+        let mut contract = cooldown_contract();
+
+        create_at(&mut contract, 0);
+        settle_creation(&mut contract, PromiseResult::Successful(vec![]));
+        create_at(&mut contract, 100);
+        assert!(contract.is_creation_in_flight(new_account()));
+    }
+
+    #[test]
+    fn test_cooldown_ignores_failed_creation() {
+        let mut contract = cooldown_contract();
+
+        create_at(&mut contract, 0);
+        settle_creation(&mut contract, PromiseResult::Failed);
+        create_at(&mut contract, 1);
+        assert!(contract.is_creation_in_flight(new_account()));
+    }
+
+    /// Fail a creation of 1000 yoctoNEAR by bob under `refund_policy`, returning the receiver of the refund if any.
+    fn fail_creation_with(contract: &mut LinkDrop, refund_policy: RefundPolicy) -> Option<AccountId> {
+        set_predecessor(owner());
+        contract.set_refund_policy(refund_policy.clone()).unwrap();
+        assert_eq!(contract.get_refund_policy(), refund_policy);
+
+        settle_creation(contract, PromiseResult::Failed);
+        get_created_receipts()
+            .into_iter()
+            .find(|receipt| receipt.actions == vec![VmAction::Transfer { deposit: 1_000 }])
+            .map(|receipt| receipt.receiver_id)
+    }
+
+    #[test]
+    fn test_refund_to_predecessor() {
+        set_predecessor(owner());
+        let mut contract = LinkDrop::new(owner(), None);
+        assert_eq!(contract.get_refund_policy(), RefundPolicy::RefundToPredecessor);
+
+        assert_eq!(fail_creation_with(&mut contract, RefundPolicy::RefundToPredecessor), Some(bob()));
+    }
+
+    #[test]
+    fn test_refund_to_override() {
+        set_predecessor(owner());
+        let mut contract = LinkDrop::new(owner(), None);
+
+        let refund_policy = RefundPolicy::RefundTo { account_id: owner() };
+        assert_eq!(fail_creation_with(&mut contract, refund_policy), Some(owner()));
+        // The event still names the caller, apart from where the deposit went
+        assert_eq!(
+            get_logs(),
+            vec![r#"EVENT_JSON:{"standard":"arkana_account","version":"1.1.0","event":"creation_failed","data":[{"new_account_id":"bob.linkdrop","predecessor_account_id":"bob","refund_to":"owner","refunded":"1000","reason":"insufficient_funds"}]}"#]
+        );
+    }
+
+    #[test]
+    fn test_retain_refund() {
+        set_predecessor(owner());
+        let mut contract = LinkDrop::new(owner(), None);
+
+        // Nothing is transferred, the deposit is credited to the treasury
+        assert_eq!(fail_creation_with(&mut contract, RefundPolicy::Retain), None);
+        assert_eq!(contract.get_retained_refunds(), U128(1_000));
+        assert_eq!(
+            get_logs(),
+            vec![
+                r#"EVENT_JSON:{"standard":"arkana_account","version":"1.1.0","event":"creation_failed","data":[{"new_account_id":"bob.linkdrop","predecessor_account_id":"bob","refund_to":null,"refunded":"0","reason":"insufficient_funds"}]}"#,
+                r#"EVENT_JSON:{"standard":"arkana_account","version":"1.1.0","event":"refund_retained","data":[{"new_account_id":"bob.linkdrop","predecessor_account_id":"bob","amount":"1000"}]}"#,
+            ]
+        );
+
+        set_predecessor(owner());
+        contract.set_treasury(bob()).unwrap();
+        drop(contract.withdraw_retained_refunds().unwrap());
+        let receipts = get_created_receipts();
+        assert_eq!(receipts[0].receiver_id, bob());
+        assert_eq!(receipts[0].actions, vec![VmAction::Transfer { deposit: 1_000 }]);
+        assert_eq!(contract.get_retained_refunds(), U128(0));
+    }
+
+    #[test]
+    fn test_withdraw_no_retained_refunds() {
+        set_predecessor(owner());
+        let mut contract = LinkDrop::new(owner(), None);
+
+        assert_eq!(
+            contract.withdraw_retained_refunds().err(),
+            Some(ContractError::InvalidArgument("No refunds are retained".to_string()))
+        );
+    }
+
+    /// A contract holding 10 N, 2 N of which are locked in linkdrops, 1 N deposited for storage and 1 N retained.
+    fn funded_contract() -> LinkDrop {
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(owner())
+            .account_balance(10 * WITHDRAW_SAFETY_MARGIN)
+            .context.clone()
+        );
+        let mut contract = LinkDrop::new(owner(), None);
+        contract.total_locked = 2 * WITHDRAW_SAFETY_MARGIN;
+        contract.storage_deposits = WITHDRAW_SAFETY_MARGIN;
+        contract.retained_refunds = WITHDRAW_SAFETY_MARGIN;
+        contract
+    }
+
+    /// What `funded_contract` can withdraw: its balance minus the protected funds, its storage and the margin.
+    fn available_balance() -> Balance {
+        5 * WITHDRAW_SAFETY_MARGIN - env::storage_usage() as Balance * env::storage_byte_cost()
+    }
+
+    #[test]
+    fn test_withdraw_contract_balance() {
+        let mut contract = funded_contract();
+        assert_eq!(contract.get_available_balance(), U128(available_balance()));
+
+        drop(contract.withdraw_contract_balance(U128(available_balance()), bob()).unwrap());
+        let receipts = get_created_receipts();
+        assert_eq!(receipts[0].receiver_id, bob());
+        assert_eq!(receipts[0].actions, vec![VmAction::Transfer { deposit: available_balance() }]);
+    }
+
+    #[test]
+    fn test_withdraw_locked_balance() {
+        let mut contract = funded_contract();
+
+        assert_eq!(
+            contract.withdraw_contract_balance(U128(available_balance() + 1), bob()).err(),
+            Some(ContractError::InvalidArgument(format!(
+                "Cannot withdraw {} yoctoNEAR, only {} yoctoNEAR are available",
+                available_balance() + 1,
+                available_balance()
+            )))
+        );
+    }
+
+    #[test]
+    fn test_pending_refunds_protected() {
+        let mut contract = funded_contract();
+
+        // The excess of an in-flight creation over its target balance is owed to its caller
+        contract.pending_refunds = WITHDRAW_SAFETY_MARGIN;
+        assert_eq!(contract.get_available_balance(), U128(available_balance() - WITHDRAW_SAFETY_MARGIN));
+    }
+
+    #[test]
+    fn test_withdraw_contract_balance_not_owner() {
+        let mut contract = funded_contract();
+
+        set_predecessor(bob());
+        assert_eq!(
+            contract.withdraw_contract_balance(U128(1), bob()).err(),
+            Some(ContractError::Unauthorized("Only the owner can call this method"))
+        );
+    }
+}
@@ -0,0 +1,585 @@
+use crate::*;
+
+/// Storage overhead NEAR charges for every key/value record on top of the bytes themselves.
+const STORAGE_RECORD_OVERHEAD: u64 = 40;
+/// Largest borsh size of a `PublicKey`: length prefix, curve byte and an uncompressed secp256k1 key.
+const PUBLIC_KEY_MAX_BYTES: u64 = 4 + 1 + 64;
+/// Largest borsh size of a `DropInfo`: balance, funder id, expiration, fungible tokens, NFT, metadata, secret
+/// hash, uses, claim rate limit, vesting, category and claim callback.
+const DROP_INFO_MAX_BYTES: u64 = 16
+    + (4 + 64)
+    + (1 + 8)
+    + (1 + (4 + 64) + 16)
+    + (1 + (4 + 64) + (4 + MAX_NFT_TOKEN_ID_BYTES as u64))
+    + (1 + 4 + MAX_METADATA_BYTES as u64)
+    + (1 + 4 + 32)
+    + 4
+    + 16
+    + 8
+    + 8
+    + (1 + 8 + 8 + 8)
+    + 16
+    + (1 + 4 + MAX_CATEGORY_BYTES as u64)
+    + (1 + 4 + 64)
+    + (1 + 4 + MAX_METHOD_NAME_BYTES as u64);
+
+/// Prefix of the `drops_by_funder` set of a funder: a short tag and the sha256 of the funder id.
+const FUNDER_PREFIX_BYTES: u64 = 2 + 32;
+
+/// Bytes taken by an entry of `accounts` and its `drops_by_funder` index. The `UnorderedMap` writes three records
+/// per entry, all under a short prefix: the key to index lookup, the key in the keys vector and the drop in the
+/// values vector. The funder's `UnorderedSet` writes two more: the key to index lookup and the key in its vector.
+pub const BYTES_PER_DROP: u64 = 5 * STORAGE_RECORD_OVERHEAD
+    + (2 + PUBLIC_KEY_MAX_BYTES + 8)
+    + (2 + 8 + PUBLIC_KEY_MAX_BYTES)
+    + (2 + 8 + DROP_INFO_MAX_BYTES)
+    + (FUNDER_PREFIX_BYTES + 1 + PUBLIC_KEY_MAX_BYTES + 8)
+    + (FUNDER_PREFIX_BYTES + 1 + 8 + PUBLIC_KEY_MAX_BYTES);
+
+/// Bytes NEAR charges for an account record, whatever its keys and code.
+pub const ACCOUNT_STORAGE_BYTES: u64 = 100;
+/// Bytes taken by a full access key on an account: the record overhead, the public key, the nonce and the
+/// permission tag.
+const ACCESS_KEY_STORAGE_BYTES: u64 = STORAGE_RECORD_OVERHEAD + PUBLIC_KEY_MAX_BYTES + 8 + 1;
+
+/// Bytes the account created with `options` takes once its keys are added and its contract deployed.
+pub fn account_storage_bytes(options: &CreateAccountOptions) -> u64 {
+    let full_access_keys = options.full_access_keys.as_ref().map_or(0, Vec::len) as u64;
+    let limited_access_keys: u64 = options
+        .limited_keys()
+        .iter()
+        .map(|key_info| {
+            // On top of a full access key: the allowance, the receiver and the method names
+            let method_names = key_info.method_names.split(',').filter(|name| !name.is_empty());
+            let method_names_bytes: u64 = method_names.map(|name| 4 + name.len() as u64).sum();
+            ACCESS_KEY_STORAGE_BYTES + (1 + 16) + (4 + key_info.receiver_id.as_str().len() as u64) + 4 + method_names_bytes
+        })
+        .sum();
+    let contract_bytes = options.contract_bytes.as_ref().map_or(0, Vec::len) as u64;
+
+    ACCOUNT_STORAGE_BYTES + full_access_keys * ACCESS_KEY_STORAGE_BYTES + limited_access_keys + contract_bytes
+}
+
+/// Margin over the storage stake below which `send` draws from the storage reserve: 1 N.
+pub const LOW_STORAGE_MARGIN: Balance = 1_000_000_000_000_000_000_000_000;
+
+/// yoctoNEAR$ a funder needs in their storage balance for every linkdrop they fund.
+pub fn drop_storage_cost() -> Balance {
+    BYTES_PER_DROP as Balance * env::storage_byte_cost()
+}
+
+#[near_bindgen]
+impl LinkDrop {
+    /// NEP-145: pre-pay the storage of the linkdrops funded by `account_id` (the predecessor by default).
+    /// With `registration_only`, only the minimum balance is kept and the rest of the deposit is refunded.
+    #[payable]
+    #[handle_result]
+    pub fn storage_deposit(
+        &mut self,
+        account_id: Option<AccountId>,
+        registration_only: Option<bool>,
+    ) -> Result<StorageBalance, ContractError> {
+        let account_id = account_id.unwrap_or_else(env::predecessor_account_id);
+        let deposit = env::attached_deposit();
+        let mut storage = self.storage_accounts.get(&account_id);
+
+        let amount = if registration_only.unwrap_or(false) {
+            // Registering twice refunds the whole deposit
+            let amount = if storage.is_some() { 0 } else { drop_storage_cost() };
+            ensure(deposit >= amount, || {
+                ContractError::InsufficientDeposit(
+                    "The attached deposit is less than the minimum storage balance".to_string(),
+                )
+            })?;
+            if deposit > amount {
+                Promise::new(env::predecessor_account_id()).transfer(deposit - amount);
+            }
+            amount
+        } else {
+            ensure(storage.is_some() || deposit >= drop_storage_cost(), || {
+                ContractError::InsufficientDeposit(
+                    "The attached deposit is less than the minimum storage balance".to_string(),
+                )
+            })?;
+            deposit
+        };
+
+        let storage = storage.get_or_insert(StorageAccount { total: 0, used: 0 });
+        storage.total += amount;
+        self.storage_accounts.insert(&account_id, storage);
+        self.storage_deposits += amount;
+        Ok(storage.into())
+    }
+
+    /// NEP-145: withdraw `amount` (everything available by default) of the predecessor's unused storage balance.
+    #[payable]
+    #[handle_result]
+    pub fn storage_withdraw(&mut self, amount: Option<U128>) -> Result<StorageBalance, ContractError> {
+        ensure(env::attached_deposit() == 1, || {
+            ContractError::InsufficientDeposit("Requires attached deposit of exactly 1 yoctoNEAR".to_string())
+        })?;
+        let account_id = env::predecessor_account_id();
+        let mut storage = self.storage_accounts.get(&account_id).ok_or_else(|| {
+            ContractError::InvalidArgument("The account is not registered".to_string())
+        })?;
+
+        let available = storage.available();
+        let amount = amount.map_or(available, |amount| amount.0);
+        ensure(amount <= available, || {
+            ContractError::InvalidArgument("The amount is greater than the available storage balance".to_string())
+        })?;
+
+        storage.total -= amount;
+        self.storage_accounts.insert(&account_id, &storage);
+        self.storage_deposits -= amount;
+        if amount > 0 {
+            Promise::new(account_id).transfer(amount);
+        }
+        Ok((&storage).into())
+    }
+
+    /// NEP-145: storage balance of `account_id`, `None` if they never deposited.
+    pub fn storage_balance_of(&self, account_id: AccountId) -> Option<StorageBalance> {
+        self.storage_accounts.get(&account_id).map(|storage| (&storage).into())
+    }
+
+    /// NEP-145: the minimum storage balance covers a single linkdrop, and there is no maximum.
+    pub fn storage_balance_bounds(&self) -> StorageBalanceBounds {
+        StorageBalanceBounds {
+            min: U128(drop_storage_cost()),
+            max: None,
+        }
+    }
+
+    /// Returns the bytes of storage the contract uses, its state and code.
+    pub fn get_storage_usage(&self) -> U64 {
+        U64(env::storage_usage())
+    }
+
+    /// Returns the yoctoNEAR$ the contract must keep in its balance to pay for its storage.
+    pub fn get_storage_cost(&self) -> U128 {
+        U128(env::storage_usage() as Balance * env::storage_byte_cost())
+    }
+
+    /// Returns the yoctoNEAR$ of the contract balance left over its storage stake, not counting the balances
+    /// locked in linkdrops nor the storage reserve. `send` draws from the reserve once it is below
+    /// `LOW_STORAGE_MARGIN`.
+    pub fn get_storage_margin(&self) -> U128 {
+        let protected = self.total_locked + self.storage_reserve + self.get_storage_cost().0;
+        U128(env::account_balance().saturating_sub(protected))
+    }
+
+    /// Add the attached deposit to the storage reserve. Only callable by the owner. Returns the new reserve.
+    #[payable]
+    #[handle_result]
+    pub fn fund_storage_reserve(&mut self) -> Result<U128, ContractError> {
+        self.check_owner()?;
+        let amount = env::attached_deposit();
+        ensure(amount > 0, || {
+            ContractError::InsufficientDeposit("Attach the yoctoNEAR to add to the storage reserve".to_string())
+        })?;
+        self.storage_reserve += amount;
+        Ok(U128(self.storage_reserve))
+    }
+
+    /// Returns the yoctoNEAR$ left in the storage reserve.
+    pub fn get_storage_reserve(&self) -> U128 {
+        U128(self.storage_reserve)
+    }
+}
+
+impl LinkDrop {
+    /// Take the storage of a new linkdrop out of the funder's storage balance.
+    pub(crate) fn internal_charge_drop_storage(&mut self, funder_id: &AccountId) -> Result<(), ContractError> {
+        let mut storage = self
+            .storage_accounts
+            .get(funder_id)
+            .unwrap_or(StorageAccount { total: 0, used: 0 });
+        ensure(storage.available() >= drop_storage_cost(), || {
+            ContractError::InsufficientDeposit(format!(
+                "Not enough storage balance to fund a new key, deposit at least {} yoctoNEAR with storage_deposit",
+                drop_storage_cost() - storage.available()
+            ))
+        })?;
+        storage.used += drop_storage_cost();
+        self.storage_accounts.insert(funder_id, &storage);
+        Ok(())
+    }
+
+    /// Draw from the storage reserve up to `LOW_STORAGE_MARGIN` if the storage margin fell below it, warning with a
+    /// `low_storage_balance` event. The drawn yoctoNEAR$ stay in the contract balance, paying for its storage
+    /// rather than being set aside.
+    pub(crate) fn internal_draw_storage_reserve(&mut self) {
+        let margin = self.get_storage_margin().0;
+        if margin >= LOW_STORAGE_MARGIN {
+            return;
+        }
+        let drawn = (LOW_STORAGE_MARGIN - margin).min(self.storage_reserve);
+        self.storage_reserve -= drawn;
+        EventLog::new(EventLogVariant::LowStorageBalance(vec![LowStorageBalanceLog {
+            margin: U128(margin),
+            drawn: U128(drawn),
+            storage_reserve: U128(self.storage_reserve),
+        }]))
+        .emit();
+    }
+
+    /// Fails if the balance of the contract, not counting the balances locked in linkdrops, doesn't cover its
+    /// storage. The locked balances belong to the claims and can't pay for storage.
+    pub(crate) fn check_storage_covered(&self) -> Result<(), ContractError> {
+        let free_balance = env::account_balance().saturating_sub(self.total_locked);
+        ensure(free_balance >= self.get_storage_cost().0, || {
+            ContractError::InsufficientDeposit(format!(
+                "The contract balance doesn't cover its storage of {} bytes",
+                env::storage_usage()
+            ))
+        })
+    }
+
+    /// Give the storage of a removed linkdrop back to the funder's storage balance.
+    pub(crate) fn internal_release_drop_storage(&mut self, funder_id: &AccountId) {
+        if let Some(mut storage) = self.storage_accounts.get(funder_id) {
+            storage.used = storage.used.saturating_sub(drop_storage_cost());
+            self.storage_accounts.insert(funder_id, &storage);
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[cfg(test)]
+mod tests {
+    use near_sdk::mock::VmAction;
+    use near_sdk::serde_json;
+    use near_sdk::test_utils::{get_created_receipts, get_logs, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use crate::tests::{bob, funding_key, linkdrop, owner};
+
+    use super::*;
+
+    fn set_deposit(attached_deposit: Balance) {
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob())
+            .attached_deposit(attached_deposit)
+            .context.clone()
+        );
+    }
+
+    #[test]
+    fn test_storage_deposit() {
+        set_deposit(0);
+        let mut contract = LinkDrop::new(owner(), None);
+        assert_eq!(contract.storage_balance_of(bob()), None);
+        assert_eq!(contract.storage_balance_bounds().min, U128(drop_storage_cost()));
+
+        // Deposits add up
+        set_deposit(drop_storage_cost());
+        contract.storage_deposit(None, None).unwrap();
+        let balance = contract.storage_deposit(None, None).unwrap();
+        assert_eq!(balance.total, U128(2 * drop_storage_cost()));
+        assert_eq!(balance.available, U128(2 * drop_storage_cost()));
+        assert_eq!(contract.storage_deposits, 2 * drop_storage_cost());
+
+        // Funding a key uses part of it
+        set_deposit(MIN_SEND_DEPOSIT);
+        contract.send(funding_key(), None, None, None, None, None, None).unwrap();
+        let balance = contract.storage_balance_of(bob()).unwrap();
+        assert_eq!(balance.total, U128(2 * drop_storage_cost()));
+        assert_eq!(balance.available, U128(drop_storage_cost()));
+    }
+
+    #[test]
+    fn test_storage_deposit_registration_only() {
+        set_deposit(3 * drop_storage_cost());
+        let mut contract = LinkDrop::new(owner(), None);
+
+        // Only the minimum is kept
+        let balance = contract.storage_deposit(None, Some(true)).unwrap();
+        assert_eq!(balance.total, U128(drop_storage_cost()));
+        assert_eq!(
+            get_created_receipts()[0].actions,
+            vec![VmAction::Transfer { deposit: 2 * drop_storage_cost() }]
+        );
+
+        // Registering again refunds everything
+        set_deposit(drop_storage_cost());
+        let balance = contract.storage_deposit(None, Some(true)).unwrap();
+        assert_eq!(balance.total, U128(drop_storage_cost()));
+        assert_eq!(
+            get_created_receipts()[0].actions,
+            vec![VmAction::Transfer { deposit: drop_storage_cost() }]
+        );
+    }
+
+    #[test]
+    fn test_storage_deposit_below_minimum() {
+        set_deposit(drop_storage_cost() - 1);
+        let mut contract = LinkDrop::new(owner(), None);
+
+        assert_eq!(
+            contract.storage_deposit(None, None).err(),
+            Some(ContractError::InsufficientDeposit("The attached deposit is less than the minimum storage balance".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_storage_deposit_for_other_account() {
+        set_deposit(drop_storage_cost());
+        let mut contract = LinkDrop::new(owner(), None);
+
+        contract.storage_deposit(Some(owner()), None).unwrap();
+        assert_eq!(contract.storage_balance_of(bob()), None);
+        assert_eq!(contract.storage_balance_of(owner()).unwrap().total, U128(drop_storage_cost()));
+    }
+
+    #[test]
+    fn test_storage_withdraw() {
+        set_deposit(3 * drop_storage_cost());
+        let mut contract = LinkDrop::new(owner(), None);
+        contract.storage_deposit(None, None).unwrap();
+        set_deposit(MIN_SEND_DEPOSIT);
+        contract.send(funding_key(), None, None, None, None, None, None).unwrap();
+
+        // Only the excess over the storage in use can be withdrawn
+        set_deposit(1);
+        let balance = contract.storage_withdraw(Some(U128(drop_storage_cost()))).unwrap();
+        assert_eq!(balance.available, U128(drop_storage_cost()));
+        let balance = contract.storage_withdraw(None).unwrap();
+        assert_eq!(balance.total, U128(drop_storage_cost()));
+        assert_eq!(balance.available, U128(0));
+        assert_eq!(contract.storage_deposits, drop_storage_cost());
+        assert_eq!(
+            get_created_receipts()[0].actions,
+            vec![VmAction::Transfer { deposit: drop_storage_cost() }]
+        );
+    }
+
+    #[test]
+    fn test_storage_withdraw_in_use() {
+        set_deposit(drop_storage_cost());
+        let mut contract = LinkDrop::new(owner(), None);
+        contract.storage_deposit(None, None).unwrap();
+        set_deposit(MIN_SEND_DEPOSIT);
+        contract.send(funding_key(), None, None, None, None, None, None).unwrap();
+
+        set_deposit(1);
+        assert_eq!(
+            contract.storage_withdraw(Some(U128(1))).err(),
+            Some(ContractError::InvalidArgument("The amount is greater than the available storage balance".to_string()))
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Not enough storage balance to fund a new key")]
+    fn test_send_without_storage() {
+        set_deposit(MIN_SEND_DEPOSIT);
+        let mut contract = LinkDrop::new(owner(), None);
+
+        contract.send(funding_key(), None, None, None, None, None, None).unwrap();
+    }
+
+    #[test]
+    fn test_claim_releases_storage() {
+        set_deposit(drop_storage_cost());
+        let mut contract = LinkDrop::new(owner(), None);
+        contract.storage_deposit(None, None).unwrap();
+        set_deposit(MIN_SEND_DEPOSIT);
+        contract.send(funding_key(), None, None, None, None, None, None).unwrap();
+
+        let drop = contract.accounts.remove(&funding_key()).unwrap();
+        crate::tests::callback_env(PromiseResult::Successful(vec![]));
+        contract.on_account_created_and_claimed(crate::tests::new_account(), funding_key(), drop, true, None, true, false);
+
+        assert_eq!(contract.storage_balance_of(bob()).unwrap().available, U128(drop_storage_cost()));
+    }
+
+    fn options(full_access_keys: Option<Vec<PublicKey>>, contract_bytes: Option<Vec<u8>>) -> CreateAccountOptions {
+        CreateAccountOptions {
+            full_access_keys,
+            full_access_key_nonce: None,
+            limited_access_keys: None,
+            templated_access_keys: None,
+            contract_bytes,
+            deploy_init: None,
+            stake: None,
+        }
+    }
+
+    #[test]
+    fn test_account_creation_cost() {
+        set_deposit(0);
+        let contract = LinkDrop::new(owner(), None);
+        let byte_cost = env::storage_byte_cost();
+
+        // Without options only the account itself is paid for
+        let base = contract.get_account_creation_cost(options(None, None)).deposit.0;
+        assert_eq!(base, ACCOUNT_STORAGE_BYTES as Balance * byte_cost);
+
+        // Every key and every contract byte adds to it
+        let keys_only = contract.get_account_creation_cost(options(Some(vec![funding_key()]), None)).deposit.0;
+        assert_eq!(keys_only, base + ACCESS_KEY_STORAGE_BYTES as Balance * byte_cost);
+        let with_contract = contract
+            .get_account_creation_cost(options(Some(vec![funding_key()]), Some(vec![0; 1_000])))
+            .deposit
+            .0;
+        assert_eq!(with_contract, keys_only + 1_000 * byte_cost);
+    }
+
+    #[test]
+    fn test_account_creation_cost_limited_key() {
+        set_deposit(0);
+        let contract = LinkDrop::new(owner(), None);
+        let limited_key = |method_names: &str| CreateAccountOptions {
+            full_access_keys: None,
+            full_access_key_nonce: None,
+            limited_access_keys: Some(vec![LimitedAccessKey {
+                public_key: funding_key(),
+                allowance: U128(1),
+                receiver_id: linkdrop(),
+                method_names: method_names.to_string(),
+                nonce: None,
+            }]),
+            templated_access_keys: None,
+            contract_bytes: None,
+            deploy_init: None,
+            stake: None,
+        };
+
+        // Limited keys cost more than full access keys, and more with every method name
+        let full_key = contract.get_account_creation_cost(options(Some(vec![funding_key()]), None)).deposit.0;
+        let any_method = contract.get_account_creation_cost(limited_key("")).deposit.0;
+        let two_methods = contract.get_account_creation_cost(limited_key("claim,send")).deposit.0;
+        assert!(full_key < any_method);
+        assert!(any_method < two_methods);
+    }
+
+    #[test]
+    fn test_account_creation_cost_includes_fee() {
+        set_deposit(0);
+        let mut contract = LinkDrop::new(owner(), None);
+        let base = contract.get_account_creation_cost(options(None, None)).deposit.0;
+
+        contract.creation_fee = 100;
+        assert_eq!(contract.get_account_creation_cost(options(None, None)).deposit.0, base + 100);
+
+        // The percentage fee is taken out of the rest, leaving the storage cost to the new account
+        contract.fee_bps = 5_000;
+        let cost = contract.get_account_creation_cost(options(None, None)).deposit.0;
+        assert_eq!(cost, 2 * base + 100);
+        assert_eq!(cost - contract.global_fee_config().fees_of(cost), base);
+
+        contract.fee_bps = MAX_FEE_BPS;
+        assert_eq!(contract.get_account_creation_cost(options(None, None)).deposit.0, Balance::MAX);
+    }
+
+    #[test]
+    fn test_storage_usage() {
+        set_deposit(drop_storage_cost());
+        let mut contract = LinkDrop::new(owner(), None);
+        contract.storage_deposit(None, None).unwrap();
+        let usage = contract.get_storage_usage();
+        assert!(usage.0 > 0);
+
+        // Funding a key adds the linkdrop to the state
+        set_deposit(MIN_SEND_DEPOSIT);
+        contract.send(funding_key(), None, None, None, None, None, None).unwrap();
+        assert!(contract.get_storage_usage().0 > usage.0);
+        assert_eq!(
+            contract.get_storage_cost(),
+            U128(contract.get_storage_usage().0 as Balance * env::storage_byte_cost())
+        );
+    }
+
+    #[test]
+    fn test_send_storage_not_covered() {
+        set_deposit(drop_storage_cost());
+        let mut contract = LinkDrop::new(owner(), None);
+        contract.storage_deposit(None, None).unwrap();
+
+        // The whole balance of the contract is the linkdrop itself
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob())
+            .attached_deposit(MIN_SEND_DEPOSIT)
+            .account_balance(MIN_SEND_DEPOSIT)
+            .context.clone()
+        );
+        assert_eq!(
+            contract.send(funding_key(), None, None, None, None, None, None).err(),
+            Some(ContractError::InsufficientDeposit(format!(
+                "The contract balance doesn't cover its storage of {} bytes",
+                env::storage_usage()
+            )))
+        );
+    }
+
+    /// Fund the funding key with a contract balance leaving `margin` over the storage stake once the linkdrop is
+    /// funded, not counting a storage reserve of 2 N. Returns the contract.
+    fn send_with_margin(margin: Balance) -> LinkDrop {
+        set_deposit(drop_storage_cost());
+        let mut contract = LinkDrop::new(owner(), None);
+        contract.storage_deposit(None, None).unwrap();
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(owner())
+            .attached_deposit(2 * LOW_STORAGE_MARGIN)
+            .context.clone()
+        );
+        assert_eq!(contract.fund_storage_reserve().unwrap(), U128(2 * LOW_STORAGE_MARGIN));
+
+        let locked = MIN_SEND_DEPOSIT - ACCESS_KEY_ALLOWANCE;
+        let storage_cost = contract.get_storage_cost().0 + drop_storage_cost();
+        testing_env!(
+            VMContextBuilder::new()
+            .current_account_id(linkdrop())
+            .predecessor_account_id(bob())
+            .attached_deposit(MIN_SEND_DEPOSIT)
+            .account_balance(locked + 2 * LOW_STORAGE_MARGIN + storage_cost + margin)
+            .context.clone()
+        );
+        contract.send(funding_key(), None, None, None, None, None, None).unwrap();
+        contract
+    }
+
+    #[test]
+    fn test_send_draws_storage_reserve() {
+        let contract = send_with_margin(LOW_STORAGE_MARGIN / 4);
+
+        // The funding still succeeds, warning that the margin ran low and refilling it out of the reserve
+        let logs = get_logs();
+        assert_eq!(logs.len(), 1);
+        let event: serde_json::Value = serde_json::from_str(logs[0].strip_prefix("EVENT_JSON:").unwrap()).unwrap();
+        assert_eq!(event["event"], "low_storage_balance");
+        let margin: Balance = event["data"][0]["margin"].as_str().unwrap().parse().unwrap();
+        assert!(margin < LOW_STORAGE_MARGIN);
+        assert_eq!(event["data"][0]["drawn"], (LOW_STORAGE_MARGIN - margin).to_string());
+        assert_eq!(contract.get_storage_reserve(), U128(LOW_STORAGE_MARGIN + margin));
+        assert_eq!(contract.get_storage_margin(), U128(LOW_STORAGE_MARGIN));
+        assert!(contract.accounts.get(&funding_key()).is_some());
+    }
+
+    #[test]
+    fn test_send_with_enough_storage_margin() {
+        let contract = send_with_margin(2 * LOW_STORAGE_MARGIN);
+
+        // Nothing is drawn from the reserve
+        assert!(get_logs().is_empty());
+        assert_eq!(contract.get_storage_reserve(), U128(2 * LOW_STORAGE_MARGIN));
+        assert!(contract.get_storage_margin().0 >= LOW_STORAGE_MARGIN);
+    }
+
+    #[test]
+    fn test_fund_storage_reserve_not_owner() {
+        set_deposit(LOW_STORAGE_MARGIN);
+        let mut contract = LinkDrop::new(owner(), None);
+
+        assert_eq!(
+            contract.fund_storage_reserve().err(),
+            Some(ContractError::Unauthorized("Only the owner can call this method"))
+        );
+    }
+}